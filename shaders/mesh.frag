@@ -0,0 +1,27 @@
+#version 450
+
+#extension GL_GOOGLE_include_directive : require
+#include "input_structures.glsl"
+
+layout (location = 0) in vec3 inNormal;
+layout (location = 1) in vec4 inColor;
+layout (location = 2) in vec2 inUV;
+//w is the handedness of the bitangent, for normal mapping
+layout (location = 3) in vec4 inTangent;
+
+layout (location = 0) out vec4 outFragColor;
+
+void main()
+{
+    vec4 color = inColor * texture(colorTex, inUV);
+    //alpha mask materials, a cutoff of 0 never discards
+    if (color.a < materialData.metal_rough_factors.z) {
+        discard;
+    }
+
+    float lightValue = max(dot(inNormal, sceneData.sunlightDirection.xyz), 0.1f);
+
+    vec3 ambient = color.rgb * sceneData.ambientColor.xyz;
+
+    outFragColor = vec4(color.rgb * lightValue * sceneData.sunlightColor.w + ambient, color.a);
+}
@@ -0,0 +1,73 @@
+#version 450
+
+#extension GL_GOOGLE_include_directive : require
+#extension GL_EXT_buffer_reference : require
+
+#include "input_structures.glsl"
+
+layout (location = 0) out vec3 outNormal;
+layout (location = 1) out vec4 outColor;
+layout (location = 2) out vec2 outUV;
+layout (location = 3) out vec4 outTangent;
+
+struct Vertex {
+    vec3 position;
+    float uv_x;
+    vec3 normal;
+    float uv_y;
+    vec4 color;
+    vec4 tangent;
+};
+
+struct MorphTargetDelta {
+    vec4 position;
+    vec4 normal;
+    vec4 tangent;
+};
+
+layout(buffer_reference, std430) readonly buffer VertexBuffer{
+    Vertex vertices[];
+};
+
+layout(buffer_reference, std430) readonly buffer MorphTargetBuffer{
+    MorphTargetDelta deltas[];
+};
+
+layout(buffer_reference, std430) readonly buffer MorphWeightBuffer{
+    float weights[];
+};
+
+//push constants block
+layout( push_constant ) uniform constants
+{
+    mat4 render_matrix;
+    VertexBuffer vertexBuffer;
+    MorphTargetBuffer morphTargets;
+    MorphWeightBuffer morphWeights;
+    uint morphTargetCount;
+    uint vertexCount;
+} PushConstants;
+
+void main()
+{
+    Vertex v = PushConstants.vertexBuffer.vertices[gl_VertexIndex];
+
+    //blend in the morph targets, each stores a delta for every vertex of the mesh
+    for (uint morphTarget = 0; morphTarget < PushConstants.morphTargetCount; morphTarget++) {
+        float weight = PushConstants.morphWeights.weights[morphTarget];
+        MorphTargetDelta delta = PushConstants.morphTargets.deltas[morphTarget * PushConstants.vertexCount + gl_VertexIndex];
+        v.position += weight * delta.position.xyz;
+        v.normal += weight * delta.normal.xyz;
+        v.tangent.xyz += weight * delta.tangent.xyz;
+    }
+
+    vec4 position = vec4(v.position, 1.0f);
+
+    gl_Position = sceneData.viewproj * PushConstants.render_matrix * position;
+
+    outNormal = (PushConstants.render_matrix * vec4(v.normal, 0.f)).xyz;
+    outTangent = vec4((PushConstants.render_matrix * vec4(v.tangent.xyz, 0.f)).xyz, v.tangent.w);
+    outColor = v.color * materialData.colorFactors;
+    outUV.x = v.uv_x;
+    outUV.y = v.uv_y;
+}
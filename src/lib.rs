@@ -0,0 +1,26 @@
+mod tests;
+mod vk_bootstrap;
+pub mod vk_animation;
+pub mod vk_compute;
+pub mod vk_config;
+pub mod vk_cubemaps;
+mod vk_debug;
+pub mod vk_descriptors;
+pub mod vk_engine;
+pub mod vk_error;
+pub mod vk_images;
+pub mod vk_init;
+pub mod vk_loader;
+pub mod vk_materials;
+pub mod vk_mipmaps;
+pub mod vk_pipelines;
+pub mod vk_scene;
+pub mod vk_skinning;
+pub mod vk_types;
+
+//re-exported so users build against the same versions the engine does, immediate_submit! needs ash
+pub use ash;
+pub use imgui;
+pub use vk_config::{EngineArgs, EngineConfig};
+pub use vk_engine::VulkanEngine;
+pub use vk_error::{EngineError, EngineResult};
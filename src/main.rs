@@ -1,22 +1,55 @@
-extern crate core;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use vk_guide_rs::vk_loader::bake_mesh_caches;
+use vk_guide_rs::{EngineArgs, EngineConfig, VulkanEngine};
 
-mod tests;
-mod vk_bootstrap;
-mod vk_compute;
-mod vk_debug;
-mod vk_descriptors;
-mod vk_engine;
-mod vk_images;
-mod vk_init;
-mod vk_pipelines;
-mod vk_types;
-mod vk_loader;
+#[derive(Parser)]
+#[command(about = "Vulkan renderer following vkguide.dev")]
+struct Cli {
+    #[command(flatten)]
+    engine: EngineArgs,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-use vk_engine::VulkanEngine;
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Write the mesh cache of every glTF file in a directory, without starting the engine")]
+    Bake {
+        #[arg(value_name = "DIR", default_value = "./assets")]
+        dir: PathBuf,
+        #[arg(long, help = "Rebake caches that are still up to date")]
+        force: bool,
+    },
+}
 
 fn main() {
     pretty_env_logger::init();
-    let mut engine = VulkanEngine::init().unwrap();
-    engine.run();
+    let cli = Cli::parse();
+    if let Some(Command::Bake { dir, force }) = &cli.command {
+        match bake_mesh_caches(dir, *force) {
+            Ok(report) => {
+                println!("{} baked, {} up to date, {} failed", report.baked, report.up_to_date, report.failed);
+                if report.failed > 0 {
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                log::error!("Bake failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut engine = match EngineConfig::from_args(&cli.engine).and_then(VulkanEngine::init) {
+        Ok(engine) => engine,
+        Err(e) => {
+            log::error!("Failed to initialize the engine: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Err(e) = engine.run() {
+        log::error!("Engine stopped: {}", e);
+    }
     //no cleanup, it's in the engine's drop
 }
@@ -1,8 +1,481 @@
 #[cfg(test)]
 mod tests {
+    use crate::vk_animation::{sample, AnimationChannel, AnimationPlayer, ChannelValues, Interpolation, NodePose};
+    use crate::vk_bootstrap::choose_present_mode;
+    use crate::vk_config::{DrawFormat, EngineArgs, EngineConfig, PresentMode};
+    use crate::vk_cubemaps::hdr_pixel_bytes;
+    use crate::vk_engine::capture::CapturedImage;
+    use crate::vk_images::{format_aspect_flags, image_barrier, ImageState};
+    use crate::vk_init;
+    use crate::vk_loader::{compute_normals, compute_tangents, decode_data_uri, remap_vertices, unweld, write_mesh_cache, BakedMesh, BakedSurface, MeshCache};
+    use crate::vk_mipmaps::mip_levels;
+    use crate::vk_scene::{DrawContext, Node, RenderObject};
+    use crate::vk_types::bounds::{Bounds, Frustum};
+    use crate::vk_types::gpu_mesh_buffers::MorphTargetDelta;
+    use crate::vk_types::sampler_cache::SamplerDesc;
+    use crate::vk_types::texture_cache::TextureCache;
+    use crate::vk_types::vertex::Vertex;
+    use crate::vk_types::ImageDesc;
+    use ash::vk;
+    use cgmath::{InnerSpace, SquareMatrix};
+    use half::f16;
+    use std::borrow::Cow;
+    use std::cell::RefCell;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use std::slice;
 
     #[test]
     fn load_gltf() {
         let gltf = gltf::import("./assets/basicmesh.glb").unwrap();
     }
+
+    #[test]
+    fn captured_image_to_rgba8() {
+        let hdr_pixel: Vec<u8> = [0.5f32, 2f32, -1f32, 1f32]
+            .iter()
+            .flat_map(|&channel| f16::from_f32(channel).to_le_bytes())
+            .collect();
+        let hdr = CapturedImage {
+            width: 1,
+            height: 1,
+            format: vk::Format::R16G16B16A16_SFLOAT,
+            data: hdr_pixel,
+        };
+        //tonemapped and sRGB encoded, alpha is only clamped
+        assert_eq!(hdr.to_rgba8().unwrap(), vec![156, 213, 0, 255]);
+
+        let bgra = CapturedImage {
+            width: 1,
+            height: 1,
+            format: vk::Format::B8G8R8A8_UNORM,
+            data: vec![1, 2, 3, 4],
+        };
+        assert_eq!(bgra.to_rgba8().unwrap(), vec![3, 2, 1, 4]);
+
+        //swapchains are often sRGB, their bytes are already what a PNG stores
+        let srgb = CapturedImage {
+            format: vk::Format::B8G8R8A8_SRGB,
+            ..bgra
+        };
+        assert_eq!(srgb.to_rgba8().unwrap(), vec![3, 2, 1, 4]);
+        let unsupported = CapturedImage {
+            format: vk::Format::A2B10G10R10_UNORM_PACK32,
+            ..srgb
+        };
+        assert!(unsupported.to_rgba8().is_err());
+    }
+
+    #[test]
+    fn engine_config_from_toml() {
+        let mut config = EngineConfig::from_toml_str(
+            r#"
+            width = 640
+            present_modes = ["mailbox", "fifo"]
+            draw_format = "rgba32_float"
+            "#,
+        )
+        .unwrap();
+        assert_eq!((config.width, config.height), (640, 900));
+        assert_eq!(config.present_modes, [PresentMode::Mailbox, PresentMode::Fifo]);
+        assert!(config.vsync);
+        assert_eq!(config.draw_format, DrawFormat::Rgba32Float);
+        assert!(EngineConfig::from_toml_str("widht = 640").is_err());
+
+        let args = EngineArgs {
+            height: Some(480),
+            no_default_meshes: true,
+            ..Default::default()
+        };
+        args.apply(&mut config);
+        assert_eq!((config.width, config.height), (640, 480));
+        assert!(config.default_meshes.is_none());
+    }
+
+    #[test]
+    fn embedded_image_data() {
+        assert_eq!(
+            decode_data_uri("data:image/png;base64,iVBORw0KGgo="),
+            Some(vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'])
+        );
+        assert_eq!(decode_data_uri("data:image/png,rawdata"), None);
+        assert_eq!(decode_data_uri("textures/albedo.png"), None);
+        //shared images are found by their bytes, not by where they come from
+        assert_eq!(TextureCache::key(b"same image"), TextureCache::key(b"same image"));
+        assert_ne!(TextureCache::key(b"same image"), TextureCache::key(b"other image"));
+    }
+
+    #[test]
+    fn present_mode_follows_vsync() {
+        let supported = [vk::PresentModeKHR::FIFO, vk::PresentModeKHR::IMMEDIATE];
+        let preferred = [PresentMode::Mailbox, PresentMode::Immediate, PresentMode::Fifo];
+        assert_eq!(
+            choose_present_mode(&supported, &preferred, false),
+            vk::PresentModeKHR::IMMEDIATE
+        );
+        assert_eq!(
+            choose_present_mode(&supported, &preferred, true),
+            vk::PresentModeKHR::FIFO
+        );
+        //nothing matches, FIFO is always there
+        assert_eq!(
+            choose_present_mode(&supported, &[PresentMode::Mailbox], false),
+            vk::PresentModeKHR::FIFO
+        );
+    }
+
+    #[test]
+    fn node_world_transform() {
+        let root = Rc::new(RefCell::new(Node::new("root", cgmath::Matrix4::from_scale(2f32))));
+        let child = Rc::new(RefCell::new(Node::new(
+            "child",
+            cgmath::Matrix4::from_translation((1f32, 0f32, 0f32).into()),
+        )));
+        Node::add_child(&root, child.clone());
+        let world_origin = child.borrow().world_transform * cgmath::Vector4::new(0f32, 0f32, 0f32, 1f32);
+        assert_eq!(world_origin, cgmath::Vector4::new(2f32, 0f32, 0f32, 1f32));
+        assert_eq!(child.borrow().parent.upgrade().unwrap().borrow().name, "root");
+        //moving the root moves everything below it
+        root.borrow_mut().local_transform = cgmath::Matrix4::identity();
+        root.borrow_mut().refresh_transform(&cgmath::Matrix4::identity());
+        assert_eq!(
+            child.borrow().world_transform,
+            cgmath::Matrix4::from_translation((1f32, 0f32, 0f32).into())
+        );
+    }
+
+    #[test]
+    fn animation_sampling() {
+        let times = [0f32, 1f32, 3f32];
+        let values = [0f32, 10f32, 30f32];
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        assert_eq!(sample(&times, &values, Interpolation::Linear, 2f32, lerp), 20f32);
+        assert_eq!(sample(&times, &values, Interpolation::Step, 2.9f32, lerp), 10f32);
+        //clamped outside the keys
+        assert_eq!(sample(&times, &values, Interpolation::Linear, -1f32, lerp), 0f32);
+        assert_eq!(sample(&times, &values, Interpolation::Linear, 5f32, lerp), 30f32);
+        //in tangent, value, out tangent per key. Flat tangents ease in and out, passing the middle halfway
+        let spline = [0f32, 0f32, 0f32, 0f32, 10f32, 0f32];
+        assert_eq!(sample(&times[..2], &spline, Interpolation::CubicSpline, 0.5f32, lerp), 5f32);
+        assert_eq!(sample(&times[..2], &spline, Interpolation::CubicSpline, 1f32, lerp), 10f32);
+
+        let mut player = AnimationPlayer::default();
+        player.advance(2.5f32, 2f32);
+        assert_eq!(player.time, 0.5f32);
+        player.looping = false;
+        player.advance(2.5f32, 2f32);
+        assert_eq!(player.time, 2f32);
+    }
+
+    #[test]
+    fn morph_weight_channel() {
+        //two targets, one fading in while the other fades out
+        let channel = AnimationChannel {
+            node: 0,
+            interpolation: Interpolation::Linear,
+            times: vec![0f32, 2f32],
+            values: ChannelValues::MorphWeights(vec![vec![0f32, 1f32], vec![1f32, 0f32]]),
+        };
+        let mut pose = NodePose::default();
+        let mut morph_weights = Vec::new();
+        channel.apply(0.5f32, &mut pose, &mut morph_weights);
+        assert_eq!(morph_weights, vec![0.25f32, 0.75f32]);
+        //the transform is left alone
+        assert_eq!(pose, NodePose::default());
+    }
+
+    #[test]
+    fn mesh_cache_round_trip() {
+        let indices = vec![0u32, 1, 2, 2, 1, 3];
+        let vertices = [(0f32, 0f32), (1f32, 0f32), (0f32, 2f32), (1f32, 2f32)]
+            .iter()
+            .map(|&(x, y)| Vertex {
+                position: (x, y, -1f32).into(),
+                ..Default::default()
+            })
+            .collect::<Vec<Vertex>>();
+        let surfaces = vec![
+            BakedSurface::new(0, 3, Some(1), &indices, &vertices),
+            BakedSurface::new(3, 3, None, &indices, &vertices),
+        ];
+        assert_eq!(surfaces[0].bounds.min(), (0f32, 0f32, -1f32).into());
+        assert_eq!(surfaces[0].bounds.max(), (1f32, 2f32, -1f32).into());
+        let mesh = BakedMesh {
+            name: String::from("quad"),
+            indices: Cow::Owned(indices),
+            vertices: Cow::Owned(vertices),
+            surfaces,
+            morph_targets: Cow::Owned(vec![MorphTargetDelta::default(); 4]),
+            morph_weights: vec![0.5f32],
+        };
+        let path = std::env::temp_dir().join(format!("vk_guide_rs_{}.meshcache", std::process::id()));
+        write_mesh_cache(&path, 42, slice::from_ref(&mesh)).unwrap();
+
+        //another source hash means the source changed since baking
+        assert!(MeshCache::open(&path, 7).is_none());
+        let cache = MeshCache::open(&path, 42).unwrap();
+        let meshes = cache.meshes().unwrap();
+        assert_eq!(meshes.len(), 1);
+        assert_eq!(meshes[0].name, mesh.name);
+        assert_eq!(meshes[0].indices, mesh.indices);
+        assert_eq!(meshes[0].surfaces, mesh.surfaces);
+        assert_eq!(meshes[0].vertices[3].position, mesh.vertices[3].position);
+        assert_eq!(meshes[0].morph_targets.len(), 4);
+        assert_eq!(meshes[0].morph_weights, mesh.morph_weights);
+        drop(cache);
+
+        //a truncated cache is never used
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(MeshCache::open(&path, 42).unwrap().meshes().is_none());
+        //and neither is one with a corrupt mesh count, it runs out of data instead of allocating
+        let mut corrupt = bytes;
+        corrupt[12..16].copy_from_slice(&u32::MAX.to_ne_bytes());
+        std::fs::write(&path, &corrupt).unwrap();
+        assert!(MeshCache::open(&path, 42).unwrap().meshes().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn bounds() {
+        let points = [(-1f32, 0f32, 0f32), (1f32, 0f32, 0f32), (0f32, 0.5f32, 0f32)].map(cgmath::Vector3::from);
+        let bounds = Bounds::from_points(points.iter().copied());
+        assert_eq!(bounds.origin, (0f32, 0.25f32, 0f32).into());
+        assert_eq!(bounds.extents, (1f32, 0.25f32, 0f32).into());
+        //the farthest point, not the corner of the box
+        assert_eq!(bounds.sphere_radius, (1f32 + 0.25f32 * 0.25f32).sqrt());
+
+        //a quarter turn around z with twice the size swaps the axes of the box
+        let matrix = cgmath::Matrix4::from_translation((0f32, 0f32, 5f32).into())
+            * cgmath::Matrix4::from_angle_z(cgmath::Deg(90f32))
+            * cgmath::Matrix4::from_scale(2f32);
+        let transformed = bounds.transform(&matrix);
+        assert!((transformed.origin - cgmath::Vector3::new(-0.5f32, 0f32, 5f32)).magnitude() < 1e-5);
+        assert!((transformed.extents - cgmath::Vector3::new(0.5f32, 2f32, 0f32)).magnitude() < 1e-5);
+        assert!((transformed.sphere_radius - bounds.sphere_radius * 2f32).abs() < 1e-5);
+
+        let other = Bounds::from_points([(3f32, 0f32, 0f32)].map(cgmath::Vector3::from).into_iter());
+        let union = bounds.union(&other);
+        assert_eq!(union.min(), (-1f32, 0f32, 0f32).into());
+        assert_eq!(union.max(), (3f32, 0.5f32, 0f32).into());
+        assert!(union.sphere_radius >= 2f32);
+    }
+
+    #[test]
+    fn frustum_culling() {
+        //the camera of update_scene: 5 units back, reversed depth and Vulkan clip space
+        let view = cgmath::Matrix4::from_translation((0f32, 0f32, -5f32).into());
+        let projection = cgmath::perspective(cgmath::Deg(70f32), 16f32 / 9f32, 10000f32, 0.1f32);
+        let clip = cgmath::Matrix4::new(
+            1f32, 0f32, 0f32, 0f32, 0f32, -1f32, 0f32, 0f32, 0f32, 0f32, 0.5f32, 0f32, 0f32, 0f32, 0.5f32, 1f32,
+        );
+        let frustum = Frustum::from_matrix(&(clip * projection * view));
+        let unit = Bounds {
+            extents: (1f32, 1f32, 1f32).into(),
+            sphere_radius: 3f32.sqrt(),
+            ..Default::default()
+        };
+        let at = |x: f32, y: f32, z: f32| unit.transform(&cgmath::Matrix4::from_translation((x, y, z).into()));
+        assert!(frustum.intersects(&at(0f32, 0f32, 0f32)));
+        //off to the side, above, behind the camera and past the far plane
+        assert!(!frustum.intersects(&at(100f32, 0f32, 0f32)));
+        assert!(!frustum.intersects(&at(0f32, 100f32, 0f32)));
+        assert!(!frustum.intersects(&at(0f32, 0f32, 10f32)));
+        assert!(!frustum.intersects(&at(0f32, 0f32, -20000f32)));
+        //only partly inside
+        assert!(frustum.intersects(&at(0f32, 0f32, 4.5f32)));
+    }
+
+    #[test]
+    fn generated_normals_and_tangents() {
+        let triangle = |uvs: [(f32, f32); 3]| {
+            [(0f32, 0f32), (1f32, 0f32), (0f32, 1f32)]
+                .iter()
+                .zip(uvs)
+                .map(|(&(x, y), (u, v))| Vertex {
+                    position: (x, y, 0f32).into(),
+                    uv_x: u,
+                    uv_y: v,
+                    ..Default::default()
+                })
+                .collect::<Vec<Vertex>>()
+        };
+        let mut indices = vec![0u32, 1, 2];
+        let mut vertices = triangle([(0f32, 0f32), (1f32, 0f32), (0f32, 1f32)]);
+        compute_normals(&indices, &mut vertices);
+        assert_eq!(compute_tangents(&mut indices, &mut vertices, 0), vec![0, 1, 2]);
+        assert_eq!(vertices[0].normal, (0f32, 0f32, 1f32).into());
+        assert_eq!(vertices[2].tangent, (1f32, 0f32, 0f32, 1f32).into());
+
+        //mirrored UVs flip the bitangent
+        let mut mirrored = triangle([(1f32, 0f32), (0f32, 0f32), (1f32, 1f32)]);
+        compute_normals(&indices, &mut mirrored);
+        compute_tangents(&mut indices, &mut mirrored, 0);
+        assert_eq!(mirrored[1].tangent, (-1f32, 0f32, 0f32, -1f32).into());
+
+        //the two triangles share the edge from 1 to 2 but mirror the UVs across it, so both of its
+        //vertices are split instead of averaging tangents that point in opposite directions
+        let mut seam_indices = vec![0u32, 1, 2, 1, 3, 2];
+        //position and UV of every vertex
+        let mut seam = [
+            ((0f32, 0f32), (0f32, 0f32)),
+            ((1f32, 0f32), (1f32, 0f32)),
+            ((1f32, 1f32), (1f32, 1f32)),
+            ((2f32, 0f32), (0f32, 0f32)),
+        ]
+        .iter()
+        .map(|&((x, y), (u, v))| Vertex {
+            position: (x, y, 0f32).into(),
+            normal: (0f32, 0f32, 1f32).into(),
+            uv_x: u,
+            uv_y: v,
+            ..Default::default()
+        })
+        .collect::<Vec<Vertex>>();
+        let remap = compute_tangents(&mut seam_indices, &mut seam, 0);
+        assert_eq!(remap, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(seam_indices, vec![0, 1, 2, 3, 4, 5]);
+        assert!((seam[1].tangent - cgmath::Vector4::new(1f32, 0f32, 0f32, 1f32)).magnitude() < 1e-5);
+        assert!((seam[3].tangent - cgmath::Vector4::new(-1f32, 0f32, 0f32, -1f32)).magnitude() < 1e-5);
+
+        //every corner gets its own vertex, in index order
+        indices.extend([2, 1, 0]);
+        let remap = unweld(&mut indices, 0);
+        remap_vertices(&mut vertices, 0, &remap);
+        assert_eq!(indices, vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(remap, vec![0, 1, 2, 2, 1, 0]);
+        assert_eq!(vertices[5].position, (0f32, 0f32, 0f32).into());
+    }
+
+    #[test]
+    fn image_desc() {
+        let extent = vk::Extent3D {
+            width: 512,
+            height: 512,
+            depth: 1,
+        };
+        let depth = ImageDesc::new(vk::Format::D32_SFLOAT, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, extent);
+        assert_eq!(depth.aspect_flags(), vk::ImageAspectFlags::DEPTH);
+
+        let cube = ImageDesc {
+            mip_levels: 10,
+            array_layers: 6,
+            view_type: vk::ImageViewType::CUBE,
+            ..ImageDesc::new(vk::Format::R16G16B16A16_SFLOAT, vk::ImageUsageFlags::SAMPLED, extent)
+        };
+        assert_eq!(cube.aspect_flags(), vk::ImageAspectFlags::COLOR);
+        let create_info = vk_init::image_create_info(
+            cube.format,
+            cube.usage,
+            cube.extent,
+            cube.mip_levels,
+            cube.array_layers,
+            cube.samples,
+            cube.view_type,
+        );
+        assert_eq!(create_info.image_type, vk::ImageType::TYPE_2D);
+        assert_eq!(create_info.flags, vk::ImageCreateFlags::CUBE_COMPATIBLE);
+        assert_eq!((create_info.mip_levels, create_info.array_layers), (10, 6));
+    }
+
+    #[test]
+    fn full_mip_chain() {
+        let extent = |width: u32, height: u32| vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        };
+        assert_eq!(mip_levels(extent(1, 1)), 1);
+        assert_eq!(mip_levels(extent(256, 256)), 9);
+        //the longer side decides, odd sizes round down
+        assert_eq!(mip_levels(extent(300, 17)), 9);
+    }
+
+    #[test]
+    fn image_barriers() {
+        let image = vk::Image::null();
+        let barrier = image_barrier(
+            image,
+            vk::ImageAspectFlags::COLOR,
+            0,
+            1,
+            ImageState::COLOR_ATTACHMENT,
+            ImageState::TRANSFER_SRC,
+        );
+        assert_eq!(barrier.src_stage_mask, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT);
+        //only the writes have to be made available
+        assert_eq!(barrier.src_access_mask, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+        assert_eq!(barrier.dst_stage_mask, vk::PipelineStageFlags2::TRANSFER);
+        assert_eq!(barrier.new_layout, vk::ImageLayout::TRANSFER_SRC_OPTIMAL);
+        assert!(!ImageState::SHADER_READ.writes());
+
+        assert_eq!(
+            format_aspect_flags(vk::Format::D24_UNORM_S8_UINT),
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        );
+        let depth_stencil = ImageDesc::new(
+            vk::Format::D24_UNORM_S8_UINT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        );
+        assert_eq!(depth_stencil.aspect_flags(), vk::ImageAspectFlags::DEPTH);
+    }
+
+    #[test]
+    fn sampler_descriptions() {
+        let mut descs = HashSet::new();
+        descs.insert(SamplerDesc::linear());
+        descs.insert(SamplerDesc::linear());
+        descs.insert(SamplerDesc::nearest());
+        descs.insert(SamplerDesc::shadow());
+        descs.insert(SamplerDesc::linear().with_address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE));
+        assert_eq!(descs.len(), 4);
+        assert!(descs.contains(&SamplerDesc {
+            max_lod: vk::LOD_CLAMP_NONE,
+            ..SamplerDesc::linear()
+        }));
+        assert!(!descs.contains(&SamplerDesc {
+            max_anisotropy: Some(16f32),
+            ..SamplerDesc::linear()
+        }));
+    }
+
+    #[test]
+    fn transparent_back_to_front() {
+        let render_object = |z: f32| RenderObject {
+            index_count: 3,
+            first_index: 0,
+            index_buffer: vk::Buffer::null(),
+            material: None,
+            transform: cgmath::Matrix4::from_translation((0f32, 0f32, z).into()),
+            vertex_buffer_address: 0,
+            vertex_count: 3,
+            morph_targets_address: 0,
+            morph_weights: Vec::new(),
+            bounds: Some(Bounds::default()),
+        };
+        let mut ctx = DrawContext {
+            transparent_surfaces: vec![render_object(-2f32), render_object(-10f32), render_object(-5f32)],
+            ..Default::default()
+        };
+        ctx.sort_transparent(&cgmath::Matrix4::identity());
+        let depths: Vec<f32> = ctx.transparent_surfaces.iter().map(|render_object| render_object.transform.w.z).collect();
+        assert_eq!(depths, [-10f32, -5f32, -2f32]);
+    }
+
+    #[test]
+    fn hdr_pixels() {
+        //values above 1 are what HDR panoramas are for
+        let bytes = hdr_pixel_bytes(&[0.5, 4f32, 1000f32, 1f32]);
+        assert_eq!(bytes.len(), 4 * size_of::<f16>());
+        let texels: Vec<f32> = bytes
+            .chunks_exact(2)
+            .map(|bits| f16::from_ne_bytes([bits[0], bits[1]]).to_f32())
+            .collect();
+        assert_eq!(texels, [0.5, 4f32, 1000f32, 1f32]);
+    }
 }
@@ -0,0 +1,207 @@
+use crate::vk_scene::Node;
+use cgmath::{InnerSpace, SquareMatrix, VectorSpace};
+use std::cell::RefCell;
+use std::ops::{Add, Mul};
+use std::rc::Rc;
+
+//local transform of a node split the way glTF animates it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NodePose {
+    pub translation: cgmath::Vector3<f32>,
+    pub rotation: cgmath::Quaternion<f32>,
+    pub scale: cgmath::Vector3<f32>,
+}
+
+impl NodePose {
+    pub fn matrix(&self) -> cgmath::Matrix4<f32> {
+        cgmath::Matrix4::from_translation(self.translation)
+            * cgmath::Matrix4::from(self.rotation)
+            * cgmath::Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+impl Default for NodePose {
+    fn default() -> Self {
+        NodePose {
+            translation: (0f32, 0f32, 0f32).into(),
+            rotation: cgmath::Quaternion::new(1f32, 0f32, 0f32, 0f32),
+            scale: (1f32, 1f32, 1f32).into(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    //every key stores an in tangent, the value and an out tangent, in that order
+    CubicSpline,
+}
+
+pub enum ChannelValues {
+    Translations(Vec<cgmath::Vector3<f32>>),
+    Rotations(Vec<cgmath::Quaternion<f32>>),
+    Scales(Vec<cgmath::Vector3<f32>>),
+    //one list of keys per morph target
+    MorphWeights(Vec<Vec<f32>>),
+}
+
+impl ChannelValues {
+    pub fn len(&self) -> usize {
+        match self {
+            ChannelValues::Translations(values) => values.len(),
+            ChannelValues::Rotations(values) => values.len(),
+            ChannelValues::Scales(values) => values.len(),
+            ChannelValues::MorphWeights(targets) => targets.first().map_or(0, Vec::len),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+//keyframes of one property of one node
+pub struct AnimationChannel {
+    //index into the nodes of the file the clip was loaded from
+    pub node: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: ChannelValues,
+}
+
+impl AnimationChannel {
+    //writes the value at time into the animated property of the node, times outside the keys are
+    //clamped
+    pub fn apply(&self, time: f32, pose: &mut NodePose, morph_weights: &mut Vec<f32>) {
+        match &self.values {
+            ChannelValues::Translations(values) => {
+                pose.translation = sample(&self.times, values, self.interpolation, time, |a, b, t| a.lerp(b, t))
+            }
+            ChannelValues::Rotations(values) => {
+                pose.rotation = sample(&self.times, values, self.interpolation, time, |a, b, t| a.slerp(b, t)).normalize()
+            }
+            ChannelValues::Scales(values) => {
+                pose.scale = sample(&self.times, values, self.interpolation, time, |a, b, t| a.lerp(b, t))
+            }
+            ChannelValues::MorphWeights(targets) => {
+                morph_weights.resize(targets.len(), 0f32);
+                for (weight, values) in morph_weights.iter_mut().zip(targets.iter()) {
+                    *weight = sample(&self.times, values, self.interpolation, time, |a, b, t| a + (b - a) * t);
+                }
+            }
+        }
+    }
+}
+
+pub fn sample<T>(times: &[f32], values: &[T], interpolation: Interpolation, time: f32, lerp: impl Fn(T, T, f32) -> T) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    let stride = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    let value = |key: usize| values[key * stride + stride / 2];
+    let last = times.len() - 1;
+    if time <= times[0] {
+        return value(0);
+    }
+    if time >= times[last] {
+        return value(last);
+    }
+    //first key after time, the one before it is at or before time
+    let next = times.partition_point(|&key_time| key_time <= time);
+    let previous = next - 1;
+    let delta = times[next] - times[previous];
+    let t = (time - times[previous]) / delta;
+    match interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => lerp(value(previous), value(next), t),
+        Interpolation::CubicSpline => {
+            //hermite spline, the tangents are scaled by the time between the keys
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let out_tangent = values[previous * 3 + 2] * delta;
+            let in_tangent = values[next * 3] * delta;
+            value(previous) * (2f32 * t3 - 3f32 * t2 + 1f32)
+                + out_tangent * (t3 - 2f32 * t2 + t)
+                + value(next) * (-2f32 * t3 + 3f32 * t2)
+                + in_tangent * (t3 - t2)
+        }
+    }
+}
+
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<AnimationChannel>,
+    //time of the last key of any channel
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    //poses and weights should start out at rest so properties the clip leaves alone keep their
+    //value, both are indexed by node
+    pub fn apply(&self, time: f32, poses: &mut [NodePose], morph_weights: &mut [Vec<f32>]) {
+        for channel in self.channels.iter() {
+            channel.apply(time, &mut poses[channel.node], &mut morph_weights[channel.node]);
+        }
+    }
+}
+
+pub struct Skin {
+    pub name: String,
+    //indices into the nodes of the file the skin was loaded from
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<cgmath::Matrix4<f32>>,
+}
+
+impl Skin {
+    //joint matrices relative to the skinned node, so its transform still applies when drawing
+    pub fn joint_matrices(&self, nodes: &[Rc<RefCell<Node>>], mesh_world_transform: &cgmath::Matrix4<f32>) -> Vec<cgmath::Matrix4<f32>> {
+        let inverse_mesh_transform = mesh_world_transform.invert().unwrap_or_else(cgmath::Matrix4::identity);
+        self.joints
+            .iter()
+            .zip(self.inverse_bind_matrices.iter())
+            .map(|(&joint, inverse_bind_matrix)| {
+                inverse_mesh_transform * nodes[joint].borrow().world_transform * inverse_bind_matrix
+            })
+            .collect()
+    }
+}
+
+//playback state of the clips of one file
+pub struct AnimationPlayer {
+    //index of the clip being played, None leaves every node at its rest pose
+    pub clip: Option<usize>,
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+    pub looping: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        AnimationPlayer {
+            clip: None,
+            time: 0f32,
+            speed: 1f32,
+            playing: true,
+            looping: true,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn advance(&mut self, delta_time: f32, duration: f32) {
+        if !self.playing {
+            return;
+        }
+        self.time += delta_time * self.speed;
+        if self.looping && duration > 0f32 {
+            self.time = self.time.rem_euclid(duration);
+        } else {
+            self.time = self.time.clamp(0f32, duration);
+        }
+    }
+}
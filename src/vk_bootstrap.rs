@@ -1,32 +1,48 @@
 mod device;
 
 use crate::vk_compute::ComputeEffect;
-#[cfg(debug_assertions)]
+use crate::vk_config::PresentMode;
 use crate::vk_debug::vulkan_debug_callback;
-use crate::vk_descriptors::{DescriptorAllocator, DescriptorSetLayoutBuilder, PoolSizeRatio};
-use crate::vk_engine::frame_data::{FrameData, FRAME_OVERLAP};
-use crate::vk_pipelines;
-use crate::vk_pipelines::PipelineBuilder;
-use crate::vk_types::AllocatedImage;
-use crate::{vk_compute, vk_init};
-#[cfg(debug_assertions)]
+use crate::vk_descriptors::{
+    DescriptorAllocator, DescriptorAllocatorGrowable, DescriptorSetLayoutBuilder, PoolSizeRatio,
+};
+use crate::vk_engine::deletion_queue::DeletionQueue;
+use crate::vk_engine::frame_data::FrameData;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_images::ImageState;
+use crate::vk_loader::GLTFMaterial;
+use crate::vk_materials::{GLTFMetallicRoughness, MaterialConstants, MaterialResources};
+use crate::vk_mipmaps::{MipmapGenerator, MipmapMethod};
+use crate::vk_skinning::SkinningPipeline;
+use crate::vk_types::buffers::{create_buffer, destroy_buffer, AllocatedBuffer};
+use crate::vk_types::material::MaterialPass;
+use crate::vk_types::sampler_cache::{SamplerCache, SamplerDesc};
+use crate::vk_types::{AllocatedImage, ImageDesc};
+use crate::{immediate_submit, vk_compute, vk_images, vk_init, vk_mipmaps, vk_pipelines};
 use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::{Surface, Swapchain};
 use ash::vk::PipelineCache;
 use ash::{vk, Device, Entry, Instance};
-use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
 use gpu_allocator::MemoryLocation;
 use sdl2::video::Window;
-use std::cell::{OnceCell, RefCell};
-use std::ffi::{c_char, CString};
+use std::ffi::{c_char, CStr, CString};
 use std::mem::size_of;
-use std::rc::Rc;
 use std::slice;
-use crate::vk_loader::{load_gltf_meshes, MeshAsset};
-use crate::vk_types::gpu_draw_push_constants::GPUDrawPushConstants;
+
+pub const SWAPCHAIN_FORMAT: vk::SurfaceFormatKHR = vk::SurfaceFormatKHR {
+    format: vk::Format::B8G8R8A8_UNORM,
+    color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+};
 
 //-----------------------------INSTANCE-------------------------------
-pub fn create_instance(entry: &Entry, window: &Window) -> Instance {
+const VALIDATION_LAYER_NAME: &[u8] = b"VK_LAYER_KHRONOS_validation\0";
+
+//window is None when running headless, no surface extensions are requested in that case
+pub fn create_instance(
+    entry: &Entry,
+    window: Option<&Window>,
+    validation: bool,
+) -> EngineResult<Instance> {
     let app_info = vk::ApplicationInfo::builder()
         .application_name(CString::new("Vulkan Application").unwrap().as_c_str())
         .application_version(vk::make_api_version(0, 0, 1, 0))
@@ -35,34 +51,45 @@ pub fn create_instance(entry: &Entry, window: &Window) -> Instance {
         .api_version(vk::make_api_version(0, 1, 3, 0))
         .build();
 
-    #[allow(unused_mut)]
-    let mut extension_names: Vec<*const c_char> = window
-        .vulkan_instance_extensions()
-        .unwrap()
-        .iter()
-        .map(|name| -> *const c_char { name.as_ptr() as *const c_char })
-        .collect();
-    #[cfg(debug_assertions)]
-    extension_names.push(DebugUtils::name().as_ptr());
-    #[allow(unused_mut)]
+    let mut extension_names: Vec<*const c_char> = match window {
+        Some(window) => window
+            .vulkan_instance_extensions()
+            .map_err(EngineError::Sdl)?
+            .iter()
+            .map(|name| -> *const c_char { name.as_ptr() as *const c_char })
+            .collect(),
+        None => Vec::new(),
+    };
+    if validation {
+        extension_names.push(DebugUtils::name().as_ptr());
+    }
     let mut instance_create_info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
         .enabled_extension_names(&extension_names);
-    cfg_if::cfg_if! {
-        if #[cfg(debug_assertions)]{
-            let _layer_names = [b"VK_LAYER_KHRONOS_validation\0".as_ptr() as *const c_char];
-            instance_create_info = instance_create_info.enabled_layer_names(&_layer_names);
+    let layer_names = [VALIDATION_LAYER_NAME.as_ptr() as *const c_char];
+    if validation {
+        //CI machines and render servers often lack the SDK layers, run without validation there
+        if validation_layer_available(entry) {
+            instance_create_info = instance_create_info.enabled_layer_names(&layer_names);
+        } else {
+            log::warn!("Validation layer not found, continuing without it");
         }
     }
-    unsafe {
-        entry
-            .create_instance(&(instance_create_info.build()), None)
-            .unwrap()
-    }
+    Ok(unsafe { entry.create_instance(&(instance_create_info.build()), None)? })
+}
+
+fn validation_layer_available(entry: &Entry) -> bool {
+    let wanted = CStr::from_bytes_with_nul(VALIDATION_LAYER_NAME).unwrap();
+    entry
+        .enumerate_instance_layer_properties()
+        .unwrap_or_default()
+        .iter()
+        .any(|layer| unsafe { CStr::from_ptr(layer.layer_name.as_ptr()) } == wanted)
 }
 //---------------------------------------DEBUG-----------------------------------------
-#[cfg(debug_assertions)]
-pub fn create_debug_messenger(debug_utils_loader: &DebugUtils) -> vk::DebugUtilsMessengerEXT {
+pub fn create_debug_messenger(
+    debug_utils_loader: &DebugUtils,
+) -> EngineResult<vk::DebugUtilsMessengerEXT> {
     let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::builder()
         .message_severity(
             vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -77,21 +104,17 @@ pub fn create_debug_messenger(debug_utils_loader: &DebugUtils) -> vk::DebugUtils
         .pfn_user_callback(Some(vulkan_debug_callback))
         .build();
 
-    unsafe {
-        debug_utils_loader
-            .create_debug_utils_messenger(&debug_info, None)
-            .unwrap()
-    }
+    Ok(unsafe { debug_utils_loader.create_debug_utils_messenger(&debug_info, None)? })
 }
 
 //----------------------------DEVICE------------------------------------
+//surface is None when running headless, the swapchain extension is not enabled in that case
 pub fn create_device(
     instance: &Instance,
-    surface_loader: &Surface,
-    surface: vk::SurfaceKHR,
-) -> (Device, vk::PhysicalDevice, vk::Queue, u32) {
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+) -> EngineResult<(Device, vk::PhysicalDevice, vk::Queue, u32)> {
     let (physical_device, queue_family_index) =
-        device::pick_physical_device_and_queue(instance, surface_loader, surface);
+        device::pick_physical_device_and_queue(instance, surface)?;
     let priorities = [1.0];
     let queue_info = [vk::DeviceQueueCreateInfo::builder()
         .queue_family_index(queue_family_index)
@@ -106,25 +129,34 @@ pub fn create_device(
         .buffer_device_address(true)
         .descriptor_indexing(true)
         .build();
-    let device_extension_names = [Swapchain::name().as_ptr()];
+    //anisotropy is optional, the sampler cache only hands out anisotropic samplers when it is there
+    let supported_features = unsafe { instance.get_physical_device_features(physical_device) };
+    let features = vk::PhysicalDeviceFeatures::builder()
+        //the background and mipmap shaders write storage images without a format qualifier
+        .shader_storage_image_write_without_format(true)
+        .sampler_anisotropy(supported_features.sampler_anisotropy == vk::TRUE)
+        .build();
+    let device_extension_names: Vec<*const c_char> = match surface {
+        Some(_) => vec![Swapchain::name().as_ptr()],
+        None => Vec::new(),
+    };
 
     let device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_info)
         .enabled_extension_names(&device_extension_names)
+        .enabled_features(&features)
         .push_next(&mut features13)
         .push_next(&mut features12)
         .build();
-    let device: Device = unsafe {
-        instance
-            .create_device(physical_device, &device_create_info, None)
-            .unwrap()
-    };
+    let device: Device =
+        unsafe { instance.create_device(physical_device, &device_create_info, None)? };
     let graphics_queue = unsafe { device.get_device_queue(queue_family_index, 0) };
 
-    (device, physical_device, graphics_queue, queue_family_index)
+    Ok((device, physical_device, graphics_queue, queue_family_index))
 }
 
 //-------------------SWAPCHAIN-----------------------
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn create_swapchain(
     instance: &Instance,
     device: &Device,
@@ -132,25 +164,20 @@ pub fn create_swapchain(
     surface_loader: &Surface,
     surface: vk::SurfaceKHR,
     extent: vk::Extent2D,
-    allocator: &mut gpu_allocator::vulkan::Allocator,
-) -> (
+    present_modes: &[PresentMode],
+    vsync: bool,
+) -> EngineResult<(
     Swapchain,
     vk::SwapchainKHR,
     vk::SurfaceFormatKHR,
     Vec<vk::Image>,
     Vec<vk::ImageView>,
     vk::Extent2D,
-    AllocatedImage,
-    AllocatedImage
-) {
-    let surface_format = vk::SurfaceFormatKHR {
-        format: vk::Format::B8G8R8A8_UNORM,
-        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-    };
+    vk::PresentModeKHR,
+)> {
+    let surface_format = SWAPCHAIN_FORMAT;
     let surface_capabilities = unsafe {
-        surface_loader
-            .get_physical_device_surface_capabilities(physical_device, surface)
-            .unwrap()
+        surface_loader.get_physical_device_surface_capabilities(physical_device, surface)?
     };
     let mut desired_image_count = surface_capabilities.min_image_count + 1;
     if surface_capabilities.max_image_count > 0
@@ -171,15 +198,24 @@ pub fn create_swapchain(
         surface_capabilities.current_transform
     };
 
+    let supported_present_modes = unsafe {
+        surface_loader.get_physical_device_surface_present_modes(physical_device, surface)?
+    };
+    let present_mode = choose_present_mode(&supported_present_modes, present_modes, vsync);
+
     let swapchain_loader = Swapchain::new(instance, device);
 
     let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
         .surface(surface)
         .image_format(surface_format.format)
         .image_color_space(surface_format.color_space)
-        .present_mode(vk::PresentModeKHR::FIFO)
-        .image_extent(extent)
-        .image_usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .present_mode(present_mode)
+        .image_extent(surface_extent)
+        .image_usage(
+            vk::ImageUsageFlags::TRANSFER_DST
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        )
         .min_image_count(desired_image_count)
         .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
         .pre_transform(pre_transform)
@@ -187,13 +223,9 @@ pub fn create_swapchain(
         .clipped(true)
         .image_array_layers(1)
         .build();
-    let swapchain = unsafe {
-        swapchain_loader
-            .create_swapchain(&swapchain_create_info, None)
-            .unwrap()
-    };
-    let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
-    let swapchain_image_views: Vec<vk::ImageView> = swapchain_images
+    let swapchain = unsafe { swapchain_loader.create_swapchain(&swapchain_create_info, None)? };
+    let swapchain_images = unsafe { swapchain_loader.get_swapchain_images(swapchain)? };
+    let swapchain_image_views = swapchain_images
         .iter()
         .map(|&image| {
             let create_view_info = vk::ImageViewCreateInfo::builder()
@@ -213,273 +245,365 @@ pub fn create_swapchain(
                     layer_count: 1,
                 })
                 .image(image);
-            unsafe { device.create_image_view(&create_view_info, None).unwrap() }
+            unsafe { device.create_image_view(&create_view_info, None) }
         })
-        .collect();
+        .collect::<Result<Vec<vk::ImageView>, vk::Result>>()?;
+
+    Ok((
+        swapchain_loader,
+        swapchain,
+        surface_format,
+        swapchain_images,
+        swapchain_image_views,
+        surface_extent,
+        present_mode,
+    ))
+}
+
+//first mode in preference order that the surface supports and matches vsync.
+//FIFO is always supported, so it is the fallback
+pub fn choose_present_mode(
+    supported: &[vk::PresentModeKHR],
+    preferred: &[PresentMode],
+    vsync: bool,
+) -> vk::PresentModeKHR {
+    let present_mode = preferred
+        .iter()
+        .filter(|present_mode| present_mode.is_vsync() == vsync)
+        .map(|&present_mode| vk::PresentModeKHR::from(present_mode))
+        .find(|present_mode| supported.contains(present_mode));
+    match present_mode {
+        Some(present_mode) => present_mode,
+        None => {
+            log::warn!(
+                "No preferred present mode with vsync {} is supported, using FIFO",
+                if vsync { "on" } else { "off" }
+            );
+            vk::PresentModeKHR::FIFO
+        }
+    }
+}
+
+//stands in for the swapchain images when running headless. Same format, so pipelines and
+//the final blit behave exactly as they do when presenting
+pub fn create_offscreen_image(
+    device: &Device,
+    extent: vk::Extent2D,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+) -> EngineResult<AllocatedImage> {
+    let offscreen_image_extent = vk::Extent3D {
+        width: extent.width,
+        height: extent.height,
+        depth: 1,
+    };
+    let offscreen_image_usage_flags = vk::ImageUsageFlags::TRANSFER_SRC
+        | vk::ImageUsageFlags::TRANSFER_DST
+        | vk::ImageUsageFlags::COLOR_ATTACHMENT;
+    AllocatedImage::new(
+        device,
+        allocator,
+        "offscreen_image_allocation",
+        &ImageDesc::new(SWAPCHAIN_FORMAT.format, offscreen_image_usage_flags, offscreen_image_extent),
+    )
+}
 
+//-------------------DRAW IMAGES-----------------------
+pub fn create_draw_images(
+    device: &Device,
+    extent: vk::Extent2D,
+    draw_image_format: vk::Format,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+) -> EngineResult<(AllocatedImage, AllocatedImage)> {
     //draw Image stuff
     let draw_image_extent = vk::Extent3D {
         width: extent.width,
         height: extent.height,
         depth: 1,
     };
-    //hardcoding the draw format to 32 bit float
-    let draw_image_format = vk::Format::R16G16B16A16_SFLOAT;
     let draw_image_usage_flags = vk::ImageUsageFlags::TRANSFER_SRC
         | vk::ImageUsageFlags::TRANSFER_DST
         | vk::ImageUsageFlags::STORAGE
         | vk::ImageUsageFlags::COLOR_ATTACHMENT;
-    let draw_image_create_info =
-        vk_init::image_create_info(draw_image_format, draw_image_usage_flags, draw_image_extent);
-    let draw_image = unsafe { device.create_image(&draw_image_create_info, None).unwrap() };
-    let mut draw_image_requirements = unsafe { device.get_image_memory_requirements(draw_image) };
-    //ensure memory is hosted on GPU VRAM. This is likely unnecessary
-    draw_image_requirements.memory_type_bits |= vk::MemoryPropertyFlags::DEVICE_LOCAL.as_raw();
-    let draw_image_allocation: OnceCell<Allocation> = OnceCell::new();
-    draw_image_allocation
-        .set(
-            allocator
-                .allocate(&AllocationCreateDesc {
-                    name: "draw_image_allocation",
-                    requirements: draw_image_requirements,
-                    location: MemoryLocation::GpuOnly,
-                    linear: false,
-                    allocation_scheme: AllocationScheme::DedicatedImage(draw_image),
-                })
-                .unwrap(),
-        )
-        .unwrap();
-    // Bind memory to the image
-    unsafe {
-        device
-            .bind_image_memory(
-                draw_image,
-                draw_image_allocation.get().unwrap().memory(),
-                draw_image_allocation.get().unwrap().offset(),
-            )
-            .unwrap()
-    };
-
-    //build a image-view for the draw image to use for rendering
-    let draw_image_view_create_info =
-        vk_init::image_view_create_info(draw_image_format, draw_image, vk::ImageAspectFlags::COLOR);
-    let draw_image_view = unsafe {
-        device
-            .create_image_view(&draw_image_view_create_info, None)
-            .unwrap()
-    };
-
-    let allocated_image_draw = AllocatedImage {
-        image: draw_image,
-        image_view: draw_image_view,
-        allocation: draw_image_allocation,
-        image_extent: draw_image_extent,
-        image_format: draw_image_format,
-    };
+    let allocated_image_draw = AllocatedImage::new(
+        device,
+        allocator,
+        "draw_image_allocation",
+        &ImageDesc::new(draw_image_format, draw_image_usage_flags, draw_image_extent),
+    )?;
 
     //depth image stuff
     let depth_image_extent = draw_image_extent;
     let depth_image_format = vk::Format::D32_SFLOAT;
     let depth_image_usage_flags = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT;
+    let allocated_image_depth = AllocatedImage::new(
+        device,
+        allocator,
+        "depth_image_allocation",
+        &ImageDesc::new(depth_image_format, depth_image_usage_flags, depth_image_extent),
+    )?;
+
+    Ok((allocated_image_draw, allocated_image_depth))
+}
 
-    let depth_image_create_info =
-        vk_init::image_create_info(depth_image_format, depth_image_usage_flags, depth_image_extent);
-
-    let depth_image = unsafe { device.create_image(&depth_image_create_info, None).unwrap() };
-    let mut depth_image_requirements = unsafe { device.get_image_memory_requirements(depth_image) };
-
-    //ensure memory is hosted on GPU VRAM. This is likely unnecessary
-    depth_image_requirements.memory_type_bits |= vk::MemoryPropertyFlags::DEVICE_LOCAL.as_raw();
-    let depth_image_allocation: OnceCell<Allocation> = OnceCell::new();
-    depth_image_allocation
-        .set(
-            allocator
-                .allocate(&AllocationCreateDesc {
-                    name: "depth_image_allocation",
-                    requirements: depth_image_requirements,
-                    location: MemoryLocation::GpuOnly,
-                    linear: false,
-                    allocation_scheme: AllocationScheme::DedicatedImage(depth_image),
-                })
-                .unwrap(),
-        )
-        .unwrap();
-    // Bind memory to the image
-    unsafe {
-        device
-            .bind_image_memory(
-                depth_image,
-                depth_image_allocation.get().unwrap().memory(),
-                depth_image_allocation.get().unwrap().offset(),
-            )
-            .unwrap()
+//creates a sampled image and fills it with tightly packed pixel data through a staging buffer,
+//layers one after the other. The description only needs the usage the image has on top of being
+//uploaded and sampled. With a mipmap generator single layer images get a full mip chain built from
+//the data, as long as the format allows it. The image is left in SHADER_READ_ONLY_OPTIMAL layout
+#[allow(clippy::too_many_arguments)]
+pub fn upload_image(
+    device: &Device,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    immediate_command_buffer: vk::CommandBuffer,
+    immediate_fence: vk::Fence,
+    immediate_queue: vk::Queue,
+    name: &str,
+    data: &[u8],
+    mut image_desc: ImageDesc,
+    mipmaps: Option<&MipmapGenerator>,
+) -> EngineResult<AllocatedImage> {
+    let mut staging = create_buffer(
+        device,
+        allocator,
+        data.len() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        MemoryLocation::CpuToGpu,
+    )?;
+    presser::copy_from_slice_to_offset(data, staging.allocation.get_mut().unwrap(), 0).unwrap();
+
+    let extent = image_desc.extent;
+    let mip_method = match mipmaps {
+        Some(generator) if vk_mipmaps::mip_levels(extent) > 1 && image_desc.array_layers == 1 => {
+            let method = generator.method(image_desc.format);
+            if method.is_none() {
+                log::warn!("{:?} cannot be mipmapped, {} only gets one level", image_desc.format, name);
+            }
+            method.map(|method| (generator, method))
+        }
+        _ => None,
     };
-
-    //build a image-view for the depth image to use for rendering
-    let depth_image_view_create_info =
-        vk_init::image_view_create_info(depth_image_format, depth_image, vk::ImageAspectFlags::DEPTH);
-    let depth_image_view = unsafe {
-        device
-            .create_image_view(&depth_image_view_create_info, None)
-            .unwrap()
+    image_desc.usage |= vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST;
+    if let Some((_, method)) = mip_method {
+        image_desc.mip_levels = vk_mipmaps::mip_levels(extent);
+        image_desc.usage |= method.usage();
+    }
+    let mut new_image = match AllocatedImage::new(device, allocator, name, &image_desc) {
+        Ok(new_image) => new_image,
+        Err(e) => {
+            destroy_buffer(device, allocator, &mut staging);
+            return Err(e);
+        }
     };
-
-    let allocated_image_depth = AllocatedImage {
-        image: depth_image,
-        image_view: depth_image_view,
-        allocation: depth_image_allocation,
-        image_extent: depth_image_extent,
-        image_format: depth_image_format,
+    let compute_chain = match mip_method {
+        Some((generator, MipmapMethod::Compute)) => match generator.prepare_compute(device, &new_image) {
+            Ok(chain) => Some(chain),
+            Err(e) => {
+                destroy_buffer(device, allocator, &mut staging);
+                unsafe { new_image.dealloc(device, allocator) };
+                return Err(e);
+            }
+        },
+        _ => None,
     };
 
-    (
-        swapchain_loader,
-        swapchain,
-        surface_format,
-        swapchain_images,
-        swapchain_image_views,
-        surface_extent,
-        allocated_image_draw,
-        allocated_image_depth
-    )
+    let upload_helper = |cmd: vk::CommandBuffer| {
+        vk_images::transition_image(
+            device,
+            cmd,
+            new_image.image,
+            vk::ImageAspectFlags::COLOR,
+            ImageState::UNDEFINED,
+            ImageState::TRANSFER_DST,
+        );
+        vk_images::copy_buffer_to_image(
+            device,
+            cmd,
+            staging.buffer,
+            new_image.image,
+            extent,
+            image_desc.array_layers,
+        );
+        match (mip_method, &compute_chain) {
+            (Some((generator, _)), Some(chain)) => generator.record_compute(device, cmd, &new_image, chain),
+            (Some((generator, _)), None) => generator.record_blit(device, cmd, &new_image),
+            (None, _) => vk_images::transition_image(
+                device,
+                cmd,
+                new_image.image,
+                vk::ImageAspectFlags::COLOR,
+                ImageState::TRANSFER_DST,
+                ImageState::SHADER_READ,
+            ),
+        }
+    };
+    immediate_submit!(
+        device,
+        immediate_command_buffer,
+        immediate_fence,
+        immediate_queue,
+        upload_helper,
+        immediate_command_buffer
+    );
+    if let Some(chain) = compute_chain {
+        chain.destroy(device);
+    }
+    destroy_buffer(device, allocator, &mut staging);
+    Ok(new_image)
 }
 
 pub fn init_frames(
     device: &Device,
     graphics_queue_family: u32,
-) -> (
-    [FrameData; FRAME_OVERLAP],
-    vk::CommandPool,
-    vk::CommandBuffer,
-    vk::Fence,
-) {
+    frame_count: usize,
+) -> EngineResult<(Vec<FrameData>, vk::CommandPool, vk::CommandBuffer, vk::Fence)> {
     let (command_stuff, immediate_pool, immediate_buffer) =
-        init_commands(device, graphics_queue_family);
-    let (sync_structures, immediate_fence) = init_sync_structures(device);
-    let frames = <[FrameData; FRAME_OVERLAP]>::try_from(
-        (0..FRAME_OVERLAP)
-            .map(|frame| -> FrameData {
-                FrameData {
-                    command_pool: command_stuff[frame].0,
-                    main_command_buffer: command_stuff[frame].1,
-                    swapchain_semaphore: sync_structures[frame].0,
-                    render_semaphore: sync_structures[frame].1,
-                    render_fence: sync_structures[frame].2,
-                }
-            })
-            .collect::<Vec<FrameData>>(),
-    );
-    (
-        frames.expect("Error - weird error going from vec to array"),
-        immediate_pool,
-        immediate_buffer,
-        immediate_fence,
-    )
+        init_commands(device, graphics_queue_family, frame_count)?;
+    let (sync_structures, immediate_fence) = init_sync_structures(device, frame_count)?;
+    let mut frames = (0..frame_count)
+        .map(|frame| -> FrameData {
+            FrameData {
+                command_pool: command_stuff[frame].0,
+                main_command_buffer: command_stuff[frame].1,
+                swapchain_semaphore: sync_structures[frame].0,
+                render_semaphore: sync_structures[frame].1,
+                render_fence: sync_structures[frame].2,
+                deletion_queue: DeletionQueue::default(),
+                frame_descriptors: DescriptorAllocatorGrowable::default(),
+            }
+        })
+        .collect::<Vec<FrameData>>();
+    //descriptors that only live for one frame, like the scene data
+    let frame_sizes = [
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+            ratio: 3f32,
+        },
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            ratio: 3f32,
+        },
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            ratio: 3f32,
+        },
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            ratio: 4f32,
+        },
+    ];
+    for frame in frames.iter_mut() {
+        frame.frame_descriptors.init(device, 1000, &frame_sizes)?;
+    }
+    Ok((frames, immediate_pool, immediate_buffer, immediate_fence))
 }
 
+#[allow(clippy::type_complexity)]
 fn init_commands(
     device: &Device,
     graphics_queue_family: u32,
-) -> (
-    [(vk::CommandPool, vk::CommandBuffer); FRAME_OVERLAP],
+    frame_count: usize,
+) -> EngineResult<(
+    Vec<(vk::CommandPool, vk::CommandBuffer)>,
     vk::CommandPool,
     vk::CommandBuffer,
-) {
+)> {
     //array is for each frame, last two are immediate
     let command_pool_info = vk_init::command_pool_create_info(
         graphics_queue_family,
         vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
     );
-    let commands: [(vk::CommandPool, vk::CommandBuffer); FRAME_OVERLAP] = (0..FRAME_OVERLAP)
-        .map(|_| -> (vk::CommandPool, vk::CommandBuffer) {
-            let command_pool = unsafe {
-                device
-                    .create_command_pool(&command_pool_info, None)
-                    .unwrap()
-            };
+    let commands = (0..frame_count)
+        .map(|_| -> EngineResult<(vk::CommandPool, vk::CommandBuffer)> {
+            let command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
             let cmd_alloc_info = vk_init::command_buffer_allocate_info(command_pool, 1);
             let main_command_buffer =
-                unsafe { device.allocate_command_buffers(&cmd_alloc_info).unwrap()[0] };
-            (command_pool, main_command_buffer)
+                unsafe { device.allocate_command_buffers(&cmd_alloc_info)?[0] };
+            Ok((command_pool, main_command_buffer))
         })
-        .collect::<Vec<(vk::CommandPool, vk::CommandBuffer)>>()
-        .try_into()
-        .unwrap();
+        .collect::<EngineResult<Vec<(vk::CommandPool, vk::CommandBuffer)>>>()?;
 
     //immediate ones
-    let immediate_command_pool = unsafe {
-        device
-            .create_command_pool(&command_pool_info, None)
-            .unwrap()
-    };
+    let immediate_command_pool = unsafe { device.create_command_pool(&command_pool_info, None)? };
     let immediate_cmd_alloc_info = vk_init::command_buffer_allocate_info(immediate_command_pool, 1);
-    let immediate_command_buffer = unsafe {
-        device
-            .allocate_command_buffers(&immediate_cmd_alloc_info)
-            .unwrap()[0]
-    };
-    (commands, immediate_command_pool, immediate_command_buffer)
+    let immediate_command_buffer =
+        unsafe { device.allocate_command_buffers(&immediate_cmd_alloc_info)?[0] };
+    Ok((commands, immediate_command_pool, immediate_command_buffer))
 }
 
+#[allow(clippy::type_complexity)]
 fn init_sync_structures(
     device: &Device,
-) -> (
-    [(vk::Semaphore, vk::Semaphore, vk::Fence); FRAME_OVERLAP],
-    vk::Fence,
-) {
+    frame_count: usize,
+) -> EngineResult<(Vec<(vk::Semaphore, vk::Semaphore, vk::Fence)>, vk::Fence)> {
     //last fence is for immediate
     let fence_create_info = vk_init::fence_create_info(vk::FenceCreateFlags::SIGNALED);
     let semaphore_create_info = vk_init::semaphore_create_info(vk::SemaphoreCreateFlags::empty());
 
-    let structures: [(vk::Semaphore, vk::Semaphore, vk::Fence); FRAME_OVERLAP] = (0..FRAME_OVERLAP)
-        .map(|_| -> (vk::Semaphore, vk::Semaphore, vk::Fence) {
-            let render_fence = unsafe { device.create_fence(&fence_create_info, None).unwrap() };
+    let structures = (0..frame_count)
+        .map(|_| -> EngineResult<(vk::Semaphore, vk::Semaphore, vk::Fence)> {
+            let render_fence = unsafe { device.create_fence(&fence_create_info, None)? };
             let (swapchain_semaphore, render_semaphore) = unsafe {
                 (
-                    device
-                        .create_semaphore(&semaphore_create_info, None)
-                        .unwrap(),
-                    device
-                        .create_semaphore(&semaphore_create_info, None)
-                        .unwrap(),
+                    device.create_semaphore(&semaphore_create_info, None)?,
+                    device.create_semaphore(&semaphore_create_info, None)?,
                 )
             };
-            (swapchain_semaphore, render_semaphore, render_fence)
+            Ok((swapchain_semaphore, render_semaphore, render_fence))
         })
-        .collect::<Vec<(vk::Semaphore, vk::Semaphore, vk::Fence)>>()
-        .try_into()
-        .unwrap();
+        .collect::<EngineResult<Vec<(vk::Semaphore, vk::Semaphore, vk::Fence)>>>()?;
     //immediate fence
-    let immediate_fence = unsafe { device.create_fence(&fence_create_info, None).unwrap() };
+    let immediate_fence = unsafe { device.create_fence(&fence_create_info, None)? };
 
-    (structures, immediate_fence)
+    Ok((structures, immediate_fence))
 }
 
 pub fn init_descriptors(
     device: &Device,
     draw_image_view: vk::ImageView,
-) -> (
+) -> EngineResult<(
     DescriptorAllocator,
     vk::DescriptorSet,
     vk::DescriptorSetLayout,
-) {
+    vk::DescriptorSetLayout,
+)> {
     let sizes = [PoolSizeRatio {
         descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
         ratio: 1.0f32,
     }];
 
     let mut global_descriptor_allocator = DescriptorAllocator::default();
-    global_descriptor_allocator.init_pool(device, 10, &sizes);
+    global_descriptor_allocator.init_pool(device, 10, &sizes)?;
 
     let mut dsl_builder = DescriptorSetLayoutBuilder {
         bindings: Vec::new(),
     };
     dsl_builder.add_binding(0, vk::DescriptorType::STORAGE_IMAGE);
-    let draw_image_descriptor_layout = dsl_builder.build(device, vk::ShaderStageFlags::COMPUTE);
+    let draw_image_descriptor_layout = dsl_builder.build(device, vk::ShaderStageFlags::COMPUTE)?;
 
     let draw_image_descriptors =
-        global_descriptor_allocator.allocate(device, draw_image_descriptor_layout);
+        global_descriptor_allocator.allocate(device, draw_image_descriptor_layout)?;
+
+    write_draw_image_descriptor(device, draw_image_descriptors, draw_image_view);
+
+    let mut dsl_builder = DescriptorSetLayoutBuilder::default();
+    dsl_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER);
+    let gpu_scene_data_descriptor_layout = dsl_builder.build(
+        device,
+        vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+    )?;
+
+    Ok((
+        global_descriptor_allocator,
+        draw_image_descriptors,
+        draw_image_descriptor_layout,
+        gpu_scene_data_descriptor_layout,
+    ))
+}
 
+pub fn write_draw_image_descriptor(
+    device: &Device,
+    draw_image_descriptors: vk::DescriptorSet,
+    draw_image_view: vk::ImageView,
+) {
     let img_info = vk::DescriptorImageInfo::builder()
         .image_layout(vk::ImageLayout::GENERAL)
         .image_view(draw_image_view)
@@ -493,18 +617,12 @@ pub fn init_descriptors(
         .build();
 
     unsafe { device.update_descriptor_sets(std::slice::from_ref(&draw_image_write), &[]) };
-
-    (
-        global_descriptor_allocator,
-        draw_image_descriptors,
-        draw_image_descriptor_layout,
-    )
 }
 
 pub fn init_background_pipelines(
     device: &Device,
     descriptor_set_layout: vk::DescriptorSetLayout,
-) -> Vec<vk_compute::ComputeEffect> {
+) -> EngineResult<Vec<vk_compute::ComputeEffect>> {
     let push_constant_range = vk::PushConstantRange::builder()
         .offset(0)
         .size(size_of::<vk_compute::ComputePushConstants>() as u32)
@@ -515,15 +633,12 @@ pub fn init_background_pipelines(
         .push_constant_ranges(slice::from_ref(
             &push_constant_range
         ));
-    let gradient_pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&compute_pipeline_layout_info, None)
-            .unwrap()
-    };
+    let gradient_pipeline_layout =
+        unsafe { device.create_pipeline_layout(&compute_pipeline_layout_info, None)? };
 
     let gradient_shader =
-        vk_pipelines::load_shader_module("./shaders/gradient_color_comp.spv", device);
-    let sky_shader = vk_pipelines::load_shader_module("./shaders/sky_comp.spv", device);
+        vk_pipelines::load_shader_module("./shaders/gradient_color_comp.spv", device)?;
+    let sky_shader = vk_pipelines::load_shader_module("./shaders/sky_comp.spv", device)?;
 
     let shader_entry = CString::new("main").unwrap();
     let stage_info = vk::PipelineShaderStageCreateInfo::builder()
@@ -544,7 +659,7 @@ pub fn init_background_pipelines(
                 std::slice::from_ref(&compute_pipeline_create_info),
                 None,
             )
-            .unwrap()[0]
+            .map_err(|(_, result)| result)?[0]
     };
     let gradient_shader_data = vk_compute::ComputePushConstants {
         data1: cgmath::Vector4::<f32>::new(1f32, 0f32, 0f32, 1f32),
@@ -567,7 +682,7 @@ pub fn init_background_pipelines(
                 std::slice::from_ref(&compute_pipeline_create_info),
                 None,
             )
-            .unwrap()[0]
+            .map_err(|(_, result)| result)?[0]
     };
     let sky_data = vk_compute::ComputePushConstants {
         data1: cgmath::Vector4::<f32>::new(0.1f32, 0.2f32, 0.4f32, 0.97f32),
@@ -585,96 +700,148 @@ pub fn init_background_pipelines(
     unsafe { device.destroy_shader_module(gradient_shader, None) };
     unsafe { device.destroy_shader_module(sky_shader, None) };
 
-    [gradient_effect, sky_effect].into()
+    Ok([gradient_effect, sky_effect].into())
 }
 
-pub fn init_mesh_pipeline(
+pub fn init_pipelines(
     device: &Device,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    gpu_scene_data_layout: vk::DescriptorSetLayout,
     draw_image_format: &vk::Format,
     depth_image_format: vk::Format
-) -> (vk::Pipeline, vk::PipelineLayout) {
-    let triangle_frag_shader =
-        vk_pipelines::load_shader_module("shaders/colored_triangle_frag.spv", device);
-    let triangle_vertex_shader =
-        vk_pipelines::load_shader_module("shaders/colored_triangle_mesh_vert.spv", device);
-
-    //push constants
-    let buffer_range = vk::PushConstantRange::builder()
-        .offset(0)
-        .size(size_of::<GPUDrawPushConstants>() as u32)
-        .stage_flags(vk::ShaderStageFlags::VERTEX)
-        .build();
+) -> EngineResult<(Vec<vk_compute::ComputeEffect>, GLTFMetallicRoughness, SkinningPipeline)> {
+    Ok((
+        init_background_pipelines(device, descriptor_set_layout)?,
+        GLTFMetallicRoughness::build_pipelines(
+            device,
+            gpu_scene_data_layout,
+            draw_image_format,
+            depth_image_format,
+        )?,
+        SkinningPipeline::build(device)?,
+    ))
+}
 
-    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::builder()
-        .push_constant_ranges(slice::from_ref(&buffer_range))
-        .build();
-    let mesh_pipeline_layout = unsafe {
-        device
-            .create_pipeline_layout(&pipeline_layout_info, None)
-            .unwrap()
-    };
+//-------------------DEFAULT DATA-----------------------
+//white is bound wherever a material has no texture, the checkerboard marks missing ones
+pub fn init_default_images(
+    device: &Device,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    immediate_command_buffer: vk::CommandBuffer,
+    immediate_fence: vk::Fence,
+    immediate_queue: vk::Queue,
+) -> EngineResult<(AllocatedImage, AllocatedImage)> {
+    let white_image = upload_image(
+        device,
+        allocator,
+        immediate_command_buffer,
+        immediate_fence,
+        immediate_queue,
+        "white_image_allocation",
+        &[255u8; 4],
+        ImageDesc::new(
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::empty(),
+            vk::Extent3D {
+                width: 1,
+                height: 1,
+                depth: 1,
+            },
+        ),
+        None,
+    )?;
+
+    //16x16 magenta and black checkerboard
+    let magenta = [255u8, 0, 255, 255];
+    let black = [0u8, 0, 0, 255];
+    let pixels = (0..16 * 16)
+        .flat_map(|pixel| {
+            if (pixel % 16 + pixel / 16) % 2 == 0 {
+                magenta
+            } else {
+                black
+            }
+        })
+        .collect::<Vec<u8>>();
+    let error_checkerboard_image = upload_image(
+        device,
+        allocator,
+        immediate_command_buffer,
+        immediate_fence,
+        immediate_queue,
+        "error_checkerboard_image_allocation",
+        &pixels,
+        ImageDesc::new(
+            vk::Format::R8G8B8A8_UNORM,
+            vk::ImageUsageFlags::empty(),
+            vk::Extent3D {
+                width: 16,
+                height: 16,
+                depth: 1,
+            },
+        ),
+        None,
+    )?;
+    Ok((white_image, error_checkerboard_image))
+}
 
-    let mut pipeline_builder = PipelineBuilder::default();
-
-    //use the triangle layout we created
-    pipeline_builder.pipeline_layout = mesh_pipeline_layout;
-    //connecting the vertex and pixel shaders to the pipeline
-    let shader_entry_name = CString::new("main").unwrap();
-    pipeline_builder.set_shaders(
-        triangle_vertex_shader,
-        triangle_frag_shader,
-        &shader_entry_name,
-        &shader_entry_name,
-    );
-    //it will draw triangles
-    pipeline_builder.set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST);
-    //filled triangles
-    pipeline_builder.set_polygon_mode(vk::PolygonMode::FILL);
-    //no backface culling
-    pipeline_builder.set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE);
-    //no multisampling
-    pipeline_builder.set_multisampling_none();
-    //enable blending
-    //pipeline_builder.disable_blending();
-    pipeline_builder.enable_blending_additive();
-    //enable depth testing
-    //pipeline_builder.disable_depth_test();
-    pipeline_builder.enable_depth_test(true, vk::CompareOp::GREATER_OR_EQUAL);
-
-    //connect the image format we will draw into, from draw image
-    pipeline_builder.set_color_attachment_format(draw_image_format);
-    pipeline_builder.set_depth_format(depth_image_format);
-
-    //finally build the pipeline
-    let mesh_pipeline = pipeline_builder.build_pipeline(device);
-
-    //clean structures
-    unsafe {
-        device.destroy_shader_module(triangle_frag_shader, None);
-        device.destroy_shader_module(triangle_vertex_shader, None);
+//highest anisotropy samplers can be created with, 0 when create_device could not enable it
+pub fn max_sampler_anisotropy(instance: &Instance, physical_device: vk::PhysicalDevice) -> f32 {
+    let features = unsafe { instance.get_physical_device_features(physical_device) };
+    if features.sampler_anisotropy == vk::FALSE {
+        return 0f32;
     }
-    (mesh_pipeline, mesh_pipeline_layout)
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    properties.limits.max_sampler_anisotropy
 }
 
-pub fn init_pipelines(
+//nearest, linear and shadow comparison samplers, owned by the cache
+pub fn init_default_samplers(
     device: &Device,
-    descriptor_set_layout: vk::DescriptorSetLayout,
-    draw_image_format: &vk::Format,
-    depth_image_format: vk::Format
-) -> (
-    Vec<vk_compute::ComputeEffect>,
-    (vk::Pipeline, vk::PipelineLayout)
-) {
-    (
-        init_background_pipelines(device, descriptor_set_layout),
-        init_mesh_pipeline(device, draw_image_format, depth_image_format)
-    )
+    sampler_cache: &mut SamplerCache,
+) -> EngineResult<(vk::Sampler, vk::Sampler, vk::Sampler)> {
+    let default_sampler_nearest = sampler_cache.get(device, &SamplerDesc::nearest())?;
+    let default_sampler_linear = sampler_cache.get(device, &SamplerDesc::linear())?;
+    let default_sampler_shadow = sampler_cache.get(device, &SamplerDesc::shadow())?;
+    Ok((default_sampler_nearest, default_sampler_linear, default_sampler_shadow))
 }
 
-pub fn init_default_data(device: &Device,
-                         allocator: &mut gpu_allocator::vulkan::Allocator,
-                         immediate_command_buffer: vk::CommandBuffer,
-                         immediate_fence: vk::Fence,
-                         immediate_queue: vk::Queue) -> Option<Vec<Rc<RefCell<MeshAsset>>>> {
-    load_gltf_meshes(device, allocator, immediate_command_buffer, immediate_fence, immediate_queue, "./assets/basicmesh.glb")
+//untextured white material, used by surfaces that do not reference one
+pub fn init_default_material(
+    device: &Device,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    metal_rough_material: &mut GLTFMetallicRoughness,
+    descriptor_allocator: &mut DescriptorAllocatorGrowable,
+    white_image: vk::ImageView,
+    sampler: vk::Sampler,
+) -> EngineResult<(GLTFMaterial, AllocatedBuffer)> {
+    let mut material_constants = create_buffer(
+        device,
+        allocator,
+        size_of::<MaterialConstants>() as vk::DeviceSize,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        MemoryLocation::CpuToGpu,
+    )?;
+    presser::copy_from_slice_to_offset(
+        slice::from_ref(&MaterialConstants::default()),
+        material_constants.allocation.get_mut().unwrap(),
+        0,
+    )
+    .unwrap();
+
+    let material_resources = MaterialResources {
+        color_image: white_image,
+        color_sampler: sampler,
+        metal_rough_image: white_image,
+        metal_rough_sampler: sampler,
+        data_buffer: material_constants.buffer,
+        data_buffer_offset: 0,
+    };
+    let data = metal_rough_material.write_material(
+        device,
+        MaterialPass::MainColor,
+        &material_resources,
+        descriptor_allocator,
+    )?;
+    Ok((GLTFMaterial { data }, material_constants))
 }
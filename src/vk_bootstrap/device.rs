@@ -1,107 +1,109 @@
+use crate::vk_error::{EngineError, EngineResult};
 use ash::extensions::khr::Surface;
-use ash::vk::{BaseOutStructure, QueueFamilyProperties};
 use ash::{vk, Instance};
-use cgmath::Zero;
+use std::ffi::CStr;
 
+//surface is None when running headless, in which case any graphics queue will do
 pub fn pick_physical_device_and_queue(
     instance: &Instance,
-    surface_loader: &Surface,
-    surface: vk::SurfaceKHR,
-) -> (vk::PhysicalDevice, u32) {
-    let physical_devices = unsafe { instance.enumerate_physical_devices().unwrap() };
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
+) -> EngineResult<(vk::PhysicalDevice, u32)> {
+    let physical_devices = unsafe { instance.enumerate_physical_devices()? };
     log::debug!(
         "{} devices (GPU) found with vulkan support.",
         physical_devices.len()
     );
-    let mut result = None;
-    for &physical_device in physical_devices.iter() {
-        match is_physical_device_suitable(instance, surface_loader, surface, physical_device) {
-            Some(queue_family_index) => {
-                if result.is_none() {
-                    result = Some((physical_device, queue_family_index));
-                    break;
-                }
-            }
-            None => {}
-        }
+    if physical_devices.is_empty() {
+        return Err(EngineError::NoGpu);
     }
-    match result {
-        Some(device_queue_pair) => device_queue_pair,
-        None => {
-            log::error!("Failed to find a suitable GPU!");
-            panic!();
+    //the first device that can render but lacks features is the one worth reporting
+    let mut missing_features_error = None;
+    for &physical_device in physical_devices.iter() {
+        let Some(queue_family_index) =
+            find_graphics_queue_family(instance, surface, physical_device)?
+        else {
+            continue;
+        };
+        let missing = missing_features(instance, physical_device);
+        if missing.is_empty() {
+            return Ok((physical_device, queue_family_index));
         }
+        let device = device_name(instance, physical_device);
+        log::debug!("Skipping {}, missing {:?}", device, missing);
+        missing_features_error.get_or_insert(EngineError::MissingFeatures {
+            device,
+            features: missing,
+        });
     }
+    Err(missing_features_error.unwrap_or(EngineError::NoGraphicsQueue {
+        needs_present: surface.is_some(),
+    }))
 }
 
-fn is_physical_device_suitable(
-    instance: &Instance,
-    surface_loader: &Surface,
-    surface: vk::SurfaceKHR,
-    physical_device: vk::PhysicalDevice,
-) -> Option<u32> {
-    let queue_family_index = surface_supported(instance, surface_loader, surface, physical_device);
-    if features_supported(instance, physical_device) {
-        Some(queue_family_index)
-    } else {
-        None
-    }
+fn device_name(instance: &Instance, physical_device: vk::PhysicalDevice) -> String {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
 }
 
-fn features_supported(instance: &Instance, physical_device: vk::PhysicalDevice) -> bool {
-    let mut features: vk::PhysicalDeviceFeatures2 = vk::PhysicalDeviceFeatures2::default();
+//names of the features create_device enables that this device does not support
+fn missing_features(instance: &Instance, physical_device: vk::PhysicalDevice) -> Vec<&'static str> {
+    let properties = unsafe { instance.get_physical_device_properties(physical_device) };
+    if properties.api_version < vk::make_api_version(0, 1, 3, 0) {
+        return vec!["Vulkan 1.3"];
+    }
+    let mut features12 = vk::PhysicalDeviceVulkan12Features::default();
+    let mut features13 = vk::PhysicalDeviceVulkan13Features::default();
+    let mut features = vk::PhysicalDeviceFeatures2::builder()
+        .push_next(&mut features12)
+        .push_next(&mut features13)
+        .build();
     unsafe { instance.get_physical_device_features2(physical_device, &mut features) };
-    let mut next = features.p_next as *const BaseOutStructure;
-    while !next.is_null() {
-        match unsafe { (*next).s_type } {
-            vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES => {
-                let features12 = unsafe { *(next as *const vk::PhysicalDeviceVulkan12Features) };
-                if features12.buffer_device_address.is_zero()
-                    || features12.descriptor_indexing.is_zero()
-                {
-                    return false;
-                }
-                next = features12.p_next as *const BaseOutStructure;
-            }
-            vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES => {
-                let features13 = unsafe { *(next as *const vk::PhysicalDeviceVulkan13Features) };
-                if features13.dynamic_rendering.is_zero() || features13.synchronization2.is_zero() {
-                    return false;
-                }
-                next = features13.p_next as *const BaseOutStructure;
-            }
-            _ => {
-                next = unsafe { (*next).p_next };
-            }
-        }
+
+    let mut missing = Vec::new();
+    if features.features.shader_storage_image_write_without_format == vk::FALSE {
+        missing.push("shaderStorageImageWriteWithoutFormat");
+    }
+    if features12.buffer_device_address == vk::FALSE {
+        missing.push("bufferDeviceAddress");
+    }
+    if features12.descriptor_indexing == vk::FALSE {
+        missing.push("descriptorIndexing");
+    }
+    if features13.dynamic_rendering == vk::FALSE {
+        missing.push("dynamicRendering");
     }
-    true
+    if features13.synchronization2 == vk::FALSE {
+        missing.push("synchronization2");
+    }
+    missing
 }
 
-fn surface_supported(
+fn find_graphics_queue_family(
     instance: &Instance,
-    surface_loader: &Surface,
-    surface: vk::SurfaceKHR,
+    surface: Option<(&Surface, vk::SurfaceKHR)>,
     physical_device: vk::PhysicalDevice,
-) -> u32 {
-    unsafe {
-        instance
-            .get_physical_device_queue_family_properties(physical_device)
-            .iter()
-            .enumerate()
-            .find(
-                |(queue_family_index, qfp): &(usize, &QueueFamilyProperties)| -> bool {
-                    qfp.queue_flags.contains(vk::QueueFlags::GRAPHICS)
-                        && surface_loader
-                            .get_physical_device_surface_support(
-                                physical_device,
-                                *queue_family_index as u32,
-                                surface,
-                            )
-                            .unwrap()
-                },
-            )
-            .unwrap()
-            .0 as u32
+) -> EngineResult<Option<u32>> {
+    let queue_families =
+        unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+    for (queue_family_index, qfp) in queue_families.iter().enumerate() {
+        if !qfp.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
+            continue;
+        }
+        let presentable = match surface {
+            Some((surface_loader, surface)) => unsafe {
+                surface_loader.get_physical_device_surface_support(
+                    physical_device,
+                    queue_family_index as u32,
+                    surface,
+                )?
+            },
+            None => true,
+        };
+        if presentable {
+            return Ok(Some(queue_family_index as u32));
+        }
     }
+    Ok(None)
 }
@@ -0,0 +1,209 @@
+use crate::vk_engine::frame_data::FRAME_OVERLAP;
+use crate::vk_error::{EngineError, EngineResult};
+use ash::vk;
+use clap::{Args, ValueEnum};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const WINDOW_TITLE: &str = "Vulkan Engine";
+const WINDOW_WIDTH: u32 = 1700;
+const WINDOW_HEIGHT: u32 = 900;
+const DEFAULT_MESHES: &str = "./assets/basicmesh.glb";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+impl From<PresentMode> for vk::PresentModeKHR {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+}
+
+impl PresentMode {
+    //FIFO and FIFO_RELAXED wait for vblank, the others present as soon as a frame is ready
+    pub fn is_vsync(self) -> bool {
+        matches!(self, PresentMode::Fifo | PresentMode::FifoRelaxed)
+    }
+}
+
+//default preference order, the first supported mode that matches the vsync setting is used
+const PRESENT_MODES: [PresentMode; 4] = [
+    PresentMode::Fifo,
+    PresentMode::FifoRelaxed,
+    PresentMode::Mailbox,
+    PresentMode::Immediate,
+];
+
+//formats of the draw image. The background compute shaders write it without a format qualifier,
+//so any storage image format works
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawFormat {
+    Rgba16Float,
+    Rgba32Float,
+    Rgba8Unorm,
+}
+
+impl From<DrawFormat> for vk::Format {
+    fn from(draw_format: DrawFormat) -> Self {
+        match draw_format {
+            DrawFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+            DrawFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+            DrawFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+//everything VulkanEngine::init needs to bring the engine up. Missing keys in a config file
+//keep their default value
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub window_title: String,
+    //window size, or the size of the offscreen image when headless
+    pub width: u32,
+    pub height: u32,
+    //no SDL, surface or swapchain. draw() renders into an offscreen image and run() is not available
+    pub headless: bool,
+    pub frames_in_flight: usize,
+    //present modes in order of preference. Only the ones matching vsync are considered, FIFO is
+    //the fallback since every driver supports it
+    pub present_modes: Vec<PresentMode>,
+    //can be toggled at runtime from the UI
+    pub vsync: bool,
+    pub draw_format: DrawFormat,
+    //enables the validation layer, if installed, and the debug messenger
+    pub validation: bool,
+    //glTF file whose meshes are loaded at startup, the third one is drawn by default
+    pub default_meshes: Option<PathBuf>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            window_title: String::from(WINDOW_TITLE),
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            headless: false,
+            frames_in_flight: FRAME_OVERLAP,
+            present_modes: PRESENT_MODES.to_vec(),
+            vsync: true,
+            draw_format: DrawFormat::Rgba16Float,
+            validation: cfg!(debug_assertions),
+            default_meshes: Some(PathBuf::from(DEFAULT_MESHES)),
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn from_toml_file(path: impl AsRef<Path>) -> EngineResult<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|source| EngineError::ConfigFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_toml_str(&contents).map_err(|source| EngineError::InvalidConfig {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn from_toml_str(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    //the config file named in args, or the defaults, with the command line flags on top
+    pub fn from_args(args: &EngineArgs) -> EngineResult<Self> {
+        let mut config = match &args.config {
+            Some(path) => Self::from_toml_file(path)?,
+            None => Self::default(),
+        };
+        args.apply(&mut config);
+        Ok(config)
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        vk::Extent2D {
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+//command line flags, each one overrides the matching config key
+#[derive(Args, Clone, Debug, Default)]
+pub struct EngineArgs {
+    #[arg(long, value_name = "FILE", help = "TOML file to read the config from")]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub window_title: Option<String>,
+    #[arg(long)]
+    pub width: Option<u32>,
+    #[arg(long)]
+    pub height: Option<u32>,
+    #[arg(long)]
+    pub headless: bool,
+    #[arg(long)]
+    pub frames_in_flight: Option<usize>,
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub present_modes: Option<Vec<PresentMode>>,
+    #[arg(long, value_name = "BOOL")]
+    pub vsync: Option<bool>,
+    #[arg(long, value_enum)]
+    pub draw_format: Option<DrawFormat>,
+    #[arg(long, value_name = "BOOL")]
+    pub validation: Option<bool>,
+    #[arg(long, value_name = "FILE", conflicts_with = "no_default_meshes")]
+    pub default_meshes: Option<PathBuf>,
+    #[arg(long)]
+    pub no_default_meshes: bool,
+}
+
+impl EngineArgs {
+    pub fn apply(&self, config: &mut EngineConfig) {
+        if let Some(window_title) = &self.window_title {
+            config.window_title = window_title.clone();
+        }
+        if let Some(width) = self.width {
+            config.width = width;
+        }
+        if let Some(height) = self.height {
+            config.height = height;
+        }
+        if self.headless {
+            config.headless = true;
+        }
+        if let Some(frames_in_flight) = self.frames_in_flight {
+            config.frames_in_flight = frames_in_flight;
+        }
+        if let Some(present_modes) = &self.present_modes {
+            config.present_modes = present_modes.clone();
+        }
+        if let Some(vsync) = self.vsync {
+            config.vsync = vsync;
+        }
+        if let Some(draw_format) = self.draw_format {
+            config.draw_format = draw_format;
+        }
+        if let Some(validation) = self.validation {
+            config.validation = validation;
+        }
+        if let Some(default_meshes) = &self.default_meshes {
+            config.default_meshes = Some(default_meshes.clone());
+        }
+        if self.no_default_meshes {
+            config.default_meshes = None;
+        }
+    }
+}
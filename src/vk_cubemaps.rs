@@ -0,0 +1,89 @@
+use crate::vk_descriptors::DescriptorSetLayoutBuilder;
+use crate::vk_error::EngineResult;
+use crate::vk_pipelines;
+use ash::vk::PipelineCache;
+use ash::{vk, Device};
+use std::ffi::CString;
+use std::slice;
+
+//must match local_size_x and local_size_y in equirect_to_cube.comp
+const CUBEMAP_GROUP_SIZE: u32 = 8;
+
+//format of the cubemaps made from HDR panoramas, equirect_to_cube.comp writes rgba16f
+pub const HDR_CUBEMAP_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+//cube faces in layer order, which is also the order load_cubemap_files takes them in
+pub const CUBE_FACES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+//compute pass that projects an equirectangular panorama onto the six faces of a cubemap
+pub struct EquirectToCubePipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    //the panorama as a combined image sampler and the cubemap as a 2D array storage image
+    pub descriptor_layout: vk::DescriptorSetLayout,
+}
+
+impl EquirectToCubePipeline {
+    pub fn build(device: &Device) -> EngineResult<Self> {
+        let mut builder = DescriptorSetLayoutBuilder::default();
+        builder.add_binding(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        builder.add_binding(1, vk::DescriptorType::STORAGE_IMAGE);
+        let descriptor_layout = builder.build(device, vk::ShaderStageFlags::COMPUTE)?;
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(slice::from_ref(&descriptor_layout));
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+
+        let cubemap_shader = vk_pipelines::load_shader_module("./shaders/equirect_to_cube_comp.spv", device)?;
+        let shader_entry = CString::new("main").unwrap();
+        let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(cubemap_shader)
+            .name(&shader_entry)
+            .build();
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+            .layout(layout)
+            .stage(stage_info)
+            .build();
+        let pipeline = unsafe {
+            device
+                .create_compute_pipelines(
+                    PipelineCache::null(),
+                    slice::from_ref(&pipeline_create_info),
+                    None,
+                )
+                .map_err(|(_, result)| result)?[0]
+        };
+        unsafe { device.destroy_shader_module(cubemap_shader, None) };
+
+        Ok(EquirectToCubePipeline {
+            pipeline,
+            layout,
+            descriptor_layout,
+        })
+    }
+
+    //the cubemap has to be in GENERAL layout
+    pub fn dispatch(&self, device: &Device, cmd: vk::CommandBuffer, descriptor_set: vk::DescriptorSet, face_size: u32) {
+        let group_count = face_size.div_ceil(CUBEMAP_GROUP_SIZE);
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::COMPUTE,
+                self.layout,
+                0,
+                slice::from_ref(&descriptor_set),
+                &[],
+            );
+            device.cmd_dispatch(cmd, group_count, group_count, CUBE_FACES.len() as u32);
+        }
+    }
+}
+
+//float RGBA pixels to the half floats HDR images are uploaded as
+pub fn hdr_pixel_bytes(pixels: &[f32]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|&value| half::f16::from_f32(value).to_bits().to_ne_bytes())
+        .collect()
+}
@@ -1,11 +1,7 @@
-cfg_if::cfg_if! {
-    if #[cfg(debug_assertions)]{
-        use ash::vk;
-        use std::borrow::Cow;
-        use std::ffi::CStr;
-    }
-}
-#[cfg(debug_assertions)]
+use ash::vk;
+use std::borrow::Cow;
+use std::ffi::CStr;
+
 pub unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
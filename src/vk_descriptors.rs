@@ -1,6 +1,9 @@
+use crate::vk_error::EngineResult;
 use ash::vk;
 use ash::Device;
+use std::slice;
 
+#[derive(Default)]
 pub struct DescriptorSetLayoutBuilder {
     pub bindings: Vec<vk::DescriptorSetLayoutBinding>,
 }
@@ -25,7 +28,7 @@ impl DescriptorSetLayoutBuilder {
         mut self,
         device: &Device,
         shader_stages: vk::ShaderStageFlags,
-    ) -> vk::DescriptorSetLayout {
+    ) -> EngineResult<vk::DescriptorSetLayout> {
         for binding in self.bindings.iter_mut() {
             binding.stage_flags |= shader_stages;
         }
@@ -33,10 +36,11 @@ impl DescriptorSetLayoutBuilder {
             .bindings(&self.bindings)
             .flags(vk::DescriptorSetLayoutCreateFlags::empty())
             .build();
-        unsafe { device.create_descriptor_set_layout(&info, None).unwrap() }
+        Ok(unsafe { device.create_descriptor_set_layout(&info, None)? })
     }
 }
 
+#[derive(Copy, Clone)]
 pub struct PoolSizeRatio {
     pub descriptor_type: vk::DescriptorType,
     pub ratio: f32,
@@ -47,7 +51,12 @@ pub struct DescriptorAllocator {
 }
 
 impl DescriptorAllocator {
-    pub fn init_pool(&mut self, device: &Device, max_sets: u32, pool_ratios: &[PoolSizeRatio]) {
+    pub fn init_pool(
+        &mut self,
+        device: &Device,
+        max_sets: u32,
+        pool_ratios: &[PoolSizeRatio],
+    ) -> EngineResult<()> {
         let mut pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
         for ratio in pool_ratios {
             pool_sizes.push(
@@ -63,7 +72,8 @@ impl DescriptorAllocator {
             .pool_sizes(&pool_sizes)
             .build();
 
-        self.pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
+        self.pool = unsafe { device.create_descriptor_pool(&pool_info, None)? };
+        Ok(())
     }
 
     #[allow(dead_code)]
@@ -85,12 +95,205 @@ impl DescriptorAllocator {
         &mut self,
         device: &Device,
         layout: vk::DescriptorSetLayout,
-    ) -> vk::DescriptorSet {
+    ) -> EngineResult<vk::DescriptorSet> {
         let alloc_info = vk::DescriptorSetAllocateInfo::builder()
             .descriptor_pool(self.pool)
             .set_layouts(std::slice::from_ref(&layout))
             .build();
 
-        unsafe { device.allocate_descriptor_sets(&alloc_info).unwrap()[0] }
+        Ok(unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] })
+    }
+}
+
+//keeps handing out sets by creating new pools whenever the current one runs out. Each new pool
+//holds more sets than the last, up to MAX_SETS_PER_POOL
+#[derive(Default)]
+pub struct DescriptorAllocatorGrowable {
+    ratios: Vec<PoolSizeRatio>,
+    full_pools: Vec<vk::DescriptorPool>,
+    ready_pools: Vec<vk::DescriptorPool>,
+    sets_per_pool: u32,
+}
+
+const MAX_SETS_PER_POOL: u32 = 4092;
+
+impl DescriptorAllocatorGrowable {
+    pub fn init(
+        &mut self,
+        device: &Device,
+        initial_sets: u32,
+        pool_ratios: &[PoolSizeRatio],
+    ) -> EngineResult<()> {
+        self.ratios = pool_ratios.to_vec();
+        let new_pool = Self::create_pool(device, initial_sets, pool_ratios)?;
+        //grow it next allocation
+        self.sets_per_pool = (initial_sets as f32 * 1.5f32) as u32;
+        self.ready_pools.push(new_pool);
+        Ok(())
+    }
+
+    //resets every pool, sets allocated from them must not be in use anymore
+    pub fn clear_pools(&mut self, device: &Device) {
+        for &pool in self.ready_pools.iter() {
+            unsafe {
+                device
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .unwrap()
+            };
+        }
+        for pool in self.full_pools.drain(..) {
+            unsafe {
+                device
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .unwrap()
+            };
+            self.ready_pools.push(pool);
+        }
+    }
+
+    pub fn destroy_pools(&mut self, device: &Device) {
+        for pool in self.ready_pools.drain(..).chain(self.full_pools.drain(..)) {
+            unsafe { device.destroy_descriptor_pool(pool, None) };
+        }
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = vk::DescriptorPool> + '_ {
+        self.ready_pools.iter().chain(self.full_pools.iter()).copied()
+    }
+
+    //hands every pool over to the caller, e.g. to a deletion queue, leaving the allocator empty
+    pub fn take_pools(&mut self) -> Vec<vk::DescriptorPool> {
+        self.ready_pools.drain(..).chain(self.full_pools.drain(..)).collect()
+    }
+
+    pub fn allocate(
+        &mut self,
+        device: &Device,
+        layout: vk::DescriptorSetLayout,
+    ) -> EngineResult<vk::DescriptorSet> {
+        //get or create a pool to allocate from
+        let mut pool_to_use = self.get_pool(device)?;
+        let mut alloc_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(pool_to_use)
+            .set_layouts(slice::from_ref(&layout))
+            .build();
+
+        let descriptor_set = match unsafe { device.allocate_descriptor_sets(&alloc_info) } {
+            Ok(sets) => sets[0],
+            //the pool is full, retire it and try once more with a fresh one
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {
+                self.full_pools.push(pool_to_use);
+                pool_to_use = self.get_pool(device)?;
+                alloc_info.descriptor_pool = pool_to_use;
+                unsafe { device.allocate_descriptor_sets(&alloc_info)?[0] }
+            }
+            Err(e) => {
+                self.ready_pools.push(pool_to_use);
+                return Err(e.into());
+            }
+        };
+        self.ready_pools.push(pool_to_use);
+        Ok(descriptor_set)
+    }
+
+    fn get_pool(&mut self, device: &Device) -> EngineResult<vk::DescriptorPool> {
+        if let Some(pool) = self.ready_pools.pop() {
+            return Ok(pool);
+        }
+        let new_pool = Self::create_pool(device, self.sets_per_pool, &self.ratios)?;
+        self.sets_per_pool = ((self.sets_per_pool as f32 * 1.5f32) as u32).min(MAX_SETS_PER_POOL);
+        Ok(new_pool)
+    }
+
+    fn create_pool(
+        device: &Device,
+        set_count: u32,
+        pool_ratios: &[PoolSizeRatio],
+    ) -> EngineResult<vk::DescriptorPool> {
+        let pool_sizes = pool_ratios
+            .iter()
+            .map(|ratio| {
+                vk::DescriptorPoolSize::builder()
+                    .ty(ratio.descriptor_type)
+                    .descriptor_count((ratio.ratio * set_count as f32) as u32)
+                    .build()
+            })
+            .collect::<Vec<vk::DescriptorPoolSize>>();
+        let pool_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::empty())
+            .max_sets(set_count)
+            .pool_sizes(&pool_sizes)
+            .build();
+        Ok(unsafe { device.create_descriptor_pool(&pool_info, None)? })
+    }
+}
+
+enum DescriptorInfo {
+    Image(vk::DescriptorImageInfo),
+    Buffer(vk::DescriptorBufferInfo),
+}
+
+//collects descriptor writes and applies them to a set in one go
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<(u32, vk::DescriptorType, DescriptorInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn write_image(
+        &mut self,
+        binding: u32,
+        image_view: vk::ImageView,
+        sampler: vk::Sampler,
+        layout: vk::ImageLayout,
+        descriptor_type: vk::DescriptorType,
+    ) {
+        let info = vk::DescriptorImageInfo::builder()
+            .sampler(sampler)
+            .image_view(image_view)
+            .image_layout(layout)
+            .build();
+        self.writes
+            .push((binding, descriptor_type, DescriptorInfo::Image(info)));
+    }
+
+    pub fn write_buffer(
+        &mut self,
+        binding: u32,
+        buffer: vk::Buffer,
+        size: vk::DeviceSize,
+        offset: vk::DeviceSize,
+        descriptor_type: vk::DescriptorType,
+    ) {
+        let info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer)
+            .offset(offset)
+            .range(size)
+            .build();
+        self.writes
+            .push((binding, descriptor_type, DescriptorInfo::Buffer(info)));
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.clear();
+    }
+
+    pub fn update_set(&self, device: &Device, set: vk::DescriptorSet) {
+        let writes = self
+            .writes
+            .iter()
+            .map(|(binding, descriptor_type, info)| {
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_binding(*binding)
+                    .dst_set(set)
+                    .descriptor_type(*descriptor_type);
+                match info {
+                    DescriptorInfo::Image(info) => write.image_info(slice::from_ref(info)),
+                    DescriptorInfo::Buffer(info) => write.buffer_info(slice::from_ref(info)),
+                }
+                .build()
+            })
+            .collect::<Vec<vk::WriteDescriptorSet>>();
+        unsafe { device.update_descriptor_sets(&writes, &[]) };
     }
 }
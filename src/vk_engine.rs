@@ -1,50 +1,73 @@
+mod animation;
+pub mod capture;
+mod cubemaps;
+pub mod deletion_queue;
 mod destructors;
 pub mod frame_data;
+mod images;
 mod immediate;
+mod init_guard;
+mod meshes;
+mod scenes;
+pub mod stats;
 
-use crate::vk_descriptors::DescriptorAllocator;
+use crate::vk_config::{EngineConfig, PresentMode};
+use crate::vk_descriptors::{DescriptorAllocator, DescriptorAllocatorGrowable, DescriptorWriter, PoolSizeRatio};
+use crate::vk_error::{EngineError, EngineResult};
 use crate::vk_types::AllocatedImage;
+use deletion_queue::DeletionQueue;
+use init_guard::InitGuard;
 use crate::{vk_bootstrap, vk_compute};
+use crate::vk_images::{ImageState, ImageStateTracker};
 use crate::{vk_images, vk_init};
-use anyhow::Result;
-#[cfg(debug_assertions)]
 use ash::extensions::ext::DebugUtils;
 use ash::extensions::khr::{Surface, Swapchain};
 use ash::vk::Handle;
 use ash::{vk, Entry};
 pub use ash::{Device, Instance};
-use frame_data::{FrameData, FRAME_OVERLAP};
+use frame_data::FrameData;
+use stats::EngineStats;
 use gpu_allocator::{AllocationSizes, AllocatorDebugSettings};
 use imgui_sdl2;
 use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::Keycode;
 use sdl2::sys::VkInstance;
 use sdl2::EventPump;
-use std::cell::{OnceCell, RefCell};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::marker::PhantomData;
-use std::mem::size_of;
+use std::path::PathBuf;
+use std::mem::{size_of, size_of_val};
 use std::rc::Rc;
 use std::slice;
-use cgmath::Deg;
-use crate::vk_bootstrap::init_default_data;
-use crate::vk_loader::MeshAsset;
+use std::time::Instant;
+use crate::vk_loader::{GLTFMaterial, LoadedGLTF, MeshAsset};
+use crate::vk_materials::GLTFMetallicRoughness;
+use crate::vk_types::buffers::{buffer_device_address, create_buffer};
 use crate::vk_types::gpu_draw_push_constants::GPUDrawPushConstants;
-
-const WINDOW_TITLE: &'static str = "Vulkan Engine";
-const WINDOW_WIDTH: u32 = 1700;
-const WINDOW_HEIGHT: u32 = 900;
+use crate::vk_types::gpu_scene_data::GPUSceneData;
+use crate::vk_scene::{DrawContext, RenderObject};
+use crate::vk_types::bounds::Frustum;
+use crate::vk_cubemaps::EquirectToCubePipeline;
+use crate::vk_mipmaps::MipmapGenerator;
+use crate::vk_skinning::SkinningPipeline;
+use crate::vk_types::sampler_cache::SamplerCache;
+use crate::vk_types::texture_cache::TextureCache;
 
 pub struct VulkanEngine<'a> {
     pub phantom: PhantomData<&'a u32>,
     pub is_initialized: bool,
+    //set when the swapchain no longer matches the window, it is rebuilt before the next frame
+    pub resize_requested: bool,
     pub entry: Entry,
     pub frame_number: i32,
     pub stop_rendering: bool,
     pub window_extent: vk::Extent2D,
-    pub window: sdl2::video::Window,
+    //None when running headless
+    pub window: Option<sdl2::video::Window>,
     pub instance: Instance,
-    #[cfg(debug_assertions)]
     pub debug_utils_loader: DebugUtils,
-    #[cfg(debug_assertions)]
+    //null when validation is disabled
     pub debug_messenger: vk::DebugUtilsMessengerEXT,
     pub surface_loader: Surface,
     pub surface: vk::SurfaceKHR,
@@ -57,10 +80,19 @@ pub struct VulkanEngine<'a> {
     pub swapchain_image_format: vk::SurfaceFormatKHR,
     pub swapchain_images: Vec<vk::Image>,
     pub swapchain_image_views: Vec<vk::ImageView>,
+    //present modes in order of preference, see vk_bootstrap::choose_present_mode
+    pub present_modes: Vec<PresentMode>,
+    pub vsync: bool,
+    //mode the current swapchain was created with
+    pub present_mode: vk::PresentModeKHR,
+    //replaces the swapchain images as the final render target when running headless
+    pub offscreen_image: Option<AllocatedImage>,
     //window event pump
-    pub event_pump: EventPump,
+    pub event_pump: Option<EventPump>,
     //frameStuff
-    pub frames: [FrameData; FRAME_OVERLAP],
+    pub frames: Vec<FrameData>,
+    //resources that live as long as the engine, flushed on drop
+    pub main_deletion_queue: DeletionQueue,
     //queueStuff
     pub graphics_queue: vk::Queue,
     pub graphics_queue_family: u32,
@@ -69,73 +101,155 @@ pub struct VulkanEngine<'a> {
     //draw resources
     pub draw_image: AllocatedImage,
     pub depth_image: AllocatedImage,
+    //layouts and last uses of the draw, depth and swapchain or offscreen images
+    pub image_states: ImageStateTracker,
     pub draw_extent: vk::Extent2D,
+    //fraction of the swapchain extent that gets rendered, the result is upscaled when presenting
+    pub render_scale: f32,
     //descriptor stuff
     pub global_descriptor_allocator: DescriptorAllocator,
     pub draw_image_descriptors: vk::DescriptorSet,
     pub draw_image_descriptor_layout: vk::DescriptorSetLayout,
+    //scene-wide uniforms, uploaded to a new buffer every frame
+    pub scene_data: GPUSceneData,
+    pub gpu_scene_data_descriptor_layout: vk::DescriptorSetLayout,
     //ImGUI stuff - Immediate
     pub immediate_fence: vk::Fence,
     pub immediate_command_pool: vk::CommandPool,
     pub immediate_command_buffer: vk::CommandBuffer,
-    //ImGUI specific structs
-    pub imgui_context: imgui::Context,
-    pub imgui_sdl2: imgui_sdl2::ImguiSdl2,
+    //ImGUI specific structs, None when running headless
+    pub imgui_context: Option<imgui::Context>,
+    pub imgui_sdl2: Option<imgui_sdl2::ImguiSdl2>,
     pub imgui_pool: vk::DescriptorPool,
-    pub renderer: OnceCell<imgui_rs_vulkan_renderer::Renderer>,
+    pub renderer: Option<imgui_rs_vulkan_renderer::Renderer>,
     //compute pipeline effects
     pub background_effects: Vec<vk_compute::ComputeEffect>,
     pub current_background_effect: usize,
-    //mesh pipeline
-    pub mesh_pipeline_layout: vk::PipelineLayout,
-    pub mesh_pipeline: vk::Pipeline,
+    //when false only the background effect is rendered
+    pub draw_meshes: bool,
+    //objects outside the camera frustum are not drawn
+    pub frustum_culling: bool,
+    pub stats: EngineStats,
+    //seconds between the last two update_scene calls, animations advance by it
+    pub delta_time: f32,
+    pub last_frame_time: Instant,
+    //default textures and samplers, bound wherever a material has none of its own
+    pub white_image: AllocatedImage,
+    pub error_checkerboard_image: AllocatedImage,
+    pub default_sampler_linear: vk::Sampler,
+    pub default_sampler_nearest: vk::Sampler,
+    //depth comparison, for shadow maps
+    pub default_sampler_shadow: vk::Sampler,
+    //every sampler of the engine, see get_sampler
+    pub sampler_cache: SamplerCache,
+    //images decoded from files, see load_image_file
+    pub texture_cache: TextureCache,
+    //materials
+    pub metal_rough_material: GLTFMetallicRoughness,
+    pub skinning_pipeline: SkinningPipeline,
+    pub mipmap_generator: MipmapGenerator,
+    //projects HDR panoramas onto cubemaps, see load_hdr_cubemap
+    pub equirect_to_cube_pipeline: EquirectToCubePipeline,
+    pub material_descriptor_allocator: DescriptorAllocatorGrowable,
+    pub default_material: Rc<GLTFMaterial>,
     //testing meshes
     pub test_meshes: Vec<Rc<RefCell<MeshAsset>>>,
+    //glTF scenes by name, see load_gltf
+    pub loaded_scenes: HashMap<String, Rc<RefCell<LoadedGLTF>>>,
+    //render list rebuilt by update_scene every frame
+    pub main_draw_context: DrawContext,
+    //where the next presented frame gets saved, see request_screenshot
+    pub screenshot_request: Option<PathBuf>,
 
 }
 
 // Main loop functions
 impl<'a> VulkanEngine<'a> {
-    pub fn init() -> Result<Self> {
-        let window_extent = vk::Extent2D {
-            width: 1700,
-            height: 900,
-        };
+    //builds the engine without SDL, a surface or a swapchain. draw() renders into an offscreen
+    //image of the given extent instead of presenting, and run() is not available
+    pub fn init_headless(extent: vk::Extent2D) -> EngineResult<Self> {
+        Self::init(EngineConfig {
+            width: extent.width,
+            height: extent.height,
+            headless: true,
+            ..Default::default()
+        })
+    }
+
+    pub fn init(config: EngineConfig) -> EngineResult<Self> {
+        let window_extent = config.extent();
+        let headless = config.headless;
+        let frames_in_flight = config.frames_in_flight.max(1);
         //SDL initialization
         //todo: not killed
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-        let window = video_subsystem
-            .window(WINDOW_TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)
-            .position_centered()
-            .vulkan()
-            .build()
-            .unwrap();
+        let (window, event_pump) = if headless {
+            (None, None)
+        } else {
+            let sdl_context = sdl2::init().map_err(EngineError::Sdl)?;
+            let video_subsystem = sdl_context.video().map_err(EngineError::Sdl)?;
+            let window = video_subsystem
+                .window(&config.window_title, window_extent.width, window_extent.height)
+                .position_centered()
+                .resizable()
+                .vulkan()
+                .build()
+                .map_err(|e| EngineError::Sdl(e.to_string()))?;
+            //Event pump
+            let event_pump = sdl_context.event_pump().map_err(EngineError::Sdl)?;
+            (Some(window), Some(event_pump))
+        };
 
-        //Vulkan initialization
+        //Vulkan initialization. Everything created from here on goes into the guard first, so an
+        //error on the way out destroys it again
+        let mut guard = InitGuard::default();
         let entry = Entry::linked();
-        let instance = vk_bootstrap::create_instance(&entry, &window);
+        let instance = vk_bootstrap::create_instance(&entry, window.as_ref(), config.validation)?;
+        guard.set_instance(&instance);
         //Debug Utils initialization
-        #[cfg(debug_assertions)]
         let debug_utils_loader = DebugUtils::new(&entry, &instance);
-        #[cfg(debug_assertions)]
-        let debug_messenger = vk_bootstrap::create_debug_messenger(&debug_utils_loader);
+        let debug_messenger = if config.validation {
+            let debug_messenger = vk_bootstrap::create_debug_messenger(&debug_utils_loader)?;
+            guard.set_debug_messenger(&debug_utils_loader, debug_messenger);
+            debug_messenger
+        } else {
+            vk::DebugUtilsMessengerEXT::null()
+        };
         //Surface initialization
         let surface_loader = Surface::new(&entry, &instance);
-        let instance_handle = instance.handle().as_raw();
-        let surface = vk::SurfaceKHR::from_raw(
-            window
-                .vulkan_create_surface(instance_handle as VkInstance)
-                .unwrap(),
-        );
+        let surface = match &window {
+            Some(window) => {
+                let instance_handle = instance.handle().as_raw();
+                let surface = vk::SurfaceKHR::from_raw(
+                    window
+                        .vulkan_create_surface(instance_handle as VkInstance)
+                        .map_err(EngineError::Sdl)?,
+                );
+                guard.set_surface(&surface_loader, surface);
+                surface
+            }
+            None => vk::SurfaceKHR::null(),
+        };
         //Device creation
         let (device, physical_device, graphics_queue, graphics_queue_family) =
-            vk_bootstrap::create_device(&instance, &surface_loader, surface);
-        //Event pump
-        let event_pump = sdl_context.event_pump().unwrap();
+            vk_bootstrap::create_device(
+                &instance,
+                window.as_ref().map(|_| (&surface_loader, surface)),
+            )?;
+        guard.set_device(&device);
         //FrameData creation
         let (frames, immediate_command_pool, immediate_command_buffer, immediate_fence) =
-            vk_bootstrap::init_frames(&device, graphics_queue_family);
+            vk_bootstrap::init_frames(&device, graphics_queue_family, frames_in_flight)?;
+        for frame in frames.iter() {
+            guard.push(frame.command_pool);
+            guard.push(frame.render_fence);
+            guard.push(frame.render_semaphore);
+            guard.push(frame.swapchain_semaphore);
+            for pool in frame.frame_descriptors.pools() {
+                guard.push(pool);
+            }
+        }
+        guard.push(immediate_command_pool);
+        guard.push(immediate_fence);
 
         //Allocator creation
         let allocator_create_info = gpu_allocator::vulkan::AllocatorCreateDesc {
@@ -146,8 +260,8 @@ impl<'a> VulkanEngine<'a> {
             buffer_device_address: true,
             allocation_sizes: AllocationSizes::default(),
         };
-        let mut allocator = gpu_allocator::vulkan::Allocator::new(&allocator_create_info).unwrap();
-        //Swapchain creation
+        guard.set_allocator(gpu_allocator::vulkan::Allocator::new(&allocator_create_info)?);
+        //Swapchain creation, or the offscreen image standing in for it
         let (
             swapchain_loader,
             swapchain,
@@ -155,50 +269,195 @@ impl<'a> VulkanEngine<'a> {
             swapchain_images,
             swapchain_image_views,
             swapchain_extent,
-            draw_image,
-            depth_image
-        ) = vk_bootstrap::create_swapchain(
-            &instance,
-            &device,
-            physical_device,
-            &surface_loader,
-            surface,
-            window_extent,
-            &mut allocator,
-        );
-        let (global_descriptor_allocator, draw_image_descriptors, draw_image_descriptor_layout) =
-            vk_bootstrap::init_descriptors(&device, draw_image.image_view);
-        let (background_effects,
-            (mesh_pipeline, mesh_pipeline_layout)) =
+            present_mode,
+            offscreen_image,
+        ) = if headless {
+            (
+                Swapchain::new(&instance, &device),
+                vk::SwapchainKHR::null(),
+                vk_bootstrap::SWAPCHAIN_FORMAT,
+                Vec::new(),
+                Vec::new(),
+                window_extent,
+                vk::PresentModeKHR::FIFO,
+                Some(vk_bootstrap::create_offscreen_image(
+                    &device,
+                    window_extent,
+                    guard.allocator(),
+                )?),
+            )
+        } else {
+            let (
+                swapchain_loader,
+                swapchain,
+                swapchain_image_format,
+                swapchain_images,
+                swapchain_image_views,
+                swapchain_extent,
+                present_mode,
+            ) = vk_bootstrap::create_swapchain(
+                &instance,
+                &device,
+                physical_device,
+                &surface_loader,
+                surface,
+                window_extent,
+                &config.present_modes,
+                config.vsync,
+            )?;
+            guard.set_swapchain(&swapchain_loader, swapchain);
+            for &view in swapchain_image_views.iter() {
+                guard.push(view);
+            }
+            (
+                swapchain_loader,
+                swapchain,
+                swapchain_image_format,
+                swapchain_images,
+                swapchain_image_views,
+                swapchain_extent,
+                present_mode,
+                None,
+            )
+        };
+        //the draw images outlive the swapchain, so they are sized for the largest extent we expect
+        let draw_image_extent = match &window {
+            Some(window) => max_draw_extent(window, window_extent),
+            None => window_extent,
+        };
+        let (draw_image, depth_image) =
+            vk_bootstrap::create_draw_images(
+                &device,
+                draw_image_extent,
+                config.draw_format.into(),
+                guard.allocator(),
+            )?;
+        if let Some(offscreen_image) = &offscreen_image {
+            guard.push_image(offscreen_image);
+        }
+        guard.push_image(&draw_image);
+        guard.push_image(&depth_image);
+        let (
+            global_descriptor_allocator,
+            draw_image_descriptors,
+            draw_image_descriptor_layout,
+            gpu_scene_data_descriptor_layout,
+        ) = vk_bootstrap::init_descriptors(&device, draw_image.image_view)?;
+        guard.deletion_queue.push(global_descriptor_allocator.pool);
+        guard.deletion_queue.push(draw_image_descriptor_layout);
+        guard.deletion_queue.push(gpu_scene_data_descriptor_layout);
+        let (background_effects, mut metal_rough_material, skinning_pipeline) =
             vk_bootstrap::init_pipelines(
                 &device,
                 draw_image_descriptor_layout,
+                gpu_scene_data_descriptor_layout,
                 &draw_image.image_format,
                 depth_image.image_format
+            )?;
+        let mut image_states = ImageStateTracker::default();
+        image_states.track(draw_image.image, draw_image.image_format, ImageState::UNDEFINED);
+        image_states.track(depth_image.image, depth_image.image_format, ImageState::UNDEFINED);
+        if let Some(offscreen_image) = &offscreen_image {
+            image_states.track(
+                offscreen_image.image,
+                offscreen_image.image_format,
+                ImageState::UNDEFINED,
             );
-        //No need to add to deletion queue, drop method takes care of it
-        let (imgui_context, imgui_sdl2, imgui_pool, renderer) = immediate::init_imgui(
-            &instance,
+        }
+        //all background effects share the same layout
+        guard.deletion_queue.push(background_effects[0].layout);
+        for effect in background_effects.iter() {
+            guard.deletion_queue.push(effect.pipeline);
+        }
+        guard.deletion_queue.push(metal_rough_material.material_layout);
+        //both material pipelines share the same layout
+        guard.deletion_queue.push(metal_rough_material.opaque_pipeline.layout);
+        guard.deletion_queue.push(metal_rough_material.opaque_pipeline.pipeline);
+        guard.deletion_queue.push(metal_rough_material.transparent_pipeline.pipeline);
+        guard.deletion_queue.push(skinning_pipeline.layout);
+        guard.deletion_queue.push(skinning_pipeline.pipeline);
+        let mipmap_generator = MipmapGenerator::build(&instance, physical_device, &device)?;
+        guard.deletion_queue.push(mipmap_generator.descriptor_layout);
+        guard.deletion_queue.push(mipmap_generator.layout);
+        guard.deletion_queue.push(mipmap_generator.pipeline);
+        let equirect_to_cube_pipeline = EquirectToCubePipeline::build(&device)?;
+        guard.deletion_queue.push(equirect_to_cube_pipeline.descriptor_layout);
+        guard.deletion_queue.push(equirect_to_cube_pipeline.layout);
+        guard.deletion_queue.push(equirect_to_cube_pipeline.pipeline);
+
+        let (white_image, error_checkerboard_image) = vk_bootstrap::init_default_images(
             &device,
-            physical_device,
+            guard.allocator(),
+            immediate_command_buffer,
+            immediate_fence,
             graphics_queue,
-            immediate_command_pool,
-            swapchain_image_format.format,
-            &window,
-        );
-        let test_meshes = init_default_data(&device, &mut allocator, immediate_command_buffer, immediate_fence, graphics_queue);
-        Ok(VulkanEngine {
+        )?;
+        guard.push_image(&white_image);
+        guard.push_image(&error_checkerboard_image);
+        let mut sampler_cache =
+            SamplerCache::new(vk_bootstrap::max_sampler_anisotropy(&instance, physical_device));
+        let (default_sampler_nearest, default_sampler_linear, default_sampler_shadow) =
+            vk_bootstrap::init_default_samplers(&device, &mut sampler_cache)?;
+        guard.push(default_sampler_nearest);
+        guard.push(default_sampler_linear);
+        guard.push(default_sampler_shadow);
+
+        //material sets live as long as the engine, each one holds a uniform buffer and two textures
+        let material_sizes = [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                ratio: 1f32,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 2f32,
+            },
+        ];
+        let mut material_descriptor_allocator = DescriptorAllocatorGrowable::default();
+        material_descriptor_allocator.init(&device, 16, &material_sizes)?;
+        for pool in material_descriptor_allocator.pools() {
+            guard.push(pool);
+        }
+        let (default_material, default_material_constants) = vk_bootstrap::init_default_material(
+            &device,
+            guard.allocator(),
+            &mut metal_rough_material,
+            &mut material_descriptor_allocator,
+            white_image.image_view,
+            default_sampler_linear,
+        )?;
+        guard.deletion_queue.push(default_material_constants);
+        //headless engines never build UI frames. imgui only allows one live context per process,
+        //so skipping it also lets several headless engines coexist
+        let (imgui_context, imgui_sdl2, imgui_pool, renderer) = match &window {
+            Some(window) => {
+                let (imgui_context, imgui_sdl2, imgui_pool, renderer) = immediate::init_imgui(
+                    &instance,
+                    &device,
+                    physical_device,
+                    graphics_queue,
+                    immediate_command_pool,
+                    swapchain_image_format.format,
+                    frames_in_flight,
+                    window,
+                )?;
+                guard.deletion_queue.push(imgui_pool);
+                (Some(imgui_context), Some(imgui_sdl2), imgui_pool, Some(renderer))
+            }
+            None => (None, None, vk::DescriptorPool::null(), None),
+        };
+        let (allocator, main_deletion_queue) = guard.disarm();
+        let mut engine = VulkanEngine {
             phantom: PhantomData,
             is_initialized: true,
+            resize_requested: false,
             entry,
             frame_number: 0,
             stop_rendering: false,
             window_extent,
             window,
             instance,
-            #[cfg(debug_assertions)]
             debug_utils_loader,
-            #[cfg(debug_assertions)]
             debug_messenger,
             surface_loader,
             surface,
@@ -210,50 +469,169 @@ impl<'a> VulkanEngine<'a> {
             swapchain_image_format,
             swapchain_images,
             swapchain_image_views,
+            present_modes: config.present_modes,
+            vsync: config.vsync,
+            present_mode,
+            offscreen_image,
             event_pump,
             frames,
+            main_deletion_queue,
             graphics_queue,
             graphics_queue_family,
             allocator,
             draw_image,
             depth_image,
+            image_states,
             draw_extent: window_extent,
+            render_scale: 1f32,
             global_descriptor_allocator,
             draw_image_descriptors,
             draw_image_descriptor_layout,
+            scene_data: GPUSceneData::default(),
+            gpu_scene_data_descriptor_layout,
             immediate_fence,
             immediate_command_pool,
             immediate_command_buffer,
             imgui_context,
             imgui_sdl2,
             imgui_pool,
-            renderer: renderer.into(),
+            renderer,
             background_effects,
             current_background_effect: 0,
-            mesh_pipeline_layout,
-            mesh_pipeline,
-            test_meshes: test_meshes.unwrap(),
-        })
+            draw_meshes: true,
+            frustum_culling: true,
+            stats: EngineStats::default(),
+            delta_time: 0f32,
+            last_frame_time: Instant::now(),
+            white_image,
+            error_checkerboard_image,
+            default_sampler_linear,
+            default_sampler_nearest,
+            default_sampler_shadow,
+            sampler_cache,
+            texture_cache: TextureCache::default(),
+            metal_rough_material,
+            skinning_pipeline,
+            mipmap_generator,
+            equirect_to_cube_pipeline,
+            material_descriptor_allocator,
+            default_material: Rc::new(default_material),
+            test_meshes: Vec::new(),
+            loaded_scenes: HashMap::new(),
+            main_draw_context: DrawContext::default(),
+            screenshot_request: None,
+        };
+        //loaded through the engine so the meshes get their materials
+        if let Some(path) = &config.default_meshes {
+            engine.load_gltf_meshes(path)?;
+        }
+        Ok(engine)
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.window.is_none()
+    }
+
+    //rebuilds the swapchain at the current window size. The draw images are left alone,
+    //draw_extent is clamped to them every frame
+    pub fn resize_swapchain(&mut self) -> EngineResult<()> {
+        let (width, height) = match &self.window {
+            Some(window) => window.size(),
+            None => return Err(EngineError::Headless("resize_swapchain")),
+        };
+        unsafe { self.device.device_wait_idle()? };
+
+        self.destroy_swapchain();
+
+        self.window_extent = vk::Extent2D { width, height };
+
+        let (
+            swapchain_loader,
+            swapchain,
+            swapchain_image_format,
+            swapchain_images,
+            swapchain_image_views,
+            swapchain_extent,
+            present_mode,
+        ) = vk_bootstrap::create_swapchain(
+            &self.instance,
+            &self.device,
+            self.physical_device,
+            &self.surface_loader,
+            self.surface,
+            self.window_extent,
+            &self.present_modes,
+            self.vsync,
+        )?;
+        self.swapchain_loader = swapchain_loader;
+        self.swapchain = swapchain;
+        self.swapchain_image_format = swapchain_image_format;
+        self.swapchain_images = swapchain_images;
+        self.swapchain_image_views = swapchain_image_views;
+        self.swapchain_extent = swapchain_extent;
+        self.present_mode = present_mode;
+
+        self.resize_requested = false;
+        Ok(())
+    }
+
+    //the swapchain is rebuilt with the new present mode before the next frame
+    pub fn set_vsync(&mut self, vsync: bool) {
+        if self.vsync != vsync {
+            self.vsync = vsync;
+            self.resize_requested = true;
+        }
+    }
+
+    pub fn run(&mut self) -> EngineResult<()> {
+        self.run_with(|_| {})
     }
-    pub fn run(&mut self) {
+
+    //on_frame is called once per frame, after window events are handled and before the UI
+    //and the frame are built
+    pub fn run_with<F>(&mut self, mut on_frame: F) -> EngineResult<()>
+    where
+        F: FnMut(&mut VulkanEngine<'a>),
+    {
+        if self.is_headless() {
+            log::error!("run() needs a window, call draw() directly when running headless");
+            return Ok(());
+        }
         let mut b_quit = false;
         // main loop
         while !b_quit {
+            //a minimized window has no size to rebuild the swapchain with, wait until it is restored
+            if self.resize_requested && !self.stop_rendering {
+                self.resize_swapchain()?;
+            }
+            let event_pump = self.event_pump.as_mut().unwrap();
+            let imgui_sdl2 = self.imgui_sdl2.as_mut().unwrap();
+            let imgui_context = self.imgui_context.as_mut().unwrap();
             // Handle events on queue
-            for event in self.event_pump.poll_iter() {
-                self.imgui_sdl2
-                    .handle_event(&mut self.imgui_context, &event);
-                if self.imgui_sdl2.ignore_event(&event) {
+            for event in event_pump.poll_iter() {
+                imgui_sdl2.handle_event(imgui_context, &event);
+                if imgui_sdl2.ignore_event(&event) {
                     continue;
                 }
                 match event {
                     Event::Quit { .. } => {
                         b_quit = true;
                     }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F12),
+                        repeat: false,
+                        ..
+                    } => {
+                        self.screenshot_request =
+                            Some(PathBuf::from(format!("screenshot_{}.png", self.frame_number)));
+                    }
                     Event::Window { win_event, .. } => {
                         match win_event {
                             WindowEvent::Minimized => self.stop_rendering = true,
                             WindowEvent::Restored => self.stop_rendering = false,
+                            WindowEvent::Resized(..) | WindowEvent::SizeChanged(..) => {
+                                self.resize_requested = true
+                            }
                             _ => {}
                         };
                     }
@@ -265,13 +643,18 @@ impl<'a> VulkanEngine<'a> {
                 std::thread::sleep(std::time::Duration::from_millis(10));
                 continue;
             }
+            on_frame(self);
+            let window = self.window.as_ref().unwrap();
+            let event_pump = self.event_pump.as_mut().unwrap();
+            let imgui_sdl2 = self.imgui_sdl2.as_mut().unwrap();
+            let imgui_context = self.imgui_context.as_mut().unwrap();
             //must be called before imgui.frame()
-            self.imgui_sdl2.prepare_frame(
-                self.imgui_context.io_mut(),
-                &self.window,
-                &self.event_pump.mouse_state(),
+            imgui_sdl2.prepare_frame(
+                imgui_context.io_mut(),
+                window,
+                &event_pump.mouse_state(),
             );
-            let ui = self.imgui_context.new_frame();
+            let ui = imgui_context.new_frame();
             //UI rendering code
 
             //ui.show_demo_window(&mut true);
@@ -280,6 +663,12 @@ impl<'a> VulkanEngine<'a> {
             let shader_selection_window = ui.window("Shader selector");
             let effects_len = effects.len();
             shader_selection_window.build(|| {
+                ui.slider("Render Scale", 0.3f32, 1f32, &mut self.render_scale);
+                if ui.checkbox("VSync", &mut self.vsync) {
+                    self.resize_requested = true;
+                }
+                ui.text(format!("Present mode: {:?}", self.present_mode));
+
                 ui.slider(
                     "Effect Index",
                     0,
@@ -293,12 +682,23 @@ impl<'a> VulkanEngine<'a> {
                 ui.input_float4("data3", &mut data.data3).build();
                 ui.input_float4("data4", &mut data.data4).build();
             });
+            if self
+                .loaded_scenes
+                .values()
+                .any(|scene| !scene.borrow().animations.is_empty())
+            {
+                ui.window("Animation")
+                    .build(|| animation::animation_ui(ui, &self.loaded_scenes));
+            }
+            ui.window("Stats")
+                .build(|| stats::stats_ui(ui, &self.stats, &mut self.frustum_culling));
 
             //call this immediately before rendering
-            self.imgui_sdl2.prepare_render(&ui, &self.window);
-            self.imgui_context.render();
+            imgui_sdl2.prepare_render(ui, window);
+            imgui_context.render();
             self.draw();
         }
+        Ok(())
     }
     pub fn draw(&mut self) {
         unsafe {
@@ -311,26 +711,53 @@ impl<'a> VulkanEngine<'a> {
                 .unwrap()
         }
         //delete all objects crated for last draw
+        let frame_index = self.get_current_frame_index();
         unsafe {
-            self.get_current_frame_mut().dealloc_last_frame();
+            self.frames[frame_index].dealloc_last_frame(&self.device, &mut self.allocator);
         }
 
-        unsafe {
-            self.device
-                .reset_fences(slice::from_ref(&self.get_current_frame().render_fence))
-                .unwrap()
-        }
-
-        let swapchain_image_index = unsafe {
-            self.swapchain_loader
-                .acquire_next_image(
+        //headless engines have a single offscreen target and nothing to acquire
+        let swapchain_image_index = if self.is_headless() {
+            0
+        } else {
+            let acquire_result = unsafe {
+                self.swapchain_loader.acquire_next_image(
                     self.swapchain,
                     1000000000,
                     self.get_current_frame().swapchain_semaphore,
                     vk::Fence::null(),
                 )
+            };
+            match acquire_result {
+                Ok((swapchain_image_index, suboptimal)) => {
+                    //still usable, draw this frame and rebuild before the next one
+                    if suboptimal {
+                        self.resize_requested = true;
+                    }
+                    swapchain_image_index
+                }
+                //the fence is still signaled, so skipping the frame here is safe
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    self.resize_requested = true;
+                    return;
+                }
+                Err(e) => panic!("Failed to acquire swapchain image: {}", e),
+            }
+        };
+
+        //only reset the fence once we know work will be submitted with it
+        unsafe {
+            self.device
+                .reset_fences(slice::from_ref(&self.get_current_frame().render_fence))
                 .unwrap()
-                .0
+        }
+
+        let (target_image, target_image_view) = match &self.offscreen_image {
+            Some(offscreen_image) => (offscreen_image.image, offscreen_image.image_view),
+            None => (
+                self.swapchain_images[swapchain_image_index as usize],
+                self.swapchain_image_views[swapchain_image_index as usize],
+            ),
         };
         let cmd = self.get_current_frame().main_command_buffer;
         //since waiting on the fence means that commands have finished executing, we can reset the buffer
@@ -344,10 +771,12 @@ impl<'a> VulkanEngine<'a> {
         let cmd_begin_info =
             vk_init::command_buffer_begin_info(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-        //set extent for draw image
+        //set extent for draw image, only the scaled part of it that maps to the swapchain is used
         self.draw_extent = vk::Extent2D {
-            width: self.draw_image.image_extent.width,
-            height: self.draw_image.image_extent.height,
+            width: (self.swapchain_extent.width.min(self.draw_image.image_extent.width) as f32
+                * self.render_scale) as u32,
+            height: (self.swapchain_extent.height.min(self.draw_image.image_extent.height) as f32
+                * self.render_scale) as u32,
         };
 
         //Begin the command buffer for instruction submmission
@@ -357,89 +786,72 @@ impl<'a> VulkanEngine<'a> {
                 .unwrap()
         }
 
-        // transition our main draw image into general layout so we can write into it
-        // we will overwrite it all so we dont care about what was the older layout
-        // GENERAL is required here by the background compute shader
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.draw_image.image,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::GENERAL,
-        );
+        //the swapchain image comes back from the presentation engine with nothing worth keeping
+        if !self.is_headless() {
+            self.image_states
+                .track(target_image, self.swapchain_image_format.format, ImageState::ACQUIRED);
+        }
+
+        //the background compute shader overwrites the whole draw image, GENERAL is required for it
+        self.image_states
+            .discard(&self.device, cmd, self.draw_image.image, ImageState::COMPUTE_WRITE);
 
         self.draw_background(cmd);
 
         //set the draw image to be drawable by graphics commands
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.draw_image.image,
-            vk::ImageLayout::GENERAL,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        );
+        self.image_states
+            .transition(&self.device, cmd, self.draw_image.image, ImageState::COLOR_ATTACHMENT);
 
-        //transition depth image for depth testing
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.depth_image.image,
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
-        );
+        //depth is cleared when rendering starts
+        self.image_states
+            .discard(&self.device, cmd, self.depth_image.image, ImageState::DEPTH_ATTACHMENT);
 
-        self.draw_geometry(&self.device, cmd);
+        if self.draw_meshes {
+            self.draw_geometry(cmd);
+        }
 
         //change draw image to be source of a copy command
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.draw_image.image,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-        );
+        self.image_states
+            .transition(&self.device, cmd, self.draw_image.image, ImageState::TRANSFER_SRC);
 
         //Set the swapchain image to be the destination of the same copy command
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.swapchain_images[swapchain_image_index as usize],
-            vk::ImageLayout::UNDEFINED,
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-        );
+        self.image_states
+            .discard(&self.device, cmd, target_image, ImageState::TRANSFER_DST);
 
         //submit copy from draw image to the current swapchain image to the command buffer
         vk_images::copy_image_to_image(
             &self.device,
             cmd,
             self.draw_image.image,
-            self.swapchain_images[swapchain_image_index as usize],
+            target_image,
             self.draw_extent,
             self.swapchain_extent,
         );
 
-        // set swapchain image layout to color attachment so we can show draw on it
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.swapchain_images[swapchain_image_index as usize],
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-        );
-        //draw ImGUI directly into swapchain image
-        self.draw_imgui(
-            cmd,
-            self.swapchain_image_views[swapchain_image_index as usize],
-        );
+        let mut screenshot = None;
+        if self.is_headless() {
+            //leave the offscreen image ready to be read back, there is no UI to draw on top of it
+            self.image_states
+                .transition(&self.device, cmd, target_image, ImageState::TRANSFER_SRC);
+        } else {
+            // set swapchain image layout to color attachment so we can show draw on it
+            self.image_states
+                .transition(&self.device, cmd, target_image, ImageState::COLOR_ATTACHMENT);
+            //draw ImGUI directly into swapchain image
+            self.draw_imgui(cmd, target_image_view);
 
-        //transition swapchain image to a presentable layout
-        vk_images::transition_image(
-            &self.device,
-            cmd,
-            self.swapchain_images[swapchain_image_index as usize],
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::PRESENT_SRC_KHR,
-        );
+            //copy the finished frame out before it is handed over to the presentation engine
+            if let Some(path) = self.screenshot_request.take() {
+                match self.record_screenshot(cmd, target_image) {
+                    Ok(readback) => screenshot = Some((path, readback)),
+                    Err(e) => log::error!("Failed to take screenshot {}: {}", path.display(), e),
+                }
+            }
+
+            //transition swapchain image to a presentable layout
+            self.image_states
+                .transition(&self.device, cmd, target_image, ImageState::PRESENT);
+        }
 
         //finalize the command buffer (we can no longer add commands, but it can now be executed)
         unsafe { self.device.end_command_buffer(cmd).unwrap() };
@@ -458,7 +870,12 @@ impl<'a> VulkanEngine<'a> {
             self.get_current_frame().render_semaphore,
         );
 
-        let submit = vk_init::submit_info(&cmd_info, Some(&signal_info), Some(&wait_info));
+        //headless frames neither wait on an acquire nor feed a present
+        let submit = if self.is_headless() {
+            vk_init::submit_info(&cmd_info, None, None)
+        } else {
+            vk_init::submit_info(&cmd_info, Some(&signal_info), Some(&wait_info))
+        };
 
         //submit command buffer to the queue and execute it.
         // render_fence will now block until the graphic commands finish execution
@@ -472,6 +889,11 @@ impl<'a> VulkanEngine<'a> {
                 .unwrap()
         }
 
+        if self.is_headless() {
+            self.frame_number += 1;
+            return;
+        }
+
         //prepare present
         // this will put the image we just rendered to into the visible window.
         // we want to wait on the render_semaphore for that,
@@ -481,11 +903,18 @@ impl<'a> VulkanEngine<'a> {
             .wait_semaphores(slice::from_ref(&self.get_current_frame().render_semaphore))
             .image_indices(slice::from_ref(&swapchain_image_index))
             .build();
-        unsafe {
+        let present_result = unsafe {
             self.swapchain_loader
                 .queue_present(self.graphics_queue, &present_info)
-                .unwrap()
         };
+        match present_result {
+            Ok(false) => {}
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.resize_requested = true,
+            Err(e) => panic!("Failed to present swapchain image: {}", e),
+        }
+        if let Some((path, readback)) = screenshot {
+            self.finish_screenshot(path, readback);
+        }
         self.frame_number += 1;
     }
 }
@@ -502,31 +931,40 @@ impl<'a> Drop for VulkanEngine<'a> {
             for mesh in &self.test_meshes {
                 mesh.borrow_mut().mesh_buffers.dealloc(&self.device, &mut self.allocator);
             }
+            for scene in self.loaded_scenes.values() {
+                unsafe { scene.borrow_mut().dealloc(&self.device, &mut self.allocator) };
+            }
             self.renderer.take();
 
             self.destroy_immediate_handles();
 
-            self.destroy_effects();
-            self.destroy_graphics();
-
-            //destroy descriptor sets and their layouts
-            self.destroy_descriptor_sets();
-
-            /*for allocated_image in self.allocated_images.iter_mut() {
-                unsafe {allocated_image.dealloc(&self.device, &mut self.allocator)}
-            }*/
-
+            //frame resources go first, they can depend on the engine-wide ones
             self.destroy_frame_data();
+            unsafe {
+                self.main_deletion_queue
+                    .flush(&self.device, &mut self.allocator)
+            };
+            self.material_descriptor_allocator.destroy_pools(&self.device);
+            self.destroy_default_images();
+            unsafe { self.sampler_cache.destroy(&self.device) };
+            unsafe {
+                self.texture_cache
+                    .dealloc(&self.device, &mut self.allocator)
+            };
 
             self.destroy_swapchain();
+            self.destroy_draw_images();
 
             //final cleanup
             unsafe {
-                self.surface_loader.destroy_surface(self.surface, None);
+                if !self.is_headless() {
+                    self.surface_loader.destroy_surface(self.surface, None);
+                }
                 self.device.destroy_device(None);
-                #[cfg(debug_assertions)]
-                self.debug_utils_loader
-                    .destroy_debug_utils_messenger(self.debug_messenger, None);
+                if self.debug_messenger != vk::DebugUtilsMessengerEXT::null() {
+                    self.debug_utils_loader
+                        .destroy_debug_utils_messenger(self.debug_messenger, None);
+                }
                 self.instance.destroy_instance(None);
             };
         }
@@ -579,15 +1017,105 @@ impl<'a> VulkanEngine<'a> {
         unsafe { self.device.cmd_begin_rendering(cmd, &render_info) };
 
         self.renderer
-            .get_mut()
+            .as_mut()
             .unwrap()
-            .cmd_draw(cmd, self.imgui_context.render())
+            .cmd_draw(cmd, self.imgui_context.as_mut().unwrap().render())
             .unwrap();
 
         unsafe { self.device.cmd_end_rendering(cmd) };
     }
 
-    fn draw_geometry(&self, device: &Device, cmd: vk::CommandBuffer) {
+    //the buffer is only used by the current frame and destroyed once that frame is done
+    fn upload_scene_data(&mut self) -> EngineResult<vk::DescriptorSet> {
+        let mut gpu_scene_data_buffer = create_buffer(
+            &self.device,
+            &mut self.allocator,
+            size_of::<GPUSceneData>() as vk::DeviceSize,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        presser::copy_from_slice_to_offset(
+            slice::from_ref(&self.scene_data),
+            gpu_scene_data_buffer.allocation.get_mut().unwrap(),
+            0,
+        )
+        .unwrap();
+        let buffer = gpu_scene_data_buffer.buffer;
+        let frame_index = self.get_current_frame_index();
+        let frame = &mut self.frames[frame_index];
+        frame.deletion_queue.push(gpu_scene_data_buffer);
+
+        //create a descriptor set that binds that buffer and update it
+        let global_descriptor = frame
+            .frame_descriptors
+            .allocate(&self.device, self.gpu_scene_data_descriptor_layout)?;
+        let mut writer = DescriptorWriter::default();
+        writer.write_buffer(
+            0,
+            buffer,
+            size_of::<GPUSceneData>() as vk::DeviceSize,
+            0,
+            vk::DescriptorType::UNIFORM_BUFFER,
+        );
+        writer.update_set(&self.device, global_descriptor);
+        Ok(global_descriptor)
+    }
+
+    //weights of every render object in draw order, in a buffer only the current frame uses.
+    //None when nothing has morph targets to blend
+    fn upload_morph_weights(&mut self) -> EngineResult<Option<vk::DeviceAddress>> {
+        let morph_weights = self
+            .main_draw_context
+            .opaque_surfaces
+            .iter()
+            .chain(self.main_draw_context.transparent_surfaces.iter())
+            .flat_map(|render_object| render_object.morph_weights.iter().copied())
+            .collect::<Vec<f32>>();
+        if morph_weights.is_empty() {
+            return Ok(None);
+        }
+        let mut morph_weights_buffer = create_buffer(
+            &self.device,
+            &mut self.allocator,
+            size_of_val(morph_weights.as_slice()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        presser::copy_from_slice_to_offset(&morph_weights, morph_weights_buffer.allocation.get_mut().unwrap(), 0).unwrap();
+        let morph_weights_address = buffer_device_address(&self.device, &morph_weights_buffer);
+        let frame_index = self.get_current_frame_index();
+        self.frames[frame_index].deletion_queue.push(morph_weights_buffer);
+        Ok(Some(morph_weights_address))
+    }
+
+    fn draw_geometry(&mut self, cmd: vk::CommandBuffer) {
+        let scene_update_start = Instant::now();
+        self.update_scene();
+        //skinning still runs for culled objects, their nodes may be drawn again next frame
+        self.stats.culled_objects = if self.frustum_culling {
+            self.main_draw_context.cull(&Frustum::from_matrix(&self.scene_data.viewproj))
+        } else {
+            0
+        };
+        self.main_draw_context.sort_transparent(&self.scene_data.view);
+        self.stats.scene_update_time = scene_update_start.elapsed();
+        if let Err(e) = self.record_skinning(cmd) {
+            log::error!("Skinned meshes are not deformed this frame: {}", e);
+        }
+
+        let global_descriptor = match self.upload_scene_data() {
+            Ok(global_descriptor) => global_descriptor,
+            Err(e) => {
+                log::error!("Skipping geometry, the scene data could not be uploaded: {}", e);
+                return;
+            }
+        };
+        let morph_weights_address = self.upload_morph_weights().unwrap_or_else(|e| {
+            log::error!("Morph targets are not blended this frame: {}", e);
+            None
+        });
+
+        let device = &self.device;
         // create necessary drawing info
         let color_attachment =
             vk_init::attachment_info(self.draw_image.image_view, None, vk::ImageLayout::GENERAL);
@@ -615,30 +1143,59 @@ impl<'a> VulkanEngine<'a> {
 
         unsafe { device.cmd_set_scissor(cmd, 0, slice::from_ref(&scissor)) };
 
-        //Set up pipeline to render meshes
-        unsafe {device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, self.mesh_pipeline)};
-
-        //world matrix needs to be upside down for gltf meshes since Vulkan uses opposite Y to OpenGL
-        //set the monkey 5 units back (using left-handed coordinates it seems)
-        let view = cgmath::Matrix4::from_translation((0f32, 0f32, -5f32).into());
-        let mut projection = cgmath::perspective(Deg {0: 70f32}, (self.draw_extent.width as f32) / (self.draw_extent.height as f32), 10000f32, 0.1f32);
-
-        // https://matthewwellings.com/blog/the-new-vulkan-coordinate-system/
-        // https://github.com/LunarG/VulkanSamples/commit/0dd36179880238014512c0637b0ba9f41febe803
-        // invert y-axis to use a left-handed system like OpenGL and change NDC to [0,1] which is what Vulkan uses
-        let clip = cgmath::Matrix4::new(1f32, 0f32, 0f32,0f32 ,
-                                                    0f32, -1f32, 0f32 ,0f32,
-                                                    0f32, 0f32 ,0.5f32, 0f32,
-                                                    0f32, 0f32, 0.5f32, 1f32);
+        //opaque surfaces first so the transparent ones blend over them
+        let mut morph_weights_offset = 0;
+        self.stats.drawn_objects = 0;
+        self.stats.triangle_count = 0;
+        for render_object in self
+            .main_draw_context
+            .opaque_surfaces
+            .iter()
+            .chain(self.main_draw_context.transparent_surfaces.iter())
+        {
+            //weights are laid out in the same order upload_morph_weights saw the objects in
+            let morph_weights = morph_weights_address
+                .filter(|_| !render_object.morph_weights.is_empty())
+                .map(|address| address + (morph_weights_offset * size_of::<f32>()) as vk::DeviceAddress);
+            morph_weights_offset += render_object.morph_weights.len();
+            self.draw_render_object(cmd, render_object, global_descriptor, morph_weights);
+            self.stats.drawn_objects += 1;
+            self.stats.triangle_count += render_object.index_count as usize / 3;
+        }
 
-        projection = clip * projection;
+        unsafe { device.cmd_end_rendering(cmd) };
+    }
 
-        //draw a blender monkeyhead
-        let monkey_mesh = self.test_meshes[2].borrow();
+    fn draw_render_object(
+        &self,
+        cmd: vk::CommandBuffer,
+        render_object: &RenderObject,
+        global_descriptor: vk::DescriptorSet,
+        morph_weights: Option<vk::DeviceAddress>,
+    ) {
+        let device = &self.device;
+        let material = render_object.material.as_ref().unwrap_or(&self.default_material);
+        let pipeline = material.data.pipeline;
+        unsafe { device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::GRAPHICS, pipeline.pipeline) };
+        //set 0 is the scene data, set 1 the material
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                cmd,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline.layout,
+                0,
+                &[global_descriptor, material.data.material_set],
+                &[],
+            )
+        };
 
         let push_constants = GPUDrawPushConstants {
-            world_matrix: projection * view,
-            vertex_buffer: monkey_mesh.mesh_buffers.vertex_buffer_address
+            world_matrix: render_object.transform,
+            vertex_buffer: render_object.vertex_buffer_address,
+            morph_targets: render_object.morph_targets_address,
+            morph_weights: morph_weights.unwrap_or(0),
+            morph_target_count: morph_weights.map_or(0, |_| render_object.morph_weights.len() as u32),
+            vertex_count: render_object.vertex_count,
         };
 
         let push_bytes = unsafe {slice::from_raw_parts(
@@ -646,11 +1203,26 @@ impl<'a> VulkanEngine<'a> {
             size_of::<GPUDrawPushConstants>(),
         )};
 
-        unsafe {device.cmd_push_constants(cmd, self.mesh_pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, push_bytes)};
-        unsafe {device.cmd_bind_index_buffer(cmd, monkey_mesh.mesh_buffers.index_buffer.buffer, 0, vk::IndexType::UINT32)};
+        unsafe {device.cmd_push_constants(cmd, pipeline.layout, vk::ShaderStageFlags::VERTEX, 0, push_bytes)};
+        unsafe {device.cmd_bind_index_buffer(cmd, render_object.index_buffer, 0, vk::IndexType::UINT32)};
 
-        unsafe {device.cmd_draw_indexed(cmd, monkey_mesh.surfaces[0].count, 1, monkey_mesh.surfaces[0].start_index, 0, 0)};
+        unsafe {device.cmd_draw_indexed(cmd, render_object.index_count, 1, render_object.first_index, 0, 0)};
+    }
+}
 
-        unsafe { device.cmd_end_rendering(cmd) };
+//the draw image is sized for the whole display so resizing the window never needs new render targets
+fn max_draw_extent(window: &sdl2::video::Window, window_extent: vk::Extent2D) -> vk::Extent2D {
+    let display_mode = window
+        .display_index()
+        .and_then(|display_index| window.subsystem().desktop_display_mode(display_index));
+    match display_mode {
+        Ok(display_mode) => vk::Extent2D {
+            width: window_extent.width.max(display_mode.w as u32),
+            height: window_extent.height.max(display_mode.h as u32),
+        },
+        Err(e) => {
+            log::warn!("Could not query the display size, sizing the draw image to the window: {}", e);
+            window_extent
+        }
     }
 }
@@ -0,0 +1,105 @@
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::EngineResult;
+use crate::vk_loader::LoadedGLTF;
+use crate::vk_skinning::{skinning_barrier, SkinningPushConstants};
+use crate::vk_types::buffers::{buffer_device_address, create_buffer};
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem::{size_of, size_of_val};
+use std::rc::Rc;
+
+impl<'a> VulkanEngine<'a> {
+    //deforms every skinned mesh of the loaded scenes into this frame's output vertices, must be
+    //recorded before the geometry that draws them
+    pub(crate) fn record_skinning(&mut self, cmd: vk::CommandBuffer) -> EngineResult<()> {
+        let frame_index = self.get_current_frame_index();
+        //the joint matrices and morph weights of all skinned meshes go into one buffer, the push
+        //constants hold offsets into it until it exists
+        let mut joint_matrices = Vec::<cgmath::Matrix4<f32>>::new();
+        let mut morph_weights = Vec::<f32>::new();
+        let mut dispatches = Vec::<SkinningPushConstants>::new();
+        for scene in self.loaded_scenes.values() {
+            let scene = scene.borrow();
+            for skinned_mesh in scene.skinned_meshes.iter() {
+                let Some(skin_vertices) = scene.skin_vertices_address(skinned_mesh.mesh) else {
+                    continue;
+                };
+                let joint_offset = joint_matrices.len();
+                let node = scene.nodes[skinned_mesh.node].borrow();
+                joint_matrices.extend(scene.skins[skinned_mesh.skin].joint_matrices(&scene.nodes, &node.world_transform));
+                let mesh_buffers = &scene.meshes[skinned_mesh.mesh].borrow().mesh_buffers;
+                let morph_weights_offset = morph_weights.len();
+                let morph_target_count = node.morph_weights.len().min(mesh_buffers.morph_target_count as usize);
+                morph_weights.extend_from_slice(&node.morph_weights[..morph_target_count]);
+                dispatches.push(SkinningPushConstants {
+                    source_vertices: mesh_buffers.vertex_buffer_address,
+                    skin_vertices,
+                    joint_matrices: (joint_offset * size_of::<cgmath::Matrix4<f32>>()) as vk::DeviceAddress,
+                    output_vertices: skinned_mesh.output_address(frame_index),
+                    morph_targets: mesh_buffers.morph_targets_address,
+                    morph_weights: (morph_weights_offset * size_of::<f32>()) as vk::DeviceAddress,
+                    vertex_count: skinned_mesh.vertex_count,
+                    morph_target_count: morph_target_count as u32,
+                });
+            }
+        }
+        if dispatches.is_empty() {
+            return Ok(());
+        }
+
+        let joint_matrices_size = size_of_val(joint_matrices.as_slice());
+        let mut skinning_buffer = create_buffer(
+            &self.device,
+            &mut self.allocator,
+            (joint_matrices_size + size_of_val(morph_weights.as_slice())).max(1) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        )?;
+        let allocation = skinning_buffer.allocation.get_mut().unwrap();
+        presser::copy_from_slice_to_offset(&joint_matrices, allocation, 0).unwrap();
+        presser::copy_from_slice_to_offset(&morph_weights, allocation, joint_matrices_size).unwrap();
+        let skinning_buffer_address = buffer_device_address(&self.device, &skinning_buffer);
+        //only needed until the frame is done
+        self.frames[frame_index].deletion_queue.push(skinning_buffer);
+
+        for dispatch in dispatches.iter_mut() {
+            dispatch.joint_matrices += skinning_buffer_address;
+            dispatch.morph_weights += skinning_buffer_address + joint_matrices_size as vk::DeviceAddress;
+            self.skinning_pipeline.dispatch(&self.device, cmd, dispatch);
+        }
+        skinning_barrier(&self.device, cmd);
+        Ok(())
+    }
+}
+
+//per file clip selection and playback controls
+pub(crate) fn animation_ui(ui: &imgui::Ui, loaded_scenes: &HashMap<String, Rc<RefCell<LoadedGLTF>>>) {
+    for (name, scene) in loaded_scenes.iter() {
+        let mut scene = scene.borrow_mut();
+        if scene.animations.is_empty() || !ui.collapsing_header(name, imgui::TreeNodeFlags::empty()) {
+            continue;
+        }
+        let _id = ui.push_id(name);
+        let clip_names = scene
+            .animations
+            .iter()
+            .map(|animation| animation.name.clone())
+            .collect::<Vec<String>>();
+        let mut clip = scene.animation_player.clip.unwrap_or(0);
+        if ui.combo_simple_string("Clip", &mut clip, &clip_names) {
+            scene.animation_player.clip = Some(clip);
+            scene.animation_player.time = 0f32;
+        }
+        let duration = scene.animations[clip].duration;
+        let player = &mut scene.animation_player;
+        ui.checkbox("Playing", &mut player.playing);
+        ui.same_line();
+        ui.checkbox("Loop", &mut player.looping);
+        ui.slider("Speed", -2f32, 2f32, &mut player.speed);
+        ui.slider("Time", 0f32, duration, &mut player.time);
+        if ui.button("Rest pose") {
+            player.clip = None;
+        }
+    }
+}
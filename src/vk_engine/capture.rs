@@ -0,0 +1,284 @@
+use crate::immediate_submit;
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_images::{self, ImageState};
+use crate::vk_types::buffers::{create_buffer, destroy_buffer, AllocatedBuffer};
+use ash::{vk, Device};
+use half::f16;
+use std::path::{Path, PathBuf};
+use std::slice;
+
+//which image of the last frame gets read back
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CaptureSource {
+    //the HDR draw image, before it is blitted to the final image and before the UI goes on top
+    DrawImage,
+    //the swapchain-format image the frame ends up in. Only readable this way when running
+    //headless, windowed engines go through request_screenshot instead
+    FinalImage,
+}
+
+//host copy of a rendered image, rows are tightly packed in the image's own format
+pub struct CapturedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: vk::Format,
+    pub data: Vec<u8>,
+}
+
+impl CapturedImage {
+    //8 bit sRGB encoded RGBA. Float colors are Reinhard tonemapped first so HDR values above 1
+    //keep some detail, alpha is only clamped. sRGB images keep their encoded values, which is what
+    //PNG files store too
+    pub fn to_rgba8(&self) -> EngineResult<Vec<u8>> {
+        Ok(match self.format {
+            vk::Format::R16G16B16A16_SFLOAT | vk::Format::R32G32B32A32_SFLOAT => self
+                .to_rgba_f32()?
+                .chunks_exact(4)
+                .flat_map(|rgba| {
+                    [
+                        linear_to_srgb(reinhard(rgba[0])),
+                        linear_to_srgb(reinhard(rgba[1])),
+                        linear_to_srgb(reinhard(rgba[2])),
+                        (rgba[3].clamp(0f32, 1f32) * 255f32).round() as u8,
+                    ]
+                })
+                .collect(),
+            vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => self
+                .data
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect(),
+            vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => self.data.clone(),
+            format => return Err(EngineError::UnsupportedCaptureFormat(format)),
+        })
+    }
+
+    //linear values, sRGB images are decoded
+    pub fn to_rgba_f32(&self) -> EngineResult<Vec<f32>> {
+        Ok(match self.format {
+            vk::Format::R16G16B16A16_SFLOAT => self
+                .data
+                .chunks_exact(2)
+                .map(|half| f16::from_le_bytes([half[0], half[1]]).to_f32())
+                .collect(),
+            vk::Format::R32G32B32A32_SFLOAT => self
+                .data
+                .chunks_exact(4)
+                .map(|float| f32::from_le_bytes([float[0], float[1], float[2], float[3]]))
+                .collect(),
+            vk::Format::B8G8R8A8_SRGB | vk::Format::R8G8B8A8_SRGB => self
+                .to_rgba8()?
+                .chunks_exact(4)
+                .flat_map(|rgba| {
+                    [
+                        srgb_to_linear(rgba[0]),
+                        srgb_to_linear(rgba[1]),
+                        srgb_to_linear(rgba[2]),
+                        rgba[3] as f32 / 255f32,
+                    ]
+                })
+                .collect(),
+            _ => self
+                .to_rgba8()?
+                .iter()
+                .map(|&channel| channel as f32 / 255f32)
+                .collect(),
+        })
+    }
+
+    //the file format is picked from the extension: exr keeps the float values, raw dumps the
+    //bytes as they were read back (half floats for the draw image) and anything else is 8 bit png
+    pub fn save(&self, path: impl AsRef<Path>) -> EngineResult<()> {
+        let path = path.as_ref();
+        let capture_file = |source: image::ImageError| EngineError::CaptureFile {
+            path: path.to_path_buf(),
+            source,
+        };
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("exr") => image::Rgba32FImage::from_raw(self.width, self.height, self.to_rgba_f32()?)
+                .unwrap()
+                .save(path)
+                .map_err(capture_file),
+            Some("raw") => std::fs::write(path, &self.data).map_err(|e| capture_file(image::ImageError::IoError(e))),
+            _ => image::RgbaImage::from_raw(self.width, self.height, self.to_rgba8()?)
+                .unwrap()
+                .save_with_format(path, image::ImageFormat::Png)
+                .map_err(capture_file),
+        }
+    }
+}
+
+fn reinhard(channel: f32) -> f32 {
+    let channel = channel.max(0f32);
+    channel / (1f32 + channel)
+}
+
+fn linear_to_srgb(channel: f32) -> u8 {
+    let channel = channel.clamp(0f32, 1f32);
+    let encoded = if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1f32 / 2.4) - 0.055
+    };
+    (encoded * 255f32).round() as u8
+}
+
+fn srgb_to_linear(channel: u8) -> f32 {
+    let channel = channel as f32 / 255f32;
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+//fails for every format CapturedImage can not convert, before anything is read back
+pub fn bytes_per_pixel(format: vk::Format) -> EngineResult<usize> {
+    match format {
+        vk::Format::R32G32B32A32_SFLOAT => Ok(16),
+        vk::Format::R16G16B16A16_SFLOAT => Ok(8),
+        vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB => Ok(4),
+        format => Err(EngineError::UnsupportedCaptureFormat(format)),
+    }
+}
+
+fn create_readback_buffer(
+    device: &Device,
+    allocator: &mut gpu_allocator::vulkan::Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+) -> EngineResult<AllocatedBuffer> {
+    let size = extent.width as usize * extent.height as usize * bytes_per_pixel(format)?;
+    create_buffer(
+        device,
+        allocator,
+        size as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_DST,
+        gpu_allocator::MemoryLocation::GpuToCpu,
+    )
+}
+
+fn read_back(buffer: &AllocatedBuffer, extent: vk::Extent2D, format: vk::Format) -> EngineResult<CapturedImage> {
+    let size = extent.width as usize * extent.height as usize * bytes_per_pixel(format)?;
+    let mapped = buffer.allocation.get().unwrap().mapped_slice().unwrap();
+    Ok(CapturedImage {
+        width: extent.width,
+        height: extent.height,
+        format,
+        data: mapped[..size].to_vec(),
+    })
+}
+
+impl<'a> VulkanEngine<'a> {
+    //reads back the last drawn frame. Fails if nothing has been drawn yet, if the requested image
+    //can not be read outside of draw or if its format can not be captured
+    pub fn capture(&mut self, source: CaptureSource) -> EngineResult<CapturedImage> {
+        if self.frame_number == 0 {
+            return Err(EngineError::NothingToCapture("no frame has been drawn yet"));
+        }
+        let (image, extent, format) = match source {
+            CaptureSource::DrawImage => (
+                self.draw_image.image,
+                self.draw_extent,
+                self.draw_image.image_format,
+            ),
+            CaptureSource::FinalImage => match &self.offscreen_image {
+                Some(offscreen_image) => (
+                    offscreen_image.image,
+                    self.swapchain_extent,
+                    offscreen_image.image_format,
+                ),
+                None => {
+                    return Err(EngineError::NothingToCapture(
+                        "the final image has been presented already, use request_screenshot",
+                    ))
+                }
+            },
+        };
+        bytes_per_pixel(format)?;
+
+        //the frame might still be in flight
+        unsafe { self.device.device_wait_idle()? };
+
+        let mut readback = create_readback_buffer(&self.device, &mut self.allocator, extent, format)?;
+        let device = &self.device;
+        let immediate_command_buffer = self.immediate_command_buffer;
+        let immediate_fence = self.immediate_fence;
+        let immediate_queue = self.graphics_queue;
+        let readback_buffer = readback.buffer;
+        let image_states = &mut self.image_states;
+        let mut readback_helper = |cmd: vk::CommandBuffer| {
+            image_states.transition(device, cmd, image, ImageState::TRANSFER_SRC);
+            vk_images::copy_image_to_buffer(device, cmd, image, readback_buffer, extent);
+        };
+        immediate_submit!(
+            device,
+            immediate_command_buffer,
+            immediate_fence,
+            immediate_queue,
+            readback_helper,
+            immediate_command_buffer
+        );
+        let captured = read_back(&readback, extent, format);
+        destroy_buffer(&self.device, &mut self.allocator, &mut readback);
+        captured
+    }
+
+    //the next drawn frame, UI included, gets written to path right after it is submitted
+    pub fn request_screenshot(&mut self, path: impl Into<PathBuf>) {
+        self.screenshot_request = Some(path.into());
+    }
+
+    //records the copy of a swapchain image holding the finished frame into a readback buffer.
+    //the image is left in TRANSFER_SRC_OPTIMAL, or untouched when its format can not be captured
+    pub fn record_screenshot(
+        &mut self,
+        cmd: vk::CommandBuffer,
+        image: vk::Image,
+    ) -> EngineResult<AllocatedBuffer> {
+        let readback = create_readback_buffer(
+            &self.device,
+            &mut self.allocator,
+            self.swapchain_extent,
+            self.swapchain_image_format.format,
+        )?;
+        self.image_states
+            .transition(&self.device, cmd, image, ImageState::TRANSFER_SRC);
+        vk_images::copy_image_to_buffer(
+            &self.device,
+            cmd,
+            image,
+            readback.buffer,
+            self.swapchain_extent,
+        );
+        Ok(readback)
+    }
+
+    //waits for the frame that recorded the screenshot and writes it to disk
+    pub fn finish_screenshot(&mut self, path: PathBuf, mut readback: AllocatedBuffer) {
+        unsafe {
+            self.device
+                .wait_for_fences(
+                    slice::from_ref(&self.get_current_frame().render_fence),
+                    true,
+                    1000000000,
+                )
+                .unwrap()
+        };
+        let saved = read_back(
+            &readback,
+            self.swapchain_extent,
+            self.swapchain_image_format.format,
+        )
+        .and_then(|captured| captured.save(&path));
+        match saved {
+            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+            Err(e) => log::error!("Failed to save screenshot to {}: {}", path.display(), e),
+        }
+        destroy_buffer(&self.device, &mut self.allocator, &mut readback);
+    }
+}
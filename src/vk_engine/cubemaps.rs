@@ -0,0 +1,237 @@
+use crate::immediate_submit;
+use crate::vk_bootstrap;
+use crate::vk_cubemaps::{hdr_pixel_bytes, CUBE_FACES, HDR_CUBEMAP_FORMAT};
+use crate::vk_descriptors::{DescriptorAllocator, DescriptorWriter, PoolSizeRatio};
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_images::{self, ImageState};
+use crate::vk_init;
+use crate::vk_types::sampler_cache::SamplerDesc;
+use crate::vk_types::{AllocatedImage, ImageDesc};
+use ash::vk;
+use std::path::Path;
+use stb_image::image::LoadResult;
+
+fn read_image_file(path: &Path) -> EngineResult<LoadResult> {
+    let encoded = std::fs::read(path).map_err(|source| EngineError::ImageFile {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    //HDR files are kept as floats
+    Ok(stb_image::image::load_from_memory_with_depth(&encoded, 4, false))
+}
+
+fn invalid_image(path: &Path, reason: impl Into<String>) -> EngineError {
+    EngineError::InvalidImage {
+        name: path.display().to_string(),
+        reason: reason.into(),
+    }
+}
+
+impl<'a> VulkanEngine<'a> {
+    //builds an RGBA8 cubemap from six square images of the same size, in CUBE_FACES order. The
+    //caller owns the cubemap, push it into main_deletion_queue to have it freed with the engine
+    pub fn load_cubemap_files(&mut self, faces: [&Path; 6]) -> EngineResult<AllocatedImage> {
+        let mut data = Vec::new();
+        let mut face_size = 0;
+        for (face, path) in CUBE_FACES.iter().zip(faces) {
+            let image = match read_image_file(path)? {
+                LoadResult::ImageU8(image) => image,
+                LoadResult::ImageF32(_) => {
+                    return Err(invalid_image(path, "HDR faces are not supported, use load_hdr_cubemap"))
+                }
+                LoadResult::Error(reason) => return Err(invalid_image(path, reason)),
+            };
+            if face_size == 0 {
+                face_size = image.width;
+            }
+            if image.width != image.height || image.width != face_size {
+                return Err(invalid_image(
+                    path,
+                    format!("the {} face is {}x{}, faces have to be {}x{}", face, image.width, image.height, face_size, face_size),
+                ));
+            }
+            data.extend_from_slice(&image.data);
+        }
+
+        let image_desc = ImageDesc {
+            array_layers: CUBE_FACES.len() as u32,
+            view_type: vk::ImageViewType::CUBE,
+            ..ImageDesc::new(
+                vk::Format::R8G8B8A8_UNORM,
+                vk::ImageUsageFlags::empty(),
+                vk::Extent3D {
+                    width: face_size as u32,
+                    height: face_size as u32,
+                    depth: 1,
+                },
+            )
+        };
+        vk_bootstrap::upload_image(
+            &self.device,
+            &mut self.allocator,
+            self.immediate_command_buffer,
+            self.immediate_fence,
+            self.graphics_queue,
+            &faces[0].display().to_string(),
+            &data,
+            image_desc,
+            None,
+        )
+    }
+
+    //projects an equirectangular panorama, usually an .hdr file, onto an HDR_CUBEMAP_FORMAT cubemap
+    //with faces of face_size texels. The caller owns the cubemap, like with load_cubemap_files
+    pub fn load_hdr_cubemap(&mut self, file_path: impl AsRef<Path>, face_size: u32) -> EngineResult<AllocatedImage> {
+        let path = file_path.as_ref();
+        if face_size == 0 {
+            return Err(invalid_image(path, "cubemap faces can not be empty"));
+        }
+        let (width, height, pixels) = match read_image_file(path)? {
+            LoadResult::ImageF32(image) => (image.width, image.height, image.data),
+            //LDR panoramas work too, they just stay within 0 to 1
+            LoadResult::ImageU8(image) => (
+                image.width,
+                image.height,
+                image.data.iter().map(|&value| value as f32 / 255f32).collect(),
+            ),
+            LoadResult::Error(reason) => return Err(invalid_image(path, reason)),
+        };
+        let name = path.display().to_string();
+        let sampler = self.get_sampler(&SamplerDesc {
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..SamplerDesc::linear()
+        })?;
+        let mut equirect = vk_bootstrap::upload_image(
+            &self.device,
+            &mut self.allocator,
+            self.immediate_command_buffer,
+            self.immediate_fence,
+            self.graphics_queue,
+            &name,
+            &hdr_pixel_bytes(&pixels),
+            ImageDesc::new(
+                HDR_CUBEMAP_FORMAT,
+                vk::ImageUsageFlags::empty(),
+                vk::Extent3D {
+                    width: width as u32,
+                    height: height as u32,
+                    depth: 1,
+                },
+            ),
+            None,
+        )?;
+        let cube_desc = ImageDesc {
+            array_layers: CUBE_FACES.len() as u32,
+            view_type: vk::ImageViewType::CUBE,
+            ..ImageDesc::new(
+                HDR_CUBEMAP_FORMAT,
+                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE,
+                vk::Extent3D {
+                    width: face_size,
+                    height: face_size,
+                    depth: 1,
+                },
+            )
+        };
+        let cube = AllocatedImage::new(&self.device, &mut self.allocator, &name, &cube_desc);
+        let result = cube.and_then(|mut cube| match self.project_equirect(&equirect, sampler, &cube) {
+            Ok(()) => Ok(cube),
+            Err(e) => {
+                unsafe { cube.dealloc(&self.device, &mut self.allocator) };
+                Err(e)
+            }
+        });
+        unsafe { equirect.dealloc(&self.device, &mut self.allocator) };
+        result
+    }
+
+    //runs equirect_to_cube.comp and waits for it, the cubemap is left in SHADER_READ_ONLY_OPTIMAL
+    fn project_equirect(&self, equirect: &AllocatedImage, sampler: vk::Sampler, cube: &AllocatedImage) -> EngineResult<()> {
+        //storage images can not be cube views
+        let layers_view_info = vk_init::image_view_create_info(
+            cube.image_format,
+            cube.image,
+            vk::ImageAspectFlags::COLOR,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            1,
+            cube.array_layers,
+        );
+        let layers_view = unsafe { self.device.create_image_view(&layers_view_info, None)? };
+        let mut descriptor_allocator = DescriptorAllocator::default();
+        let ratios = [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ratio: 1f32,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 1f32,
+            },
+        ];
+        let descriptor_set = descriptor_allocator
+            .init_pool(&self.device, 1, &ratios)
+            .and_then(|()| descriptor_allocator.allocate(&self.device, self.equirect_to_cube_pipeline.descriptor_layout));
+        let descriptor_set = match descriptor_set {
+            Ok(descriptor_set) => descriptor_set,
+            Err(e) => {
+                descriptor_allocator.destroy_pool(&self.device);
+                unsafe { self.device.destroy_image_view(layers_view, None) };
+                return Err(e);
+            }
+        };
+        let mut writer = DescriptorWriter::default();
+        writer.write_image(
+            0,
+            equirect.image_view,
+            sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        writer.write_image(
+            1,
+            layers_view,
+            vk::Sampler::null(),
+            vk::ImageLayout::GENERAL,
+            vk::DescriptorType::STORAGE_IMAGE,
+        );
+        writer.update_set(&self.device, descriptor_set);
+
+        let device = &self.device;
+        let immediate_command_buffer = self.immediate_command_buffer;
+        let immediate_fence = self.immediate_fence;
+        let immediate_queue = self.graphics_queue;
+        let pipeline = &self.equirect_to_cube_pipeline;
+        let face_size = cube.image_extent.width;
+        let projection_helper = |cmd: vk::CommandBuffer| {
+            vk_images::transition_image(
+                device,
+                cmd,
+                cube.image,
+                vk::ImageAspectFlags::COLOR,
+                ImageState::UNDEFINED,
+                ImageState::COMPUTE_WRITE,
+            );
+            pipeline.dispatch(device, cmd, descriptor_set, face_size);
+            vk_images::transition_image(
+                device,
+                cmd,
+                cube.image,
+                vk::ImageAspectFlags::COLOR,
+                ImageState::COMPUTE_WRITE,
+                ImageState::SHADER_READ,
+            );
+        };
+        immediate_submit!(
+            device,
+            immediate_command_buffer,
+            immediate_fence,
+            immediate_queue,
+            projection_helper,
+            immediate_command_buffer
+        );
+        descriptor_allocator.destroy_pool(&self.device);
+        unsafe { self.device.destroy_image_view(layers_view, None) };
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::vk_types::buffers::{destroy_buffer, AllocatedBuffer};
+use crate::vk_types::AllocatedImage;
+use ash::{vk, Device};
+
+//every kind of resource a deletion queue knows how to destroy
+pub enum DeletionEntry {
+    Buffer(AllocatedBuffer),
+    Image(AllocatedImage),
+    ImageView(vk::ImageView),
+    Pipeline(vk::Pipeline),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    DescriptorPool(vk::DescriptorPool),
+    Sampler(vk::Sampler),
+}
+
+macro_rules! deletion_entry_from {
+    ($resource:ty, $variant:ident) => {
+        impl From<$resource> for DeletionEntry {
+            fn from(resource: $resource) -> Self {
+                DeletionEntry::$variant(resource)
+            }
+        }
+    };
+}
+
+deletion_entry_from!(AllocatedBuffer, Buffer);
+deletion_entry_from!(AllocatedImage, Image);
+deletion_entry_from!(vk::ImageView, ImageView);
+deletion_entry_from!(vk::Pipeline, Pipeline);
+deletion_entry_from!(vk::PipelineLayout, PipelineLayout);
+deletion_entry_from!(vk::DescriptorSetLayout, DescriptorSetLayout);
+deletion_entry_from!(vk::DescriptorPool, DescriptorPool);
+deletion_entry_from!(vk::Sampler, Sampler);
+
+//resources are destroyed in the reverse order they were pushed in, so anything that depends on
+//another resource has to be pushed after it
+#[derive(Default)]
+pub struct DeletionQueue {
+    entries: Vec<DeletionEntry>,
+}
+
+impl DeletionQueue {
+    pub fn push(&mut self, entry: impl Into<DeletionEntry>) {
+        self.entries.push(entry.into());
+    }
+
+    /// # Safety
+    /// The GPU must be done with every resource in the queue
+    pub unsafe fn flush(
+        &mut self,
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+    ) {
+        for entry in self.entries.drain(..).rev() {
+            match entry {
+                DeletionEntry::Buffer(mut buffer) => destroy_buffer(device, allocator, &mut buffer),
+                DeletionEntry::Image(mut image) => image.dealloc(device, allocator),
+                DeletionEntry::ImageView(view) => device.destroy_image_view(view, None),
+                DeletionEntry::Pipeline(pipeline) => device.destroy_pipeline(pipeline, None),
+                DeletionEntry::PipelineLayout(layout) => {
+                    device.destroy_pipeline_layout(layout, None)
+                }
+                DeletionEntry::DescriptorSetLayout(layout) => {
+                    device.destroy_descriptor_set_layout(layout, None)
+                }
+                DeletionEntry::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+                DeletionEntry::Sampler(sampler) => device.destroy_sampler(sampler, None),
+            }
+        }
+    }
+}
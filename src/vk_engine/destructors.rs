@@ -1,54 +1,47 @@
 use crate::vk_engine::VulkanEngine;
 impl<'a> VulkanEngine<'a> {
     pub fn destroy_swapchain(&mut self) {
-        unsafe {
-            self.swapchain_loader
-                .destroy_swapchain(self.swapchain, None)
-        };
-        // destroy swapchain resources
-        for &view in self.swapchain_image_views.iter() {
-            unsafe { self.device.destroy_image_view(view, None) };
-        }
-        //deallocate the memory for the draw image associated to the swapchain
-        unsafe { self.draw_image.dealloc(&self.device, &mut self.allocator) };
-        //do the same for the depth image
-        unsafe { self.depth_image.dealloc(&self.device, &mut self.allocator) };
-    }
-
-    pub fn destroy_effects(&mut self) {
-        unsafe {
-            self.device
-                .destroy_pipeline_layout(self.background_effects[0].layout, None);
-
-            for effect in self.background_effects.iter() {
-                self.device.destroy_pipeline(effect.pipeline, None);
+        //headless engines own an offscreen image instead of a swapchain
+        if let Some(mut offscreen_image) = self.offscreen_image.take() {
+            self.image_states.forget(offscreen_image.image);
+            unsafe { offscreen_image.dealloc(&self.device, &mut self.allocator) };
+        } else {
+            for &image in self.swapchain_images.iter() {
+                self.image_states.forget(image);
+            }
+            unsafe {
+                self.swapchain_loader
+                    .destroy_swapchain(self.swapchain, None)
+            };
+            // destroy swapchain resources
+            for &view in self.swapchain_image_views.iter() {
+                unsafe { self.device.destroy_image_view(view, None) };
             }
         }
     }
 
-    pub fn destroy_graphics(&mut self) {
-        unsafe {
-            self.device
-                .destroy_pipeline_layout(self.mesh_pipeline_layout, None);
-            self.device.destroy_pipeline(self.mesh_pipeline, None);
-        }
+    pub fn destroy_draw_images(&mut self) {
+        self.image_states.forget(self.draw_image.image);
+        self.image_states.forget(self.depth_image.image);
+        //deallocate the memory for the draw image
+        unsafe { self.draw_image.dealloc(&self.device, &mut self.allocator) };
+        //do the same for the depth image
+        unsafe { self.depth_image.dealloc(&self.device, &mut self.allocator) };
     }
 
-    pub fn destroy_descriptor_sets(&mut self) {
-        unsafe {
-            self.device
-                .destroy_descriptor_set_layout(self.draw_image_descriptor_layout, None)
-        };
+    pub fn destroy_default_images(&mut self) {
         unsafe {
-            self.device
-                .destroy_descriptor_pool(self.global_descriptor_allocator.pool, None)
+            self.white_image.dealloc(&self.device, &mut self.allocator);
+            self.error_checkerboard_image
+                .dealloc(&self.device, &mut self.allocator);
         };
     }
 
     pub fn destroy_frame_data(&mut self) {
         for frame_data in self.frames.iter_mut() {
             unsafe {
-                frame_data.dealloc_last_frame();
+                frame_data.dealloc_last_frame(&self.device, &mut self.allocator);
+                frame_data.frame_descriptors.destroy_pools(&self.device);
                 self.device
                     .destroy_command_pool(frame_data.command_pool, None);
                 self.device.destroy_fence(frame_data.render_fence, None);
@@ -68,6 +61,5 @@ impl<'a> VulkanEngine<'a> {
             self.device
                 .destroy_command_pool(self.immediate_command_pool, None)
         };
-        unsafe { self.device.destroy_descriptor_pool(self.imgui_pool, None) };
     }
 }
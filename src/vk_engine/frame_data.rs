@@ -1,7 +1,10 @@
+use crate::vk_descriptors::DescriptorAllocatorGrowable;
+use crate::vk_engine::deletion_queue::DeletionQueue;
 use crate::vk_engine::VulkanEngine;
-use ash::vk;
+use ash::{vk, Device};
 use std::fmt::Formatter;
 
+//default number of frames in flight, see EngineConfig::frames_in_flight
 pub const FRAME_OVERLAP: usize = 2;
 #[derive(Default)]
 pub struct FrameData {
@@ -10,19 +13,36 @@ pub struct FrameData {
     pub swapchain_semaphore: vk::Semaphore,
     pub render_semaphore: vk::Semaphore,
     pub render_fence: vk::Fence,
+    //resources only used by this frame, destroyed once render_fence signals again
+    pub deletion_queue: DeletionQueue,
+    //descriptor sets only used by this frame, reset together with the deletion queue
+    pub frame_descriptors: DescriptorAllocatorGrowable,
 }
 
 impl<'a> VulkanEngine<'a> {
+    pub fn get_current_frame_index(&self) -> usize {
+        self.frame_number as usize % self.frames.len()
+    }
     pub fn get_current_frame(&self) -> &FrameData {
-        &self.frames[(self.frame_number % FRAME_OVERLAP as i32) as usize]
+        &self.frames[self.get_current_frame_index()]
     }
     pub fn get_current_frame_mut(&mut self) -> &mut FrameData {
-        &mut self.frames[(self.frame_number % FRAME_OVERLAP as i32) as usize]
+        let frame_index = self.get_current_frame_index();
+        &mut self.frames[frame_index]
     }
 }
 
 impl FrameData {
-    pub unsafe fn dealloc_last_frame(&mut self) {}
+    /// # Safety
+    /// Must only be called once render_fence has signaled
+    pub unsafe fn dealloc_last_frame(
+        &mut self,
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+    ) {
+        self.deletion_queue.flush(device, allocator);
+        self.frame_descriptors.clear_pools(device);
+    }
 }
 
 //Necessary to be able to collect vector in array, somehow
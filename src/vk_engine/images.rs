@@ -0,0 +1,78 @@
+use crate::vk_bootstrap;
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_types::sampler_cache::SamplerDesc;
+use crate::vk_types::texture_cache::TextureCache;
+use crate::vk_types::{AllocatedImage, ImageDesc};
+use ash::vk;
+use std::path::Path;
+use stb_image::image::LoadResult;
+
+impl<'a> VulkanEngine<'a> {
+    //creates a sampled RGBA8 image from tightly packed pixels, mipmapped images get a full mip chain
+    //generated on the GPU. The caller owns the image, push it into main_deletion_queue to have it
+    //freed with the engine
+    pub fn upload_image(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        extent: vk::Extent3D,
+        mipmapped: bool,
+    ) -> EngineResult<AllocatedImage> {
+        vk_bootstrap::upload_image(
+            &self.device,
+            &mut self.allocator,
+            self.immediate_command_buffer,
+            self.immediate_fence,
+            self.graphics_queue,
+            name,
+            data,
+            ImageDesc::new(vk::Format::R8G8B8A8_UNORM, vk::ImageUsageFlags::empty(), extent),
+            mipmapped.then_some(&self.mipmap_generator),
+        )
+    }
+
+    //decodes a PNG or JPG file. Images already loaded from the same bytes are not uploaded again
+    pub fn load_image_file(&mut self, file_path: impl AsRef<Path>) -> EngineResult<&AllocatedImage> {
+        let path = file_path.as_ref();
+        let encoded = std::fs::read(path).map_err(|source| EngineError::ImageFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.load_image_bytes(&path.display().to_string(), &encoded)
+    }
+
+    //same as load_image_file for images that are already in memory, like the ones embedded in a
+    //glTF file
+    pub fn load_image_bytes(&mut self, name: &str, encoded: &[u8]) -> EngineResult<&AllocatedImage> {
+        let key = TextureCache::key(encoded);
+        if self.texture_cache.get(key).is_none() {
+            let invalid = |reason: String| EngineError::InvalidImage {
+                name: String::from(name),
+                reason,
+            };
+            //always expand to RGBA so every texture can use the same format
+            let image = match stb_image::image::load_from_memory_with_depth(encoded, 4, false) {
+                LoadResult::ImageU8(image) => image,
+                LoadResult::ImageF32(_) => {
+                    return Err(invalid(String::from("HDR images are not supported")))
+                }
+                LoadResult::Error(reason) => return Err(invalid(reason)),
+            };
+            let extent = vk::Extent3D {
+                width: image.width as u32,
+                height: image.height as u32,
+                depth: 1,
+            };
+            let new_image = self.upload_image(name, &image.data, extent, true)?;
+            self.texture_cache.insert(key, new_image);
+        }
+        Ok(self.texture_cache.get(key).unwrap())
+    }
+
+    //sampler for the description, created the first time it is asked for. Samplers are shared
+    //and owned by the engine, never destroy them
+    pub fn get_sampler(&mut self, desc: &SamplerDesc) -> EngineResult<vk::Sampler> {
+        self.sampler_cache.get(&self.device, desc)
+    }
+}
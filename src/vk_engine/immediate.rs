@@ -1,4 +1,4 @@
-use crate::vk_engine;
+use crate::vk_error::{EngineError, EngineResult};
 use ash::vk::{DescriptorPoolSize, PhysicalDevice};
 use ash::{vk, Device, Instance};
 use gpu_allocator::vulkan::{Allocator, AllocatorCreateDesc};
@@ -6,33 +6,34 @@ use imgui_rs_vulkan_renderer::{DynamicRendering, Options, Renderer};
 use std::sync::{Arc, Mutex};
 
 //called on device. Is a macro because fuck Rust sometimes
+//every path is absolute so it expands the same inside and outside this crate
 #[macro_export]
 macro_rules! immediate_submit {
     ($device:ident, $immediate_command_buffer:ident, $immediate_fence:ident, $immediate_queue:ident, $callback:ident, $($callback_param:expr),*)=> {
-        use crate::vk_init;
-        unsafe {$device.reset_fences(slice::from_ref(& $immediate_fence)).unwrap()};
-        unsafe {$device.reset_command_buffer( $immediate_command_buffer, vk::CommandBufferResetFlags::empty()).unwrap()};
+        unsafe {$device.reset_fences(::std::slice::from_ref(& $immediate_fence)).unwrap()};
+        unsafe {$device.reset_command_buffer( $immediate_command_buffer, $crate::ash::vk::CommandBufferResetFlags::empty()).unwrap()};
 
-        let cmd_begin_info = vk_init::command_buffer_begin_info(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        let cmd_begin_info = $crate::vk_init::command_buffer_begin_info($crate::ash::vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
         unsafe {$device.begin_command_buffer( $immediate_command_buffer, & cmd_begin_info).unwrap()};
 
         $callback($($callback_param),*);
 
         unsafe {$device.end_command_buffer( $immediate_command_buffer).unwrap()};
 
-        let cmd_submit_info = vk_init::command_buffer_submit_info( $immediate_command_buffer);
-        let submit_info = vk_init::submit_info(& cmd_submit_info, None, None);
+        let cmd_submit_info = $crate::vk_init::command_buffer_submit_info( $immediate_command_buffer);
+        let submit_info = $crate::vk_init::submit_info(& cmd_submit_info, None, None);
 
         // submit command buffer to the queue and execute it
         // immediate_fence will now block until the GUI commands finish execution on the immediate queue
-        unsafe {$device.queue_submit2( $immediate_queue, slice::from_ref( & submit_info), $immediate_fence).unwrap()};
+        unsafe {$device.queue_submit2( $immediate_queue, ::std::slice::from_ref( & submit_info), $immediate_fence).unwrap()};
 
-        unsafe {$device.wait_for_fences(slice::from_ref(& $immediate_fence), true, 9999999999).unwrap()};
+        unsafe {$device.wait_for_fences(::std::slice::from_ref(& $immediate_fence), true, 9999999999).unwrap()};
 
     };
 }
 
 //WARNING: extremely oversized
+#[allow(clippy::too_many_arguments)]
 pub fn init_imgui(
     instance: &Instance,
     device: &Device,
@@ -40,13 +41,14 @@ pub fn init_imgui(
     queue: vk::Queue,
     command_pool: vk::CommandPool,
     swapchain_format: vk::Format,
+    in_flight_frames: usize,
     window: &sdl2::video::Window,
-) -> (
+) -> EngineResult<(
     imgui::Context,
     imgui_sdl2::ImguiSdl2,
     vk::DescriptorPool,
     imgui_rs_vulkan_renderer::Renderer,
-) {
+)> {
     let pool_sizes: [vk::DescriptorPoolSize; 11] = [
         DescriptorPoolSize {
             ty: vk::DescriptorType::SAMPLER,
@@ -100,7 +102,7 @@ pub fn init_imgui(
         .pool_sizes(&pool_sizes)
         .build();
 
-    let imgui_pool = unsafe { device.create_descriptor_pool(&pool_info, None).unwrap() };
+    let imgui_pool = unsafe { device.create_descriptor_pool(&pool_info, None)? };
 
     //Initialize imgui
     //Initialize core structures
@@ -117,8 +119,7 @@ pub fn init_imgui(
             debug_settings: Default::default(),
             buffer_device_address: true,
             allocation_sizes: Default::default(),
-        })
-        .unwrap();
+        })?;
 
         Renderer::with_gpu_allocator(
             Arc::new(Mutex::new(allocator)),
@@ -131,16 +132,16 @@ pub fn init_imgui(
             },
             &mut imgui_context,
             Some(Options {
-                in_flight_frames: vk_engine::frame_data::FRAME_OVERLAP,
+                in_flight_frames,
                 ..Default::default()
             }),
         )
-        .unwrap()
+        .map_err(|e| EngineError::Ui(e.to_string()))?
     };
 
     renderer
         .update_fonts_texture(queue, command_pool, &mut imgui_context)
-        .unwrap();
+        .map_err(|e| EngineError::Ui(e.to_string()))?;
 
-    (imgui_context, imgui_sdl2, imgui_pool, renderer)
+    Ok((imgui_context, imgui_sdl2, imgui_pool, renderer))
 }
@@ -0,0 +1,145 @@
+use crate::vk_engine::deletion_queue::DeletionQueue;
+use crate::vk_types::AllocatedImage;
+use ash::extensions::ext::DebugUtils;
+use ash::extensions::khr::{Surface, Swapchain};
+use ash::{vk, Device, Instance};
+use gpu_allocator::vulkan::Allocator;
+
+//handles the engine will own once init succeeds, the guard only destroys them when it does not
+pub enum InitHandle {
+    Image(vk::Image),
+    ImageView(vk::ImageView),
+    CommandPool(vk::CommandPool),
+    Fence(vk::Fence),
+    Semaphore(vk::Semaphore),
+    DescriptorPool(vk::DescriptorPool),
+    Sampler(vk::Sampler),
+}
+
+macro_rules! init_handle_from {
+    ($handle:ty, $variant:ident) => {
+        impl From<$handle> for InitHandle {
+            fn from(handle: $handle) -> Self {
+                InitHandle::$variant(handle)
+            }
+        }
+    };
+}
+
+init_handle_from!(vk::Image, Image);
+init_handle_from!(vk::ImageView, ImageView);
+init_handle_from!(vk::CommandPool, CommandPool);
+init_handle_from!(vk::Fence, Fence);
+init_handle_from!(vk::Semaphore, Semaphore);
+init_handle_from!(vk::DescriptorPool, DescriptorPool);
+init_handle_from!(vk::Sampler, Sampler);
+
+//everything VulkanEngine::init has created so far. If init returns early the guard is dropped and
+//destroys it all in reverse order, so a failed init leaks nothing. Once the engine is built the
+//guard is disarmed and the engine owns everything
+#[derive(Default)]
+pub struct InitGuard {
+    instance: Option<Instance>,
+    debug_messenger: Option<(DebugUtils, vk::DebugUtilsMessengerEXT)>,
+    surface: Option<(Surface, vk::SurfaceKHR)>,
+    device: Option<Device>,
+    allocator: Option<Allocator>,
+    swapchain: Option<(Swapchain, vk::SwapchainKHR)>,
+    handles: Vec<InitHandle>,
+    //becomes the engine's main deletion queue
+    pub deletion_queue: DeletionQueue,
+}
+
+impl InitGuard {
+    pub fn set_instance(&mut self, instance: &Instance) {
+        self.instance = Some(instance.clone());
+    }
+
+    pub fn set_debug_messenger(&mut self, debug_utils_loader: &DebugUtils, debug_messenger: vk::DebugUtilsMessengerEXT) {
+        self.debug_messenger = Some((debug_utils_loader.clone(), debug_messenger));
+    }
+
+    pub fn set_surface(&mut self, surface_loader: &Surface, surface: vk::SurfaceKHR) {
+        self.surface = Some((surface_loader.clone(), surface));
+    }
+
+    pub fn set_device(&mut self, device: &Device) {
+        self.device = Some(device.clone());
+    }
+
+    pub fn set_swapchain(&mut self, swapchain_loader: &Swapchain, swapchain: vk::SwapchainKHR) {
+        self.swapchain = Some((swapchain_loader.clone(), swapchain));
+    }
+
+    //the allocator stays in the guard until init is done, borrow it through allocator()
+    pub fn set_allocator(&mut self, allocator: Allocator) {
+        self.allocator = Some(allocator);
+    }
+
+    pub fn allocator(&mut self) -> &mut Allocator {
+        self.allocator.as_mut().unwrap()
+    }
+
+    pub fn push(&mut self, handle: impl Into<InitHandle>) {
+        self.handles.push(handle.into());
+    }
+
+    //the image itself, its memory comes back when the allocator is dropped
+    pub fn push_image(&mut self, image: &AllocatedImage) {
+        self.push(image.image);
+        self.push(image.image_view);
+    }
+
+    //hands the allocator and the deletion queue over to the engine, nothing is destroyed anymore
+    pub fn disarm(mut self) -> (Allocator, DeletionQueue) {
+        let allocator = self.allocator.take().unwrap();
+        let deletion_queue = std::mem::take(&mut self.deletion_queue);
+        self.instance = None;
+        self.debug_messenger = None;
+        self.surface = None;
+        self.device = None;
+        self.swapchain = None;
+        self.handles.clear();
+        (allocator, deletion_queue)
+    }
+}
+
+impl Drop for InitGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(device) = self.device.take() {
+                //default images may still be uploading
+                let _ = device.device_wait_idle();
+                if let Some(allocator) = self.allocator.as_mut() {
+                    self.deletion_queue.flush(&device, allocator);
+                }
+                for handle in self.handles.drain(..).rev() {
+                    match handle {
+                        InitHandle::Image(image) => device.destroy_image(image, None),
+                        InitHandle::ImageView(view) => device.destroy_image_view(view, None),
+                        InitHandle::CommandPool(pool) => device.destroy_command_pool(pool, None),
+                        InitHandle::Fence(fence) => device.destroy_fence(fence, None),
+                        InitHandle::Semaphore(semaphore) => device.destroy_semaphore(semaphore, None),
+                        InitHandle::DescriptorPool(pool) => device.destroy_descriptor_pool(pool, None),
+                        InitHandle::Sampler(sampler) => device.destroy_sampler(sampler, None),
+                    }
+                }
+                if let Some((swapchain_loader, swapchain)) = self.swapchain.take() {
+                    swapchain_loader.destroy_swapchain(swapchain, None);
+                }
+                //frees every memory block, images included, and needs the device to do it
+                self.allocator.take();
+                device.destroy_device(None);
+            }
+            if let Some((surface_loader, surface)) = self.surface.take() {
+                surface_loader.destroy_surface(surface, None);
+            }
+            if let Some((debug_utils_loader, debug_messenger)) = self.debug_messenger.take() {
+                debug_utils_loader.destroy_debug_utils_messenger(debug_messenger, None);
+            }
+            if let Some(instance) = self.instance.take() {
+                instance.destroy_instance(None);
+            }
+        }
+    }
+}
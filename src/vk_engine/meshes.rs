@@ -0,0 +1,62 @@
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::EngineResult;
+use crate::vk_loader::{self, mesh_bounds, GeoSurface, MeshAsset};
+use crate::vk_types::bounds::Bounds;
+use crate::vk_types::gpu_mesh_buffers::upload_mesh;
+use crate::vk_types::vertex::Vertex;
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+
+//meshes uploaded through the engine are owned by it and freed when it is dropped
+impl<'a> VulkanEngine<'a> {
+    //the bounds of the surfaces are computed from the vertices, whatever they were set to
+    pub fn upload_mesh(
+        &mut self,
+        name: impl Into<String>,
+        indices: &[u32],
+        vertices: &[Vertex],
+        mut surfaces: Vec<GeoSurface>,
+    ) -> EngineResult<Rc<RefCell<MeshAsset>>> {
+        for surface in surfaces.iter_mut() {
+            let surface_indices = &indices[surface.start_index as usize..(surface.start_index + surface.count) as usize];
+            surface.bounds = Bounds::from_indexed(surface_indices, vertices);
+        }
+        let mesh_buffers = upload_mesh(
+            &self.device,
+            &mut self.allocator,
+            indices,
+            vertices,
+            self.immediate_command_buffer,
+            self.immediate_fence,
+            self.graphics_queue,
+        )?;
+        let mesh = Rc::new(RefCell::new(MeshAsset {
+            name: name.into(),
+            bounds: mesh_bounds(&surfaces),
+            surfaces,
+            mesh_buffers,
+            morph_weights: Vec::new(),
+        }));
+        self.test_meshes.push(mesh.clone());
+        Ok(mesh)
+    }
+
+    pub fn load_gltf_meshes(
+        &mut self,
+        file_path: impl AsRef<Path>,
+    ) -> EngineResult<Vec<Rc<RefCell<MeshAsset>>>> {
+        let meshes = vk_loader::load_gltf_meshes(self, file_path.as_ref())?;
+        self.test_meshes.extend(meshes.iter().cloned());
+        Ok(meshes)
+    }
+
+    pub fn load_obj_meshes(
+        &mut self,
+        file_path: impl AsRef<Path>,
+    ) -> EngineResult<Rc<RefCell<MeshAsset>>> {
+        let mesh = vk_loader::load_obj_meshes(self, file_path.as_ref())?;
+        self.test_meshes.push(mesh.clone());
+        Ok(mesh)
+    }
+}
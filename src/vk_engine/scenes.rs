@@ -0,0 +1,87 @@
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::EngineResult;
+use crate::vk_loader::{self, LoadedGLTF};
+use crate::vk_scene::Renderable;
+use cgmath::{Deg, SquareMatrix};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Instant;
+
+//loaded scenes are drawn every frame until they are unloaded or the engine is dropped
+impl<'a> VulkanEngine<'a> {
+    //loading under a name that is already taken replaces the previous scene
+    pub fn load_gltf(
+        &mut self,
+        name: impl Into<String>,
+        file_path: impl AsRef<Path>,
+    ) -> EngineResult<Rc<RefCell<LoadedGLTF>>> {
+        let scene = Rc::new(RefCell::new(vk_loader::load_gltf(self, file_path.as_ref())?));
+        if let Some(previous) = self.loaded_scenes.insert(name.into(), scene.clone()) {
+            self.destroy_loaded_gltf(&previous)?;
+        }
+        Ok(scene)
+    }
+
+    //returns false when nothing was loaded under that name
+    pub fn unload_gltf(&mut self, name: &str) -> EngineResult<bool> {
+        match self.loaded_scenes.remove(name) {
+            Some(scene) => {
+                self.destroy_loaded_gltf(&scene)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn destroy_loaded_gltf(&mut self, scene: &RefCell<LoadedGLTF>) -> EngineResult<()> {
+        //frames in flight can still be drawing it
+        unsafe { self.device.device_wait_idle()? };
+        unsafe { scene.borrow_mut().dealloc(&self.device, &mut self.allocator) };
+        Ok(())
+    }
+
+    //sets up the camera, advances animations and refills the render list from the test meshes
+    //and loaded scenes
+    pub fn update_scene(&mut self) {
+        //world matrix needs to be upside down for gltf meshes since Vulkan uses opposite Y to OpenGL
+        //set the monkey 5 units back (using left-handed coordinates it seems)
+        let view = cgmath::Matrix4::from_translation((0f32, 0f32, -5f32).into());
+        let mut projection = cgmath::perspective(Deg(70f32), (self.draw_extent.width as f32) / (self.draw_extent.height as f32), 10000f32, 0.1f32);
+
+        // https://matthewwellings.com/blog/the-new-vulkan-coordinate-system/
+        // https://github.com/LunarG/VulkanSamples/commit/0dd36179880238014512c0637b0ba9f41febe803
+        // invert y-axis to use a left-handed system like OpenGL and change NDC to [0,1] which is what Vulkan uses
+        let clip = cgmath::Matrix4::new(1f32, 0f32, 0f32,0f32 ,
+                                                    0f32, -1f32, 0f32 ,0f32,
+                                                    0f32, 0f32 ,0.5f32, 0f32,
+                                                    0f32, 0f32, 0.5f32, 1f32);
+
+        projection = clip * projection;
+
+        self.scene_data.view = view;
+        self.scene_data.proj = projection;
+        self.scene_data.viewproj = projection * view;
+
+        let now = Instant::now();
+        self.stats.frame_time = now.duration_since(self.last_frame_time);
+        self.delta_time = self.stats.frame_time.as_secs_f32();
+        self.last_frame_time = now;
+        let frame_index = self.get_current_frame_index();
+        for scene in self.loaded_scenes.values() {
+            let mut scene = scene.borrow_mut();
+            scene.update_animation(self.delta_time);
+            scene.use_skinned_vertices(frame_index);
+        }
+
+        self.main_draw_context.clear();
+        let top_matrix = cgmath::Matrix4::identity();
+        //draw a blender monkeyhead, if the default meshes were loaded
+        if let Some(monkey_mesh) = self.test_meshes.get(2) {
+            self.main_draw_context.add_mesh(&monkey_mesh.borrow(), &top_matrix);
+        }
+        for scene in self.loaded_scenes.values() {
+            scene.borrow().draw(&top_matrix, &mut self.main_draw_context);
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+
+//counters of the last frame, shown in the stats window
+#[derive(Copy, Clone, Debug, Default)]
+pub struct EngineStats {
+    pub frame_time: Duration,
+    //time spent building the render list and culling it
+    pub scene_update_time: Duration,
+    pub drawn_objects: usize,
+    pub culled_objects: usize,
+    pub triangle_count: usize,
+}
+
+pub(crate) fn stats_ui(ui: &imgui::Ui, stats: &EngineStats, frustum_culling: &mut bool) {
+    ui.text(format!("Frame time: {:.2} ms", stats.frame_time.as_secs_f64() * 1000f64));
+    ui.text(format!("Scene update: {:.2} ms", stats.scene_update_time.as_secs_f64() * 1000f64));
+    ui.text(format!("Draws: {}", stats.drawn_objects));
+    ui.text(format!("Culled: {}", stats.culled_objects));
+    ui.text(format!("Triangles: {}", stats.triangle_count));
+    ui.checkbox("Frustum culling", frustum_culling);
+}
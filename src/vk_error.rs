@@ -0,0 +1,86 @@
+use ash::vk;
+use std::path::PathBuf;
+use thiserror::Error;
+
+//everything that can go wrong while bringing the engine up
+#[derive(Debug, Error)]
+pub enum EngineError {
+    #[error("Failed to read config {}: {source}", .path.display())]
+    ConfigFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Invalid config {}: {source}", .path.display())]
+    InvalidConfig {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("SDL error: {0}")]
+    Sdl(String),
+    #[error("No GPU with Vulkan support found")]
+    NoGpu,
+    #[error("No GPU has a queue family that can render{}", if *.needs_present { " and present" } else { "" })]
+    NoGraphicsQueue { needs_present: bool },
+    #[error("GPU {device} is missing required features: {}", .features.join(", "))]
+    MissingFeatures {
+        device: String,
+        features: Vec<&'static str>,
+    },
+    #[error("Vulkan call failed: {0}")]
+    Vulkan(#[from] vk::Result),
+    #[error("GPU memory allocation failed: {0}")]
+    Allocation(#[from] gpu_allocator::AllocationError),
+    #[error("Failed to open shader {}: {source}", .path.display())]
+    ShaderFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Shader {} is not valid SPIR-V: {source}", .path.display())]
+    InvalidSpirv {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to load glTF {}: {source}", .path.display())]
+    Gltf { path: PathBuf, source: gltf::Error },
+    #[error("glTF {} can not be used: {reason}", .path.display())]
+    InvalidGltf { path: PathBuf, reason: String },
+    #[error("Failed to load OBJ {}: {source}", .path.display())]
+    Obj {
+        path: PathBuf,
+        source: tobj::LoadError,
+    },
+    #[error("OBJ {} can not be used: {reason}", .path.display())]
+    InvalidObj { path: PathBuf, reason: String },
+    #[error("Failed to write mesh cache {}: {source}", .path.display())]
+    MeshCache {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to read asset directory {}: {source}", .path.display())]
+    AssetDirectory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to read image {}: {source}", .path.display())]
+    ImageFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Image {name} can not be decoded: {reason}")]
+    InvalidImage { name: String, reason: String },
+    #[error("Captures of {0:?} images are not supported")]
+    UnsupportedCaptureFormat(vk::Format),
+    #[error("Nothing to capture: {0}")]
+    NothingToCapture(&'static str),
+    #[error("Failed to save capture {}: {source}", .path.display())]
+    CaptureFile {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[error("{0} needs a window, the engine is running headless")]
+    Headless(&'static str),
+    #[error("Failed to initialize the UI renderer: {0}")]
+    Ui(String),
+}
+
+pub type EngineResult<T> = Result<T, EngineError>;
@@ -1,40 +1,272 @@
 use crate::vk_init;
 use ash::vk::{ImageSubresourceLayers, Offset3D};
 use ash::{vk, Device};
+use std::collections::HashMap;
 use std::slice;
 
-//using sync2 pipeline barrier to transition image layouts
+//layout of an image together with the stages and accesses of its last use, or of its next use
+//when transitioning to it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageState {
+    pub layout: vk::ImageLayout,
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+//the flag types only have const constructors, not const bit operations
+const fn stages(flags: &[vk::PipelineStageFlags2]) -> vk::PipelineStageFlags2 {
+    let mut raw = 0;
+    let mut index = 0;
+    while index < flags.len() {
+        raw |= flags[index].as_raw();
+        index += 1;
+    }
+    vk::PipelineStageFlags2::from_raw(raw)
+}
+
+const fn accesses(flags: &[vk::AccessFlags2]) -> vk::AccessFlags2 {
+    let mut raw = 0;
+    let mut index = 0;
+    while index < flags.len() {
+        raw |= flags[index].as_raw();
+        index += 1;
+    }
+    vk::AccessFlags2::from_raw(raw)
+}
+
+//accesses that have to be made available before anything else touches the image
+const WRITE_ACCESS: vk::AccessFlags2 = accesses(&[
+    vk::AccessFlags2::SHADER_WRITE,
+    vk::AccessFlags2::SHADER_STORAGE_WRITE,
+    vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+    vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+    vk::AccessFlags2::TRANSFER_WRITE,
+    vk::AccessFlags2::HOST_WRITE,
+    vk::AccessFlags2::MEMORY_WRITE,
+]);
+
+impl ImageState {
+    //freshly created, nothing to wait for
+    pub const UNDEFINED: ImageState = ImageState {
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags2::NONE,
+        access: vk::AccessFlags2::NONE,
+    };
+    //a swapchain image right after acquiring it. The acquire semaphore is waited on at
+    //COLOR_ATTACHMENT_OUTPUT, the first barrier has to chain onto that stage
+    pub const ACQUIRED: ImageState = ImageState {
+        layout: vk::ImageLayout::UNDEFINED,
+        stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        access: vk::AccessFlags2::NONE,
+    };
+    pub const TRANSFER_SRC: ImageState = ImageState {
+        layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        stage: vk::PipelineStageFlags2::TRANSFER,
+        access: vk::AccessFlags2::TRANSFER_READ,
+    };
+    pub const TRANSFER_DST: ImageState = ImageState {
+        layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        stage: vk::PipelineStageFlags2::TRANSFER,
+        access: vk::AccessFlags2::TRANSFER_WRITE,
+    };
+    //storage image written by a compute shader
+    pub const COMPUTE_WRITE: ImageState = ImageState {
+        layout: vk::ImageLayout::GENERAL,
+        stage: vk::PipelineStageFlags2::COMPUTE_SHADER,
+        access: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+    };
+    pub const COLOR_ATTACHMENT: ImageState = ImageState {
+        layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        stage: vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT,
+        access: accesses(&[
+            vk::AccessFlags2::COLOR_ATTACHMENT_READ,
+            vk::AccessFlags2::COLOR_ATTACHMENT_WRITE,
+        ]),
+    };
+    pub const DEPTH_ATTACHMENT: ImageState = ImageState {
+        layout: vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+        stage: stages(&[
+            vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS,
+            vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS,
+        ]),
+        access: accesses(&[
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ,
+            vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE,
+        ]),
+    };
+    //sampled from fragment or compute shaders
+    pub const SHADER_READ: ImageState = ImageState {
+        layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        stage: stages(&[
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+        ]),
+        access: vk::AccessFlags2::SHADER_SAMPLED_READ,
+    };
+    //the presentation engine waits on the render semaphore, the barrier does not have to block
+    //anything
+    pub const PRESENT: ImageState = ImageState {
+        layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        stage: vk::PipelineStageFlags2::NONE,
+        access: vk::AccessFlags2::NONE,
+    };
+
+    pub fn writes(&self) -> bool {
+        self.access.intersects(WRITE_ACCESS)
+    }
+}
+
+//every aspect of the format, which is what layout transitions need
+pub fn format_aspect_flags(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+//waits for the writes of the current state before the next one's stages, reads need no
+//availability. Only levels base_mip_level to base_mip_level + level_count are covered
+pub fn image_barrier(
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+    base_mip_level: u32,
+    level_count: u32,
+    current: ImageState,
+    new: ImageState,
+) -> vk::ImageMemoryBarrier2 {
+    let mut subresource_range = vk_init::image_subresource_range(aspect_mask);
+    subresource_range.base_mip_level = base_mip_level;
+    subresource_range.level_count = level_count;
+    vk::ImageMemoryBarrier2::builder()
+        .src_stage_mask(current.stage)
+        .src_access_mask(current.access & WRITE_ACCESS)
+        .dst_stage_mask(new.stage)
+        .dst_access_mask(new.access)
+        .old_layout(current.layout)
+        .new_layout(new.layout)
+        .subresource_range(subresource_range)
+        .image(image)
+        .build()
+}
+
+//using sync2 pipeline barrier to transition every level and layer of an image
 pub fn transition_image(
     device: &Device,
     cmd: vk::CommandBuffer,
     image: vk::Image,
-    current_layout: vk::ImageLayout,
-    new_layout: vk::ImageLayout,
+    aspect_mask: vk::ImageAspectFlags,
+    current: ImageState,
+    new: ImageState,
 ) {
-    let mut image_barrier = vk::ImageMemoryBarrier2::builder()
-        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
-        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
-        .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE | vk::AccessFlags2::MEMORY_READ)
-        .old_layout(current_layout)
-        .new_layout(new_layout);
-
-    let aspect_mask = if new_layout == vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL {
-        vk::ImageAspectFlags::DEPTH
-    } else {
-        vk::ImageAspectFlags::COLOR
-    };
-    image_barrier = image_barrier
-        .subresource_range(vk_init::image_subresource_range(aspect_mask))
-        .image(image);
-
+    let image_barrier = image_barrier(image, aspect_mask, 0, vk::REMAINING_MIP_LEVELS, current, new);
     let dependency_info = vk::DependencyInfo::builder()
         .image_memory_barriers(slice::from_ref(&image_barrier))
         .build();
+    unsafe { device.cmd_pipeline_barrier2(cmd, &dependency_info) };
+}
 
+//same as transition_image for level_count mip levels of a color image starting at
+//base_mip_level, the others keep their layout
+pub fn transition_image_levels(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    image: vk::Image,
+    base_mip_level: u32,
+    level_count: u32,
+    current: ImageState,
+    new: ImageState,
+) {
+    let image_barrier = image_barrier(
+        image,
+        vk::ImageAspectFlags::COLOR,
+        base_mip_level,
+        level_count,
+        current,
+        new,
+    );
+    let dependency_info = vk::DependencyInfo::builder()
+        .image_memory_barriers(slice::from_ref(&image_barrier))
+        .build();
     unsafe { device.cmd_pipeline_barrier2(cmd, &dependency_info) };
 }
 
+struct TrackedImage {
+    aspect_mask: vk::ImageAspectFlags,
+    state: ImageState,
+}
+
+//last known state of the images the engine renders to, so every transition waits on exactly
+//what touched the image last. States follow the order commands are recorded in, which has to
+//be the order they are submitted in. Images only used through an upload, like textures, are not
+//tracked
+#[derive(Default)]
+pub struct ImageStateTracker {
+    images: HashMap<vk::Image, TrackedImage>,
+}
+
+impl ImageStateTracker {
+    //starts tracking an image, or resets what is known about it
+    pub fn track(&mut self, image: vk::Image, format: vk::Format, state: ImageState) {
+        self.images.insert(
+            image,
+            TrackedImage {
+                aspect_mask: format_aspect_flags(format),
+                state,
+            },
+        );
+    }
+
+    //has to be called before the image is destroyed, its handle could be reused
+    pub fn forget(&mut self, image: vk::Image) {
+        self.images.remove(&image);
+    }
+
+    pub fn state(&self, image: vk::Image) -> Option<ImageState> {
+        self.images.get(&image).map(|tracked| tracked.state)
+    }
+
+    //records the barrier to the next use of the image. Reads in the same layout as the
+    //previous reads need none, their stages are added to the state instead
+    pub fn transition(&mut self, device: &Device, cmd: vk::CommandBuffer, image: vk::Image, new: ImageState) {
+        self.transition_from(device, cmd, image, new, false);
+    }
+
+    //same as transition for uses that overwrite the whole image, the old contents are dropped
+    pub fn discard(&mut self, device: &Device, cmd: vk::CommandBuffer, image: vk::Image, new: ImageState) {
+        self.transition_from(device, cmd, image, new, true);
+    }
+
+    fn transition_from(
+        &mut self,
+        device: &Device,
+        cmd: vk::CommandBuffer,
+        image: vk::Image,
+        new: ImageState,
+        discard: bool,
+    ) {
+        let tracked = self.images.get_mut(&image).expect("transition of an untracked image");
+        let mut current = tracked.state;
+        if !discard && current.layout == new.layout && !current.writes() && !new.writes() {
+            tracked.state.stage |= new.stage;
+            tracked.state.access |= new.access;
+            return;
+        }
+        if discard {
+            current.layout = vk::ImageLayout::UNDEFINED;
+        }
+        transition_image(device, cmd, image, tracked.aspect_mask, current, new);
+        tracked.state = new;
+    }
+}
+
 pub fn copy_image_to_image(
     device: &Device,
     cmd: vk::CommandBuffer,
@@ -43,6 +275,31 @@ pub fn copy_image_to_image(
     src_size: vk::Extent2D,
     dst_size: vk::Extent2D,
 ) {
+    blit_image_level(device, cmd, source, 0, destination, 0, src_size, dst_size);
+}
+
+//linearly filtered blit from one mip level into another, source and destination may be the same
+//image. The source level must be in TRANSFER_SRC_OPTIMAL and the destination level in
+//TRANSFER_DST_OPTIMAL layout
+#[allow(clippy::too_many_arguments)]
+pub fn blit_image_level(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    source: vk::Image,
+    source_mip_level: u32,
+    destination: vk::Image,
+    destination_mip_level: u32,
+    src_size: vk::Extent2D,
+    dst_size: vk::Extent2D,
+) {
+    let subresource = |mip_level: u32| {
+        ImageSubresourceLayers::builder()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .base_array_layer(0)
+            .layer_count(1)
+            .mip_level(mip_level)
+            .build()
+    };
     let blit_region = vk::ImageBlit2::builder()
         .src_offsets([
             Offset3D::default(),
@@ -60,7 +317,36 @@ pub fn copy_image_to_image(
                 z: 1,
             },
         ])
-        .src_subresource(
+        .src_subresource(subresource(source_mip_level))
+        .dst_subresource(subresource(destination_mip_level))
+        .build();
+
+    let blit_info = vk::BlitImageInfo2::builder()
+        .dst_image(destination)
+        .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_image(source)
+        .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .filter(vk::Filter::LINEAR)
+        .regions(slice::from_ref(&blit_region))
+        .build();
+
+    unsafe { device.cmd_blit_image2(cmd, &blit_info) };
+}
+
+//copies the color contents of an image in TRANSFER_SRC_OPTIMAL layout into a tightly packed buffer
+pub fn copy_image_to_buffer(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    source: vk::Image,
+    destination: vk::Buffer,
+    size: vk::Extent2D,
+) {
+    let copy_region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        //zero means tightly packed
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
             ImageSubresourceLayers::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .base_array_layer(0)
@@ -68,24 +354,58 @@ pub fn copy_image_to_image(
                 .mip_level(0)
                 .build(),
         )
-        .dst_subresource(
+        .image_offset(Offset3D::default())
+        .image_extent(vk::Extent3D {
+            width: size.width,
+            height: size.height,
+            depth: 1,
+        })
+        .build();
+
+    unsafe {
+        device.cmd_copy_image_to_buffer(
+            cmd,
+            source,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            destination,
+            slice::from_ref(&copy_region),
+        )
+    };
+}
+
+//copies a tightly packed buffer into the first mip level of an image in TRANSFER_DST_OPTIMAL layout.
+//Layers follow each other in the buffer
+pub fn copy_buffer_to_image(
+    device: &Device,
+    cmd: vk::CommandBuffer,
+    source: vk::Buffer,
+    destination: vk::Image,
+    extent: vk::Extent3D,
+    layer_count: u32,
+) {
+    let copy_region = vk::BufferImageCopy::builder()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
             ImageSubresourceLayers::builder()
                 .aspect_mask(vk::ImageAspectFlags::COLOR)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(layer_count)
                 .mip_level(0)
                 .build(),
         )
+        .image_offset(Offset3D::default())
+        .image_extent(extent)
         .build();
 
-    let blit_info = vk::BlitImageInfo2::builder()
-        .dst_image(destination)
-        .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
-        .src_image(source)
-        .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
-        .filter(vk::Filter::LINEAR)
-        .regions(slice::from_ref(&blit_region))
-        .build();
-
-    unsafe { device.cmd_blit_image2(cmd, &blit_info) };
+    unsafe {
+        device.cmd_copy_buffer_to_image(
+            cmd,
+            source,
+            destination,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            slice::from_ref(&copy_region),
+        )
+    };
 }
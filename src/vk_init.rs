@@ -82,40 +82,60 @@ pub fn submit_info(
         .build()
 }
 
+//cube views need their image to be cube compatible, 3D views a 3D image
 pub fn image_create_info(
     format: vk::Format,
     usage_flags: vk::ImageUsageFlags,
     extent: vk::Extent3D,
+    mip_levels: u32,
+    array_layers: u32,
+    samples: vk::SampleCountFlags,
+    view_type: vk::ImageViewType,
 ) -> vk::ImageCreateInfo {
+    let (image_type, flags) = match view_type {
+        vk::ImageViewType::TYPE_1D | vk::ImageViewType::TYPE_1D_ARRAY => {
+            (vk::ImageType::TYPE_1D, vk::ImageCreateFlags::empty())
+        }
+        vk::ImageViewType::TYPE_3D => (vk::ImageType::TYPE_3D, vk::ImageCreateFlags::empty()),
+        vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY => {
+            (vk::ImageType::TYPE_2D, vk::ImageCreateFlags::CUBE_COMPATIBLE)
+        }
+        _ => (vk::ImageType::TYPE_2D, vk::ImageCreateFlags::empty()),
+    };
     vk::ImageCreateInfo::builder()
-        .image_type(vk::ImageType::TYPE_2D)
+        .flags(flags)
+        .image_type(image_type)
         .format(format)
         .extent(extent)
-        .mip_levels(1)
-        .array_layers(1)
-        //for MSAA. we will not be using it by default, so default it to 1 sample per pixel.
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .mip_levels(mip_levels)
+        .array_layers(array_layers)
+        //more than one sample for MSAA render targets
+        .samples(samples)
         //optimal tiling, which means the image is stored on the best gpu format
         .tiling(vk::ImageTiling::OPTIMAL)
         .usage(usage_flags)
         .build()
 }
 
+//the view covers every mip level and layer of the image
 pub fn image_view_create_info(
     format: vk::Format,
     image: vk::Image,
     aspect_flags: vk::ImageAspectFlags,
+    view_type: vk::ImageViewType,
+    mip_levels: u32,
+    array_layers: u32,
 ) -> vk::ImageViewCreateInfo {
     vk::ImageViewCreateInfo::builder()
-        .view_type(vk::ImageViewType::TYPE_2D)
+        .view_type(view_type)
         .image(image)
         .format(format)
         .subresource_range(
             vk::ImageSubresourceRange::builder()
                 .base_mip_level(0)
-                .level_count(1)
+                .level_count(mip_levels)
                 .base_array_layer(0)
-                .layer_count(1)
+                .layer_count(array_layers)
                 .aspect_mask(aspect_flags)
                 .build(),
         )
@@ -1,79 +1,545 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::fmt::Display;
+use std::mem::{size_of, size_of_val};
 use std::path::Path;
 use std::rc::Rc;
-use ash::{Device, vk};
-use cfg_if::cfg_if;
+use ash::{vk, Device};
+use cgmath::SquareMatrix;
+use gltf::animation::util::ReadOutputs;
+use gltf::material::AlphaMode;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
 use gltf::Semantic;
-use crate::vk_types::gpu_mesh_buffers::{GPUMeshBuffers, upload_mesh};
+use crate::vk_animation::{AnimationChannel, AnimationClip, AnimationPlayer, ChannelValues, Interpolation, NodePose, Skin};
+use crate::vk_descriptors::{DescriptorAllocatorGrowable, PoolSizeRatio};
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_materials::{MaterialConstants, MaterialResources};
+use crate::vk_scene::{DrawContext, Node, Renderable};
+use crate::vk_skinning::{SkinVertex, SkinnedMesh};
+use crate::vk_types::bounds::Bounds;
+use crate::vk_types::buffers::{buffer_device_address, create_buffer, destroy_buffer, AllocatedBuffer};
+use crate::vk_types::gpu_mesh_buffers::{GPUMeshBuffers, MorphTargetDelta, upload_mesh};
+use crate::vk_types::material::{MaterialInstance, MaterialPass};
+use crate::vk_types::sampler_cache::SamplerDesc;
 use crate::vk_types::vertex::Vertex;
 
+mod cache;
+mod geometry;
+mod obj;
+pub use cache::{bake_gltf_meshes, bake_mesh_caches, mesh_cache_path, write_mesh_cache, BakeReport, BakedMesh, BakedSurface, MeshCache, MESH_CACHE_VERSION};
+pub use geometry::{compute_normals, compute_tangents, remap_vertices, unweld};
+pub use obj::load_obj_meshes;
+
+pub struct GLTFMaterial {
+    pub data: MaterialInstance
+}
+
 #[derive(Default)]
 pub struct GeoSurface {
     pub start_index: u32,
-    pub count: u32
+    pub count: u32,
+    //drawn with the engine's default material when None
+    pub material: Option<Rc<GLTFMaterial>>,
+    //around the vertices the surface draws, without morph targets or skinning applied
+    pub bounds: Bounds
 }
 
 pub struct MeshAsset {
     pub name: String,
     pub surfaces: Vec<GeoSurface>,
-    pub mesh_buffers: GPUMeshBuffers
-}
-
-pub fn load_gltf_meshes<P: AsRef<Path> + ?Sized + Display>(device: &Device,
-                        allocator: &mut gpu_allocator::vulkan::Allocator,
-                        immediate_command_buffer: vk::CommandBuffer,
-                        immediate_fence: vk::Fence,
-                        immediate_queue: vk::Queue,
-                        file_path: &P) -> Option<Vec<Rc<RefCell<MeshAsset>>>> {
-    println!("Loading GLTF: {}", file_path);
-    let import = gltf::import(file_path);
-    if let Err(e) = import {
-        println!("Failed to load glTF: {}", e);
-        return None
-    }
-    let (gltf, buffers, _) = import.unwrap();
-    let mut meshes = Vec::<Rc<RefCell<MeshAsset>>>::new();
-    // use the same vectors for all meshes so that the memory doesnt reallocate as often
-    let mut indices = Vec::<u32>::new();
-    let mut vertices = Vec::<Vertex>::new();
+    pub mesh_buffers: GPUMeshBuffers,
+    //default morph target weights, nodes can override them
+    pub morph_weights: Vec<f32>,
+    //around every surface
+    pub bounds: Bounds
+}
+
+//bounds of a whole mesh, empty for a mesh without surfaces
+pub fn mesh_bounds(surfaces: &[GeoSurface]) -> Bounds {
+    surfaces
+        .iter()
+        .map(|surface| surface.bounds)
+        .reduce(|bounds, surface_bounds| bounds.union(&surface_bounds))
+        .unwrap_or_default()
+}
+
+pub struct GLTFScene {
+    pub name: String,
+    pub top_nodes: Vec<Rc<RefCell<Node>>>
+}
+
+//everything loaded from one glTF file. It owns the GPU resources of its meshes and materials,
+//images are shared through the engine's texture cache
+pub struct LoadedGLTF {
+    pub meshes: Vec<Rc<RefCell<MeshAsset>>>,
+    pub nodes: Vec<Rc<RefCell<Node>>>,
+    pub materials: Vec<Rc<GLTFMaterial>>,
+    pub scenes: Vec<GLTFScene>,
+    //index of the scene draw() adds to the render list, the file's default scene after loading
+    pub active_scene: usize,
+    pub skins: Vec<Skin>,
+    pub skinned_meshes: Vec<SkinnedMesh>,
+    pub animations: Vec<AnimationClip>,
+    pub animation_player: AnimationPlayer,
+    //local transforms and morph target weights from the file, every animated frame starts from them
+    rest_poses: Vec<NodePose>,
+    rest_morph_weights: Vec<Vec<f32>>,
+    root_nodes: Vec<Rc<RefCell<Node>>>,
+    //joint influences per mesh, only uploaded for meshes a skinned node draws
+    skin_buffers: Vec<Option<(AllocatedBuffer, vk::DeviceAddress)>>,
+    descriptor_allocator: DescriptorAllocatorGrowable,
+    material_data_buffer: Option<AllocatedBuffer>
+}
+
+impl LoadedGLTF {
+    pub fn find_node(&self, name: &str) -> Option<&Rc<RefCell<Node>>> {
+        self.nodes.iter().find(|node| node.borrow().name == name)
+    }
+
+    pub fn find_mesh(&self, name: &str) -> Option<&Rc<RefCell<MeshAsset>>> {
+        self.meshes.iter().find(|mesh| mesh.borrow().name == name)
+    }
+
+    pub fn find_scene(&self, name: &str) -> Option<usize> {
+        self.scenes.iter().position(|scene| scene.name == name)
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|animation| animation.name == name)
+    }
+
+    pub fn skin_vertices_address(&self, mesh: usize) -> Option<vk::DeviceAddress> {
+        self.skin_buffers[mesh].as_ref().map(|(_, address)| *address)
+    }
+
+    //advances the clip being played and poses the nodes with it, no clip puts them at rest
+    pub fn update_animation(&mut self, delta_time: f32) {
+        if self.animations.is_empty() {
+            return;
+        }
+        let mut poses = self.rest_poses.clone();
+        let mut morph_weights = self.rest_morph_weights.clone();
+        if let Some(clip) = self.animation_player.clip.and_then(|clip| self.animations.get(clip)) {
+            self.animation_player.advance(delta_time, clip.duration);
+            clip.apply(self.animation_player.time, &mut poses, &mut morph_weights);
+        }
+        for ((node, pose), weights) in self.nodes.iter().zip(poses.iter()).zip(morph_weights) {
+            let mut node = node.borrow_mut();
+            node.local_transform = pose.matrix();
+            node.morph_weights = weights;
+        }
+        for node in self.root_nodes.iter() {
+            node.borrow_mut().refresh_transform(&cgmath::Matrix4::identity());
+        }
+    }
+
+    //skinned nodes draw the vertices the skinning pass writes for this frame
+    pub fn use_skinned_vertices(&self, frame_index: usize) {
+        for skinned_mesh in self.skinned_meshes.iter() {
+            self.nodes[skinned_mesh.node].borrow_mut().skinned_vertices =
+                Some(skinned_mesh.output_address(frame_index));
+        }
+    }
+
+    /// # Safety
+    /// The GPU must be done with the meshes and materials of the file
+    pub unsafe fn dealloc(&mut self, device: &Device, allocator: &mut gpu_allocator::vulkan::Allocator) {
+        for mesh in self.meshes.iter() {
+            mesh.borrow_mut().mesh_buffers.dealloc(device, allocator);
+        }
+        if let Some(mut material_data_buffer) = self.material_data_buffer.take() {
+            destroy_buffer(device, allocator, &mut material_data_buffer);
+        }
+        for skinned_mesh in self.skinned_meshes.iter_mut() {
+            skinned_mesh.dealloc(device, allocator);
+        }
+        for (mut skin_buffer, _) in self.skin_buffers.iter_mut().filter_map(Option::take) {
+            destroy_buffer(device, allocator, &mut skin_buffer);
+        }
+        self.descriptor_allocator.destroy_pools(device);
+    }
+}
+
+impl Renderable for LoadedGLTF {
+    fn draw(&self, top_matrix: &cgmath::Matrix4<f32>, ctx: &mut DrawContext) {
+        if let Some(scene) = self.scenes.get(self.active_scene) {
+            for node in scene.top_nodes.iter() {
+                node.borrow().draw(top_matrix, ctx);
+            }
+        }
+    }
+}
+
+//loads the whole file: meshes, materials and every scene with its node hierarchy. Skins and
+//animations need the buffers anyway, so unlike load_gltf_meshes this always reads the meshes from
+//the file and never from the mesh cache
+pub fn load_gltf<P: AsRef<Path> + ?Sized>(engine: &mut VulkanEngine,
+                 file_path: &P) -> EngineResult<LoadedGLTF> {
+    let path = file_path.as_ref();
+    let (gltf, buffers, images) = open_gltf(engine, path)?;
+    let mut descriptor_allocator = init_material_allocator(&engine.device, gltf.materials().len())?;
+    let (materials, material_data_buffer) = load_gltf_materials(engine, &gltf, &images, &mut descriptor_allocator)?;
+    let (baked_meshes, mesh_skin_vertices) = read_gltf_meshes(path, &gltf, &buffers)?;
+    let meshes = baked_meshes
+        .iter()
+        .map(|mesh| upload_baked_mesh(engine, mesh, &materials))
+        .collect::<EngineResult<Vec<Rc<RefCell<MeshAsset>>>>>()?;
+
+    //load all nodes and their meshes
+    let nodes = gltf
+        .nodes()
+        .map(|node| {
+            //unnamed nodes are valid glTF too
+            let name = match node.name() {
+                Some(name) => String::from(name),
+                None => format!("node_{}", node.index()),
+            };
+            let mut new_node = Node::new(name, cgmath::Matrix4::from(node.transform().matrix()));
+            if let Some(mesh) = node.mesh() {
+                let mesh = meshes[mesh.index()].clone();
+                //nodes can override the weights of their mesh
+                new_node.morph_weights = match node.weights() {
+                    Some(weights) => weights.to_vec(),
+                    None => mesh.borrow().morph_weights.clone(),
+                };
+                new_node.mesh = Some(mesh);
+            }
+            Rc::new(RefCell::new(new_node))
+        })
+        .collect::<Vec<Rc<RefCell<Node>>>>();
+
+    //run loop again to setup transform hierarchy
+    for node in gltf.nodes() {
+        let scene_node = &nodes[node.index()];
+        for child in node.children() {
+            let child_node = &nodes[child.index()];
+            child_node.borrow_mut().parent = Rc::downgrade(scene_node);
+            scene_node.borrow_mut().children.push(child_node.clone());
+        }
+    }
+
+    //find the top nodes, with no parents, and propagate their transforms down
+    let root_nodes = nodes
+        .iter()
+        .filter(|node| node.borrow().parent.upgrade().is_none())
+        .cloned()
+        .collect::<Vec<Rc<RefCell<Node>>>>();
+    for node in root_nodes.iter() {
+        node.borrow_mut().refresh_transform(&cgmath::Matrix4::identity());
+    }
+    let rest_poses = gltf
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            NodePose {
+                translation: translation.into(),
+                //glTF stores quaternions as x, y, z, w
+                rotation: cgmath::Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+                scale: scale.into(),
+            }
+        })
+        .collect::<Vec<NodePose>>();
+    let rest_morph_weights = nodes
+        .iter()
+        .map(|node| node.borrow().morph_weights.clone())
+        .collect::<Vec<Vec<f32>>>();
+
+    let skins = load_gltf_skins(&gltf, &buffers);
+    let animations = load_gltf_animations(&gltf, &buffers);
+
+    //every skinned node gets its own output vertices, the joint influences are shared per mesh
+    let mut skin_buffers = meshes.iter().map(|_| None).collect::<Vec<Option<(AllocatedBuffer, vk::DeviceAddress)>>>();
+    let mut skinned_meshes = Vec::<SkinnedMesh>::new();
+    for node in gltf.nodes() {
+        let (Some(mesh), Some(skin)) = (node.mesh(), node.skin()) else {
+            continue;
+        };
+        let skin_vertices = &mesh_skin_vertices[mesh.index()];
+        if skin_vertices.is_empty() {
+            log::warn!("Mesh {} has a skin but no joints, it is drawn unskinned", meshes[mesh.index()].borrow().name);
+            continue;
+        }
+        if skin_buffers[mesh.index()].is_none() {
+            let mut skin_buffer = create_buffer(
+                &engine.device,
+                &mut engine.allocator,
+                size_of_val(skin_vertices.as_slice()) as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+            )?;
+            presser::copy_from_slice_to_offset(skin_vertices, skin_buffer.allocation.get_mut().unwrap(), 0).unwrap();
+            let skin_buffer_address = buffer_device_address(&engine.device, &skin_buffer);
+            skin_buffers[mesh.index()] = Some((skin_buffer, skin_buffer_address));
+        }
+        skinned_meshes.push(SkinnedMesh::new(
+            &engine.device,
+            &mut engine.allocator,
+            (node.index(), mesh.index(), skin.index()),
+            skin_vertices.len() as u32,
+            engine.frames.len(),
+        )?);
+    }
+
+    let mut scenes = gltf
+        .scenes()
+        .map(|scene| GLTFScene {
+            name: match scene.name() {
+                Some(name) => String::from(name),
+                None => format!("scene_{}", scene.index()),
+            },
+            top_nodes: scene.nodes().map(|node| nodes[node.index()].clone()).collect(),
+        })
+        .collect::<Vec<GLTFScene>>();
+    //scenes are optional, files without any still show every root node
+    if scenes.is_empty() {
+        scenes.push(GLTFScene {
+            name: String::from("scene_0"),
+            top_nodes: root_nodes.clone(),
+        });
+    }
+
+    let animation_player = AnimationPlayer {
+        clip: if animations.is_empty() { None } else { Some(0) },
+        ..Default::default()
+    };
+
+    Ok(LoadedGLTF {
+        meshes,
+        nodes,
+        materials,
+        scenes,
+        active_scene: gltf.default_scene().map_or(0, |scene| scene.index()),
+        skins,
+        skinned_meshes,
+        animations,
+        animation_player,
+        rest_poses,
+        rest_morph_weights,
+        root_nodes,
+        skin_buffers,
+        descriptor_allocator,
+        material_data_buffer
+    })
+}
+
+//loads only the meshes of a file, their materials live as long as the engine. The meshes come
+//from the mesh cache next to the file, which is (re)written whenever it is missing or stale. The
+//cache is checked before any buffer is loaded, and only the images of the materials the meshes
+//use are uploaded
+pub fn load_gltf_meshes<P: AsRef<Path> + ?Sized>(engine: &mut VulkanEngine,
+                        file_path: &P) -> EngineResult<Vec<Rc<RefCell<MeshAsset>>>> {
+    let path = file_path.as_ref();
+    println!("Loading GLTF: {}", path.display());
+    let (source, gltf, mut blob) = read_gltf_document(path)?;
+    let source_hash = cache::source_hash(path, &source, &gltf)?;
+    drop(source);
+    let cache_path = mesh_cache_path(path);
+    let mesh_cache = MeshCache::open(&cache_path, source_hash);
+    let cached_meshes = mesh_cache.as_ref().and_then(|mesh_cache| {
+        let meshes = mesh_cache.meshes();
+        if meshes.is_none() {
+            log::warn!("Mesh cache {} is corrupt, rebuilding it", cache_path.display());
+        }
+        meshes
+    });
+    let (baked_meshes, mut buffers) = match cached_meshes {
+        Some(baked_meshes) => (baked_meshes, None),
+        None => {
+            let buffers = import_gltf_buffers(path, &gltf, blob.take())?;
+            let (baked_meshes, _) = read_gltf_meshes(path, &gltf, &buffers)?;
+            //without a cache the next run just reads the file again
+            if let Err(e) = write_mesh_cache(&cache_path, source_hash, &baked_meshes) {
+                log::warn!("{}", e);
+            }
+            (baked_meshes, Some(buffers))
+        }
+    };
+
+    let used_images = used_material_images(&gltf, &baked_meshes);
+    //images stored in buffer views are the only reason left to load the buffers
+    let needs_buffers = gltf.images().any(|image| {
+        used_images[image.index()] && matches!(image.source(), gltf::image::Source::View { .. })
+    });
+    if buffers.is_none() && needs_buffers {
+        buffers = Some(import_gltf_buffers(path, &gltf, blob.take())?);
+    }
+    let images = load_gltf_images(engine, path, &gltf, buffers.as_deref().unwrap_or_default(), Some(&used_images));
+    let mut descriptor_allocator = init_material_allocator(&engine.device, gltf.materials().len())?;
+    let (materials, material_data_buffer) = load_gltf_materials(engine, &gltf, &images, &mut descriptor_allocator)?;
+    hand_over_to_engine(engine, descriptor_allocator, material_data_buffer);
+    baked_meshes.iter().map(|mesh| upload_baked_mesh(engine, mesh, &materials)).collect()
+}
+
+//which images the materials of the meshes sample, by image index
+fn used_material_images(gltf: &gltf::Document, meshes: &[BakedMesh]) -> Vec<bool> {
+    let mut used_materials = vec![false; gltf.materials().len()];
+    for material in meshes.iter().flat_map(|mesh| mesh.surfaces.iter()).filter_map(|surface| surface.material) {
+        if let Some(used) = used_materials.get_mut(material as usize) {
+            *used = true;
+        }
+    }
+    let mut used_images = vec![false; gltf.images().len()];
+    for material in gltf.materials().filter(|material| material.index().is_some_and(|index| used_materials[index])) {
+        let pbr = material.pbr_metallic_roughness();
+        for info in [pbr.base_color_texture(), pbr.metallic_roughness_texture()].into_iter().flatten() {
+            used_images[info.texture().source().index()] = true;
+        }
+    }
+    used_images
+}
+
+//parses the file without loading its buffers. Its raw bytes come back too, the mesh cache is keyed
+//by them, along with the binary chunk of .glb files
+fn read_gltf_document(path: &Path) -> EngineResult<(Vec<u8>, gltf::Document, Option<Vec<u8>>)> {
+    let gltf_error = |source: gltf::Error| EngineError::Gltf {
+        path: path.to_path_buf(),
+        source,
+    };
+    let source = std::fs::read(path).map_err(|e| gltf_error(gltf::Error::Io(e)))?;
+    let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(&source).map_err(gltf_error)?;
+    Ok((source, document, blob))
+}
+
+//loads every buffer of a parsed file, external ones are read relative to it
+fn import_gltf_buffers(path: &Path,
+                       gltf: &gltf::Document,
+                       blob: Option<Vec<u8>>) -> EngineResult<Vec<gltf::buffer::Data>> {
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    gltf::import_buffers(gltf, Some(base), blob).map_err(|source| EngineError::Gltf {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+//parses the file, loads its buffers and uploads its images
+fn open_gltf(engine: &mut VulkanEngine,
+             path: &Path) -> EngineResult<(gltf::Document, Vec<gltf::buffer::Data>, Vec<vk::ImageView>)> {
+    println!("Loading GLTF: {}", path.display());
+    //images are decoded by us, gltf::import would decode every one of them again
+    let (_, gltf, blob) = read_gltf_document(path)?;
+    let buffers = import_gltf_buffers(path, &gltf, blob)?;
+    let images = load_gltf_images(engine, path, &gltf, &buffers, None);
+    Ok((gltf, buffers, images))
+}
+
+//uploads the images of the file, or only the used ones. Images that are left out or fail to load
+//read as the error texture
+fn load_gltf_images(engine: &mut VulkanEngine,
+                    path: &Path,
+                    gltf: &gltf::Document,
+                    buffers: &[gltf::buffer::Data],
+                    used: Option<&[bool]>) -> Vec<vk::ImageView> {
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    gltf.images()
+        .map(|image| {
+            if used.is_some_and(|used| !used[image.index()]) {
+                return engine.error_checkerboard_image.image_view;
+            }
+            match load_gltf_image(engine, base, buffers, &image) {
+                Ok(image_view) => image_view,
+                Err(e) => {
+                    log::warn!("{}, using the error texture instead", e);
+                    engine.error_checkerboard_image.image_view
+                }
+            }
+        })
+        .collect()
+}
+
+//sized so all the materials of a file fit in the first pool
+fn init_material_allocator(device: &Device, material_count: usize) -> EngineResult<DescriptorAllocatorGrowable> {
+    let sizes = [
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            ratio: 3f32,
+        },
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+            ratio: 3f32,
+        },
+        PoolSizeRatio {
+            descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+            ratio: 1f32,
+        },
+    ];
+    let mut descriptor_allocator = DescriptorAllocatorGrowable::default();
+    descriptor_allocator.init(device, material_count.max(1) as u32, &sizes)?;
+    Ok(descriptor_allocator)
+}
+
+//builds every mesh of the file on the CPU, ready to be uploaded or baked. The joint influences
+//of every mesh come back next to the meshes, empty for meshes without any
+fn read_gltf_meshes(path: &Path,
+                    gltf: &gltf::Document,
+                    buffers: &[gltf::buffer::Data]) -> EngineResult<(Vec<BakedMesh<'static>>, Vec<Vec<SkinVertex>>)> {
+    let invalid = |reason: String| EngineError::InvalidGltf {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let mut meshes = Vec::<BakedMesh>::new();
+    let mut mesh_skin_vertices = Vec::<Vec<SkinVertex>>::new();
     for mesh in gltf.meshes() {
-        let name = String::from(mesh.name().unwrap());
-        let mut surfaces = Vec::<GeoSurface>::new();
-        // clear the mesh arrays each mesh, we dont want to merge them by error
-        indices.clear();
-        vertices.clear();
+        //unnamed meshes are valid glTF, fall back to the index
+        let name = match mesh.name() {
+            Some(name) => String::from(name),
+            None => format!("mesh_{}", mesh.index()),
+        };
+        let mut surfaces = Vec::<BakedSurface>::new();
+        let mut skin_vertices = Vec::<SkinVertex>::new();
+        let mut morph_targets = Vec::<Vec<MorphTargetDelta>>::new();
+        let mut indices = Vec::<u32>::new();
+        let mut vertices = Vec::<Vertex>::new();
         for primitive in mesh.primitives() {
-            let new_surface = GeoSurface {
-                start_index: indices.len() as u32,
-                count: primitive.indices().unwrap().count() as u32
-            };
+            let index_accessor = &primitive.indices().ok_or_else(|| {
+                invalid(format!("a primitive of mesh {} has no indices", name))
+            })?;
+            let start_index = indices.len() as u32;
             let initial_vertex = vertices.len();
             //load indexes
-            let index_accessor = &primitive.indices().unwrap();
             indices.reserve(index_accessor.count());
             let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
-            let primitive_indices = reader.read_indices().unwrap();
+            let primitive_indices = reader.read_indices().ok_or_else(|| {
+                invalid(format!("the indices of mesh {} can not be read", name))
+            })?;
             for index in primitive_indices.into_u32() {
                 indices.push(index + initial_vertex as u32);
             }
 
             //load vertex positions
-            let position_accessor = &primitive.get(&Semantic::Positions).unwrap();
+            let position_accessor = &primitive.get(&Semantic::Positions).ok_or_else(|| {
+                invalid(format!("a primitive of mesh {} has no positions", name))
+            })?;
             vertices.reserve(position_accessor.count());
-            let primitive_positions = reader.read_positions().unwrap();
+            let primitive_positions = reader.read_positions().ok_or_else(|| {
+                invalid(format!("the positions of mesh {} can not be read", name))
+            })?;
             for position in primitive_positions {
                 vertices.push(Vertex {
                     position: position.into(),
+                    //white so the material color factors come through untouched
+                    color: (1f32, 1f32, 1f32, 1f32).into(),
                     ..Default::default()
                 })
             }
+            if indices[start_index as usize..].iter().any(|&index| index as usize >= vertices.len()) {
+                return Err(invalid(format!("a primitive of mesh {} has indices past its vertices", name)));
+            }
             //load vertex normals
-            if let Some(primitive_normals) = reader.read_normals() {
+            let primitive_normals = reader.read_normals();
+            let has_normals = primitive_normals.is_some();
+            if let Some(primitive_normals) = primitive_normals {
                 for (index, normal) in primitive_normals.enumerate() {
                     vertices[initial_vertex + index].normal = normal.into();
                 }
             }
+            //load vertex tangents
+            let primitive_tangents = reader.read_tangents();
+            let has_tangents = primitive_tangents.is_some();
+            if let Some(primitive_tangents) = primitive_tangents {
+                for (index, tangent) in primitive_tangents.enumerate() {
+                    vertices[initial_vertex + index].tangent = tangent.into();
+                }
+            }
             //load UVs
             if let Some(primitive_uv) = reader.read_tex_coords(0) {
                 for (index, uv) in primitive_uv.into_f32().enumerate() {
@@ -87,23 +553,397 @@ pub fn load_gltf_meshes<P: AsRef<Path> + ?Sized + Display>(device: &Device,
                     vertices[initial_vertex + index].color = color.into();
                 }
             }
+            //load joint influences, primitives without any are padded with unweighted vertices
+            if let (Some(primitive_joints), Some(primitive_weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                skin_vertices.resize(initial_vertex, SkinVertex::default());
+                for (joints, weights) in primitive_joints.into_u16().zip(primitive_weights.into_f32()) {
+                    skin_vertices.push(SkinVertex {
+                        joints: joints.map(u32::from),
+                        weights,
+                    });
+                }
+            }
+            //load morph targets, attributes a target leaves alone get zero deltas
+            for (target_index, (positions, normals, tangents)) in reader.read_morph_targets().enumerate() {
+                if morph_targets.len() <= target_index {
+                    morph_targets.push(Vec::new());
+                }
+                let deltas = &mut morph_targets[target_index];
+                deltas.resize(vertices.len(), MorphTargetDelta::default());
+                if let Some(positions) = positions {
+                    for (index, position) in positions.enumerate() {
+                        deltas[initial_vertex + index].position = cgmath::Vector3::from(position).extend(0f32);
+                    }
+                }
+                if let Some(normals) = normals {
+                    for (index, normal) in normals.enumerate() {
+                        deltas[initial_vertex + index].normal = cgmath::Vector3::from(normal).extend(0f32);
+                    }
+                }
+                if let Some(tangents) = tangents {
+                    for (index, tangent) in tangents.enumerate() {
+                        deltas[initial_vertex + index].tangent = cgmath::Vector3::from(tangent).extend(0f32);
+                    }
+                }
+            }
+            //glTF asks for flat normals when a primitive has none, which needs a vertex per corner
+            if !has_normals {
+                let remap = unweld(&mut indices[start_index as usize..], initial_vertex);
+                remap_vertices(&mut vertices, initial_vertex, &remap);
+                remap_vertex_data(&remap, initial_vertex, &mut skin_vertices, &mut morph_targets);
+                compute_normals(&indices[start_index as usize..], &mut vertices);
+            }
+            if !has_tangents {
+                let remap = compute_tangents(&mut indices[start_index as usize..], &mut vertices, initial_vertex);
+                remap_vertex_data(&remap, initial_vertex, &mut skin_vertices, &mut morph_targets);
+            }
             //add the submesh info to surfaces vector
-            surfaces.push(new_surface)
+            let count = indices.len() as u32 - start_index;
+            let material = primitive.material().index().map(|index| index as u32);
+            surfaces.push(BakedSurface::new(start_index, count, material, &indices, &vertices))
+        }
+        if !skin_vertices.is_empty() {
+            skin_vertices.resize(vertices.len(), SkinVertex::default());
         }
-        //painting vertex normals
-        cfg_if!{
-            if #[cfg(feature="vertex_normals")] {
-                for vertex in vertices.iter_mut() {
-                    vertex.color = vertex.normal.clone().extend(1f32);
+        let morph_weights = if morph_targets.is_empty() {
+            Vec::new()
+        } else {
+            let mut morph_weights = mesh.weights().map_or_else(Vec::new, <[f32]>::to_vec);
+            morph_weights.resize(morph_targets.len(), 0f32);
+            morph_weights
+        };
+        let morph_targets = morph_targets
+            .into_iter()
+            .flat_map(|mut deltas| {
+                deltas.resize(vertices.len(), MorphTargetDelta::default());
+                deltas
+            })
+            .collect::<Vec<MorphTargetDelta>>();
+        meshes.push(BakedMesh {
+            name,
+            indices: Cow::Owned(indices),
+            vertices: Cow::Owned(vertices),
+            surfaces,
+            morph_targets: Cow::Owned(morph_targets),
+            morph_weights,
+        });
+        mesh_skin_vertices.push(skin_vertices);
+    }
+    Ok((meshes, mesh_skin_vertices))
+}
+
+//surfaces whose material is out of range are drawn with the engine's default material
+//the skin and morph target data of a primitive whose vertices were split or reordered
+fn remap_vertex_data(remap: &[usize],
+                     first_vertex: usize,
+                     skin_vertices: &mut Vec<SkinVertex>,
+                     morph_targets: &mut [Vec<MorphTargetDelta>]) {
+    if skin_vertices.len() > first_vertex {
+        remap_vertices(skin_vertices, first_vertex, remap);
+    }
+    for deltas in morph_targets.iter_mut().filter(|deltas| deltas.len() > first_vertex) {
+        remap_vertices(deltas, first_vertex, remap);
+    }
+}
+
+fn upload_baked_mesh(engine: &mut VulkanEngine,
+                     mesh: &BakedMesh,
+                     materials: &[Rc<GLTFMaterial>]) -> EngineResult<Rc<RefCell<MeshAsset>>> {
+    let vertices: Cow<[Vertex]> = if cfg!(feature = "vertex_normals") {
+        //painting vertex normals, done here so baked caches do not depend on the feature
+        mesh.vertices
+            .iter()
+            .map(|vertex| Vertex {
+                color: vertex.normal.extend(1f32),
+                ..*vertex
+            })
+            .collect()
+    } else {
+        Cow::Borrowed(&mesh.vertices)
+    };
+    let mut mesh_buffers = upload_mesh(&engine.device, &mut engine.allocator, &mesh.indices, &vertices, engine.immediate_command_buffer, engine.immediate_fence, engine.graphics_queue)?;
+    if !mesh.morph_weights.is_empty() {
+        mesh_buffers.upload_morph_targets(&engine.device, &mut engine.allocator, &mesh.morph_targets, mesh.morph_weights.len() as u32, engine.immediate_command_buffer, engine.immediate_fence, engine.graphics_queue)?;
+    }
+    let surfaces = mesh
+        .surfaces
+        .iter()
+        .map(|surface| GeoSurface {
+            start_index: surface.start_index,
+            count: surface.count,
+            material: surface.material.and_then(|index| materials.get(index as usize).cloned()),
+            bounds: surface.bounds,
+        })
+        .collect::<Vec<GeoSurface>>();
+    Ok(Rc::new(RefCell::new(MeshAsset {
+        name: mesh.name.clone(),
+        bounds: mesh_bounds(&surfaces),
+        surfaces,
+        mesh_buffers,
+        morph_weights: mesh.morph_weights.clone(),
+    })))
+}
+
+fn load_gltf_skins(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Skin> {
+    gltf.skins()
+        .map(|skin| {
+            let joints = skin.joints().map(|joint| joint.index()).collect::<Vec<usize>>();
+            let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+            let mut inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(cgmath::Matrix4::from).collect(),
+                None => Vec::new(),
+            };
+            //missing matrices are identities
+            inverse_bind_matrices.resize(joints.len(), cgmath::Matrix4::identity());
+            Skin {
+                name: match skin.name() {
+                    Some(name) => String::from(name),
+                    None => format!("skin_{}", skin.index()),
+                },
+                joints,
+                inverse_bind_matrices,
+            }
+        })
+        .collect()
+}
+
+//channels that can not be played are skipped with a warning, the rest of the clip still plays
+fn load_gltf_animations(gltf: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<AnimationClip> {
+    let mut animations = Vec::<AnimationClip>::new();
+    for animation in gltf.animations() {
+        let name = match animation.name() {
+            Some(name) => String::from(name),
+            None => format!("animation_{}", animation.index()),
+        };
+        let mut channels = Vec::<AnimationChannel>::new();
+        let mut duration = 0f32;
+        for channel in animation.channels() {
+            let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let times = reader.read_inputs().map_or_else(Vec::new, |inputs| inputs.collect::<Vec<f32>>());
+            let values = match reader.read_outputs() {
+                Some(ReadOutputs::Translations(translations)) => {
+                    ChannelValues::Translations(translations.map(cgmath::Vector3::from).collect())
                 }
+                Some(ReadOutputs::Rotations(rotations)) => ChannelValues::Rotations(
+                    rotations
+                        .into_f32()
+                        .map(|[x, y, z, w]| cgmath::Quaternion::new(w, x, y, z))
+                        .collect(),
+                ),
+                Some(ReadOutputs::Scales(scales)) => ChannelValues::Scales(scales.map(cgmath::Vector3::from).collect()),
+                Some(ReadOutputs::MorphTargetWeights(weights)) => {
+                    //keys hold one weight per target of the mesh of the node, split them per target
+                    let target_count = channel
+                        .target()
+                        .node()
+                        .mesh()
+                        .and_then(|mesh| mesh.primitives().next())
+                        .map_or(0, |primitive| primitive.morph_targets().len());
+                    let weights = weights.into_f32().collect::<Vec<f32>>();
+                    ChannelValues::MorphWeights(
+                        (0..target_count)
+                            .map(|target| weights.iter().skip(target).step_by(target_count).copied().collect())
+                            .collect(),
+                    )
+                }
+                None => ChannelValues::Translations(Vec::new()),
+            };
+            let interpolation = match channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                gltf::animation::Interpolation::Step => Interpolation::Step,
+                gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+            };
+            let keys_per_time = match interpolation {
+                Interpolation::CubicSpline => 3,
+                _ => 1,
+            };
+            if times.is_empty() || values.len() != times.len() * keys_per_time {
+                log::warn!("Animation {} has a channel with {} times and {} values, skipping it", name, times.len(), values.len());
+                continue;
             }
+            duration = duration.max(times[times.len() - 1]);
+            channels.push(AnimationChannel {
+                node: channel.target().node().index(),
+                interpolation,
+                times,
+                values,
+            });
         }
-        let mesh_buffers = upload_mesh(device, allocator, &indices, &vertices, immediate_command_buffer, immediate_fence, immediate_queue);
-        meshes.push(Rc::new(RefCell::new(MeshAsset {
+        animations.push(AnimationClip {
             name,
-            surfaces,
-            mesh_buffers,
-        })));
+            channels,
+            duration,
+        });
     }
-    Some(meshes)
-}
\ No newline at end of file
+    animations
+}
+
+//one material instance per glTF material
+fn load_gltf_materials(engine: &mut VulkanEngine,
+                       gltf: &gltf::Document,
+                       images: &[vk::ImageView],
+                       descriptor_allocator: &mut DescriptorAllocatorGrowable) -> EngineResult<(Vec<Rc<GLTFMaterial>>, Option<AllocatedBuffer>)> {
+    let samplers = gltf
+        .textures()
+        .map(|texture| engine.get_sampler(&gltf_sampler_desc(&texture.sampler())))
+        .collect::<EngineResult<Vec<vk::Sampler>>>()?;
+    let texture_view = |texture: Option<gltf::texture::Info>| match texture {
+        Some(info) => (images[info.texture().source().index()], samplers[info.texture().index()]),
+        None => (engine.white_image.image_view, engine.default_sampler_linear),
+    };
+
+    let mut constants = Vec::<MaterialConstants>::new();
+    let mut passes = Vec::<(MaterialPass, MaterialTexture, MaterialTexture)>::new();
+    for material in gltf.materials() {
+        let pbr = material.pbr_metallic_roughness();
+        //masked materials discard fragments below the cutoff, the others never do
+        let alpha_cutoff = match material.alpha_mode() {
+            AlphaMode::Mask => material.alpha_cutoff().unwrap_or(0.5f32),
+            _ => 0f32,
+        };
+        constants.push(MaterialConstants {
+            color_factors: pbr.base_color_factor().into(),
+            metal_rough_factors: (pbr.metallic_factor(), pbr.roughness_factor(), alpha_cutoff, 0f32).into(),
+            ..Default::default()
+        });
+        let pass_type = match material.alpha_mode() {
+            AlphaMode::Blend => MaterialPass::Transparent,
+            _ => MaterialPass::MainColor,
+        };
+        passes.push((
+            pass_type,
+            texture_view(pbr.base_color_texture()),
+            texture_view(pbr.metallic_roughness_texture()),
+        ));
+    }
+
+    write_materials(engine, &constants, &passes, descriptor_allocator)
+}
+
+//glTF leaves the filters up to the renderer when they are not set, those get trilinear filtering
+fn gltf_sampler_desc(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let filter = |linear: bool| if linear { vk::Filter::LINEAR } else { vk::Filter::NEAREST };
+    let address_mode = |wrapping: WrappingMode| match wrapping {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => (false, false),
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapNearest) => (true, false),
+        Some(MinFilter::NearestMipmapLinear) => (false, true),
+        Some(MinFilter::LinearMipmapLinear) | None => (true, true),
+    };
+    //without mipmapping only the first level is sampled
+    let max_lod = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::Linear) => 0f32,
+        _ => vk::LOD_CLAMP_NONE,
+    };
+    SamplerDesc {
+        mag_filter: filter(!matches!(sampler.mag_filter(), Some(MagFilter::Nearest))),
+        min_filter: filter(min_filter),
+        mipmap_mode: if mipmap_mode {
+            vk::SamplerMipmapMode::LINEAR
+        } else {
+            vk::SamplerMipmapMode::NEAREST
+        },
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+        max_lod,
+        ..SamplerDesc::linear()
+    }
+}
+
+//an image view and the sampler it is read with
+type MaterialTexture = (vk::ImageView, vk::Sampler);
+
+//uploads the constants of every material into one uniform buffer, owned by the caller like the
+//descriptor sets, and writes a material instance for each
+fn write_materials(engine: &mut VulkanEngine,
+                   constants: &[MaterialConstants],
+                   passes: &[(MaterialPass, MaterialTexture, MaterialTexture)],
+                   descriptor_allocator: &mut DescriptorAllocatorGrowable) -> EngineResult<(Vec<Rc<GLTFMaterial>>, Option<AllocatedBuffer>)> {
+    if constants.is_empty() {
+        return Ok((Vec::new(), None));
+    }
+    let mut material_data_buffer = create_buffer(
+        &engine.device,
+        &mut engine.allocator,
+        size_of_val(constants) as vk::DeviceSize,
+        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        gpu_allocator::MemoryLocation::CpuToGpu,
+    )?;
+    presser::copy_from_slice_to_offset(constants, material_data_buffer.allocation.get_mut().unwrap(), 0).unwrap();
+    let data_buffer = material_data_buffer.buffer;
+
+    let mut materials = Vec::<Rc<GLTFMaterial>>::new();
+    for (index, &(pass_type, (color_image, color_sampler), (metal_rough_image, metal_rough_sampler))) in passes.iter().enumerate() {
+        let resources = MaterialResources {
+            color_image,
+            color_sampler,
+            metal_rough_image,
+            metal_rough_sampler,
+            data_buffer,
+            data_buffer_offset: (index * size_of::<MaterialConstants>()) as u32,
+        };
+        let data = engine.metal_rough_material.write_material(
+            &engine.device,
+            pass_type,
+            &resources,
+            descriptor_allocator,
+        )?;
+        materials.push(Rc::new(GLTFMaterial { data }));
+    }
+    Ok((materials, Some(material_data_buffer)))
+}
+
+//materials loaded without a LoadedGLTF to own them live as long as the engine
+fn hand_over_to_engine(engine: &mut VulkanEngine,
+                       mut descriptor_allocator: DescriptorAllocatorGrowable,
+                       material_data_buffer: Option<AllocatedBuffer>) {
+    for pool in descriptor_allocator.take_pools() {
+        engine.main_deletion_queue.push(pool);
+    }
+    if let Some(material_data_buffer) = material_data_buffer {
+        engine.main_deletion_queue.push(material_data_buffer);
+    }
+}
+
+//embedded images come from a buffer view or a base64 data URI, the others are files relative to
+//the glTF file. Images shared by several textures are only uploaded once
+fn load_gltf_image(engine: &mut VulkanEngine,
+                   base: &Path,
+                   buffers: &[gltf::buffer::Data],
+                   image: &gltf::Image) -> EngineResult<vk::ImageView> {
+    let name = match image.name() {
+        Some(name) => String::from(name),
+        None => format!("image_{}", image.index()),
+    };
+    match image.source() {
+        gltf::image::Source::View { view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            let encoded = &buffer[view.offset()..view.offset() + view.length()];
+            Ok(engine.load_image_bytes(&name, encoded)?.image_view)
+        }
+        gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => {
+            let encoded = decode_data_uri(uri).ok_or_else(|| EngineError::InvalidImage {
+                name: name.clone(),
+                reason: String::from("the data URI is not base64 encoded"),
+            })?;
+            Ok(engine.load_image_bytes(&name, &encoded)?.image_view)
+        }
+        gltf::image::Source::Uri { uri, .. } => {
+            let file = urlencoding::decode(uri).map_err(|e| EngineError::InvalidImage {
+                name: name.clone(),
+                reason: e.to_string(),
+            })?;
+            Ok(engine.load_image_file(base.join(file.as_ref()))?.image_view)
+        }
+    }
+}
+
+//payload of a data:[<mediatype>];base64,<data> URI
+pub fn decode_data_uri(uri: &str) -> Option<Vec<u8>> {
+    let (_, data) = uri.strip_prefix("data:")?.split_once(";base64,")?;
+    base64::decode(data).ok()
+}
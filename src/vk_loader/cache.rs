@@ -0,0 +1,323 @@
+use std::borrow::Cow;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::mem::{align_of, size_of, size_of_val};
+use std::path::{Path, PathBuf};
+use std::slice;
+use memmap2::Mmap;
+use xxhash_rust::xxh3::Xxh3;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_loader::{import_gltf_buffers, read_gltf_document, read_gltf_meshes};
+use crate::vk_types::bounds::Bounds;
+use crate::vk_types::gpu_mesh_buffers::MorphTargetDelta;
+use crate::vk_types::vertex::Vertex;
+
+//bump whenever the layout below or one of the stored types changes, older caches are then rebuilt
+pub const MESH_CACHE_VERSION: u32 = 4;
+const MESH_CACHE_MAGIC: [u8; 8] = *b"VKGMESH\0";
+//surfaces without a material are stored with this index
+const NO_MATERIAL: u32 = u32::MAX;
+
+//a surface as it is stored in the cache
+#[derive(Clone, Debug, PartialEq)]
+pub struct BakedSurface {
+    pub start_index: u32,
+    pub count: u32,
+    //index into the materials of the source file
+    pub material: Option<u32>,
+    pub bounds: Bounds,
+}
+
+impl BakedSurface {
+    pub fn new(start_index: u32, count: u32, material: Option<u32>, indices: &[u32], vertices: &[Vertex]) -> Self {
+        BakedSurface {
+            start_index,
+            count,
+            material,
+            bounds: Bounds::from_indexed(&indices[start_index as usize..(start_index + count) as usize], vertices),
+        }
+    }
+}
+
+//a mesh exactly as it is uploaded. Loaded from a cache the arrays point into the mapped file
+pub struct BakedMesh<'a> {
+    pub name: String,
+    pub indices: Cow<'a, [u32]>,
+    pub vertices: Cow<'a, [Vertex]>,
+    pub surfaces: Vec<BakedSurface>,
+    //one delta per vertex for every target, targets one after the other
+    pub morph_targets: Cow<'a, [MorphTargetDelta]>,
+    //default weights, one per target
+    pub morph_weights: Vec<f32>,
+}
+
+/// Types the cache stores as raw bytes.
+///
+/// # Safety
+/// Every bit pattern has to be a valid value and there must be no padding.
+unsafe trait CacheData: Copy + 'static {}
+unsafe impl CacheData for u8 {}
+unsafe impl CacheData for u32 {}
+unsafe impl CacheData for u64 {}
+unsafe impl CacheData for f32 {}
+unsafe impl CacheData for Vertex {}
+unsafe impl CacheData for MorphTargetDelta {}
+
+//arrays are aligned to their type within the file so they can be used straight from the mapping
+#[derive(Default)]
+struct CacheWriter {
+    bytes: Vec<u8>,
+}
+
+impl CacheWriter {
+    fn write_slice<T: CacheData>(&mut self, values: &[T]) {
+        self.bytes.resize(self.bytes.len().next_multiple_of(align_of::<T>()), 0);
+        let bytes = unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, size_of_val(values)) };
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn write<T: CacheData>(&mut self, value: T) {
+        self.write_slice(slice::from_ref(&value));
+    }
+}
+
+//every read returns None once the data runs out, so a truncated file is just a stale cache
+struct CacheReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CacheReader<'a> {
+    fn read_slice<T: CacheData>(&mut self, count: usize) -> Option<&'a [T]> {
+        let start = self.offset.checked_next_multiple_of(align_of::<T>())?;
+        let end = start.checked_add(count.checked_mul(size_of::<T>())?)?;
+        let bytes = self.bytes.get(start..end)?;
+        //mappings start on a page boundary, this only fails for data that was never mapped
+        if bytes.as_ptr().align_offset(align_of::<T>()) != 0 {
+            return None;
+        }
+        self.offset = end;
+        Some(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, count) })
+    }
+
+    fn read<T: CacheData>(&mut self) -> Option<T> {
+        self.read_slice::<T>(1).map(|values| values[0])
+    }
+}
+
+//a cache file mapped into memory. Opening it only checks the header, the meshes are read on demand
+pub struct MeshCache {
+    mmap: Mmap,
+    mesh_count: usize,
+}
+
+impl MeshCache {
+    //None when there is no cache or it was baked by another version or from another source
+    pub fn open(path: &Path, source_hash: u64) -> Option<MeshCache> {
+        let file = File::open(path).ok()?;
+        //caches are only written through a rename, a mapped file is never changed underneath us
+        let mmap = unsafe { Mmap::map(&file) }.ok()?;
+        let mut reader = CacheReader { bytes: &mmap, offset: 0 };
+        if reader.read_slice::<u8>(MESH_CACHE_MAGIC.len())? != MESH_CACHE_MAGIC
+            || reader.read::<u32>()? != MESH_CACHE_VERSION
+        {
+            return None;
+        }
+        let mesh_count = reader.read::<u32>()? as usize;
+        if reader.read::<u64>()? != source_hash {
+            return None;
+        }
+        Some(MeshCache { mmap, mesh_count })
+    }
+
+    //None when the file is corrupt
+    pub fn meshes(&self) -> Option<Vec<BakedMesh<'_>>> {
+        let mut reader = CacheReader {
+            bytes: &self.mmap,
+            offset: MESH_CACHE_MAGIC.len() + 2 * size_of::<u32>() + size_of::<u64>(),
+        };
+        //the counts come straight from the file, a corrupt one must not turn into a huge allocation
+        //before the reads below run out of data, so nothing is preallocated
+        let mut meshes = Vec::new();
+        for _ in 0..self.mesh_count {
+            let name_length = reader.read::<u32>()? as usize;
+            let name = String::from_utf8_lossy(reader.read_slice::<u8>(name_length)?).into_owned();
+            let index_count = reader.read::<u32>()? as usize;
+            let vertex_count = reader.read::<u32>()? as usize;
+            let surface_count = reader.read::<u32>()? as usize;
+            let morph_target_count = reader.read::<u32>()? as usize;
+            let mut surfaces = Vec::new();
+            for _ in 0..surface_count {
+                let start_index = reader.read::<u32>()?;
+                let count = reader.read::<u32>()?;
+                let material = reader.read::<u32>()?;
+                let origin = reader.read_slice::<f32>(3)?;
+                let extents = reader.read_slice::<f32>(3)?;
+                let sphere_radius = reader.read::<f32>()?;
+                if start_index as usize + count as usize > index_count {
+                    return None;
+                }
+                surfaces.push(BakedSurface {
+                    start_index,
+                    count,
+                    material: (material != NO_MATERIAL).then_some(material),
+                    bounds: Bounds {
+                        origin: (origin[0], origin[1], origin[2]).into(),
+                        extents: (extents[0], extents[1], extents[2]).into(),
+                        sphere_radius,
+                    },
+                });
+            }
+            let morph_weights = reader.read_slice::<f32>(morph_target_count)?.to_vec();
+            let indices = reader.read_slice::<u32>(index_count)?;
+            if indices.iter().any(|&index| index as usize >= vertex_count) {
+                return None;
+            }
+            let vertices = reader.read_slice::<Vertex>(vertex_count)?;
+            let morph_targets = reader.read_slice::<MorphTargetDelta>(morph_target_count.checked_mul(vertex_count)?)?;
+            meshes.push(BakedMesh {
+                name,
+                indices: Cow::Borrowed(indices),
+                vertices: Cow::Borrowed(vertices),
+                surfaces,
+                morph_targets: Cow::Borrowed(morph_targets),
+                morph_weights,
+            });
+        }
+        Some(meshes)
+    }
+}
+
+//caches sit next to their source, assets/basicmesh.glb is cached in assets/basicmesh.glb.meshcache
+pub fn mesh_cache_path(source: &Path) -> PathBuf {
+    let mut path = OsString::from(source.as_os_str());
+    path.push(".meshcache");
+    PathBuf::from(path)
+}
+
+//covers the bytes of the file and of every external buffer it references, read straight from
+//disk so the cache can be checked before any buffer is loaded. Embedded buffers are part of the
+//file already
+pub fn source_hash(path: &Path, source: &[u8], gltf: &gltf::Document) -> EngineResult<u64> {
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let mut hasher = Xxh3::new();
+    hasher.update(source);
+    for buffer in gltf.buffers() {
+        let gltf::buffer::Source::Uri(uri) = buffer.source() else {
+            continue;
+        };
+        if uri.starts_with("data:") {
+            continue;
+        }
+        let file = urlencoding::decode(uri).map_err(|e| EngineError::InvalidGltf {
+            path: path.to_path_buf(),
+            reason: format!("buffer {} has an invalid URI: {}", buffer.index(), e),
+        })?;
+        let buffer_path = base.join(file.as_ref());
+        let bytes = fs::read(&buffer_path).map_err(|e| EngineError::Gltf {
+            path: buffer_path,
+            source: gltf::Error::Io(e),
+        })?;
+        hasher.update(&bytes);
+    }
+    Ok(hasher.digest())
+}
+
+//written to a temporary file first, so a cache that exists is always complete
+pub fn write_mesh_cache(path: &Path, source_hash: u64, meshes: &[BakedMesh]) -> EngineResult<()> {
+    let mut writer = CacheWriter::default();
+    writer.write_slice(&MESH_CACHE_MAGIC);
+    writer.write(MESH_CACHE_VERSION);
+    writer.write(meshes.len() as u32);
+    writer.write(source_hash);
+    for mesh in meshes {
+        writer.write(mesh.name.len() as u32);
+        writer.write_slice(mesh.name.as_bytes());
+        writer.write(mesh.indices.len() as u32);
+        writer.write(mesh.vertices.len() as u32);
+        writer.write(mesh.surfaces.len() as u32);
+        writer.write(mesh.morph_weights.len() as u32);
+        for surface in mesh.surfaces.iter() {
+            writer.write(surface.start_index);
+            writer.write(surface.count);
+            writer.write(surface.material.unwrap_or(NO_MATERIAL));
+            writer.write_slice(AsRef::<[f32; 3]>::as_ref(&surface.bounds.origin));
+            writer.write_slice(AsRef::<[f32; 3]>::as_ref(&surface.bounds.extents));
+            writer.write(surface.bounds.sphere_radius);
+        }
+        writer.write_slice(&mesh.morph_weights);
+        writer.write_slice(&mesh.indices);
+        writer.write_slice(&mesh.vertices);
+        writer.write_slice(&mesh.morph_targets);
+    }
+
+    let cache_error = |source: std::io::Error| EngineError::MeshCache {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut temporary_path = OsString::from(path.as_os_str());
+    temporary_path.push(".tmp");
+    fs::write(&temporary_path, &writer.bytes).map_err(cache_error)?;
+    fs::rename(&temporary_path, path).map_err(cache_error)
+}
+
+//files a bake run went through, by outcome
+#[derive(Copy, Clone, Debug, Default)]
+pub struct BakeReport {
+    pub baked: usize,
+    pub up_to_date: usize,
+    pub failed: usize,
+}
+
+//bakes the meshes of one glTF file, returns false when its cache was already up to date. Needs
+//no GPU, images and materials are left alone
+pub fn bake_gltf_meshes(path: &Path, force: bool) -> EngineResult<bool> {
+    let (source, gltf, blob) = read_gltf_document(path)?;
+    let source_hash = source_hash(path, &source, &gltf)?;
+    let cache_path = mesh_cache_path(path);
+    if !force && MeshCache::open(&cache_path, source_hash).is_some_and(|cache| cache.meshes().is_some()) {
+        return Ok(false);
+    }
+    let buffers = import_gltf_buffers(path, &gltf, blob)?;
+    let (meshes, _) = read_gltf_meshes(path, &gltf, &buffers)?;
+    write_mesh_cache(&cache_path, source_hash, &meshes)?;
+    Ok(true)
+}
+
+//bakes every .glb and .gltf file under dir. A file that fails is reported and skipped, only an
+//unreadable directory stops the run
+pub fn bake_mesh_caches(dir: &Path, force: bool) -> EngineResult<BakeReport> {
+    let mut report = BakeReport::default();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let directory_error = |source: std::io::Error| EngineError::AssetDirectory {
+            path: dir.clone(),
+            source,
+        };
+        for entry in fs::read_dir(&dir).map_err(directory_error)? {
+            let path = entry.map_err(directory_error)?.path();
+            if path.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let is_gltf = path.extension().is_some_and(|extension| {
+                extension.eq_ignore_ascii_case("glb") || extension.eq_ignore_ascii_case("gltf")
+            });
+            if !is_gltf {
+                continue;
+            }
+            match bake_gltf_meshes(&path, force) {
+                Ok(true) => {
+                    log::info!("Baked {}", path.display());
+                    report.baked += 1;
+                }
+                Ok(false) => report.up_to_date += 1,
+                Err(e) => {
+                    log::error!("{}", e);
+                    report.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(report)
+}
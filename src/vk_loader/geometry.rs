@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use cgmath::InnerSpace;
+use crate::vk_types::vertex::Vertex;
+
+//gives every index its own copy of the vertex it points at, from first_vertex on, so no two
+//triangles share a vertex anymore. Returns the source of every new vertex, pass it to
+//remap_vertices for the vertices and any data kept next to them
+pub fn unweld(indices: &mut [u32], first_vertex: usize) -> Vec<usize> {
+    indices
+        .iter_mut()
+        .enumerate()
+        .map(|(new_index, index)| {
+            let source = *index as usize - first_vertex;
+            *index = (first_vertex + new_index) as u32;
+            source
+        })
+        .collect()
+}
+
+pub fn remap_vertices<T: Copy>(values: &mut Vec<T>, first_vertex: usize, remap: &[usize]) {
+    let source = values.split_off(first_vertex);
+    values.extend(remap.iter().map(|&index| source[index]));
+}
+
+//area weighted average of the triangles around each vertex the indices use. Unwelded vertices
+//only have one triangle, which makes the normals flat
+pub fn compute_normals(indices: &[u32], vertices: &mut [Vertex]) {
+    for &index in indices {
+        vertices[index as usize].normal = (0f32, 0f32, 0f32).into();
+    }
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize].position);
+        //its length is twice the area of the triangle
+        let normal = (b - a).cross(c - a);
+        for &index in triangle {
+            vertices[index as usize].normal += normal;
+        }
+    }
+    for &index in indices {
+        let vertex = &mut vertices[index as usize];
+        if vertex.normal.magnitude2() > 0f32 {
+            vertex.normal = vertex.normal.normalize();
+        }
+    }
+}
+
+//the triangles as mikktspace sees them, it hands back a tangent for every corner
+struct TangentSpace<'a> {
+    indices: &'a [u32],
+    vertices: &'a [Vertex],
+    tangents: Vec<[f32; 4]>,
+}
+
+impl TangentSpace<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for TangentSpace<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position.into()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.into()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let vertex = self.vertex(face, vert);
+        [vertex.uv_x, vertex.uv_y]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = tangent;
+    }
+}
+
+//any direction along the surface, for corners mikktspace has no tangent for
+fn fallback_tangent(normal: cgmath::Vector3<f32>) -> cgmath::Vector4<f32> {
+    let axis = if normal.x.abs() < 0.9f32 {
+        cgmath::Vector3::unit_x()
+    } else {
+        cgmath::Vector3::unit_y()
+    };
+    let tangent = axis - normal * normal.dot(axis);
+    if tangent.magnitude2() > 0f32 {
+        tangent.normalize().extend(1f32)
+    } else {
+        axis.extend(1f32)
+    }
+}
+
+//MikkTSpace tangents for the triangles of indices, which use the vertices from first_vertex on.
+//Corners that share a vertex but not a tangent, like the two sides of a mirrored UV seam, each get
+//a vertex of their own, vertices the indices do not use are dropped. The vertices are remapped
+//already, the returned sources work like the ones from unweld for any data kept next to them
+pub fn compute_tangents(indices: &mut [u32], vertices: &mut Vec<Vertex>, first_vertex: usize) -> Vec<usize> {
+    let mut geometry = TangentSpace {
+        indices,
+        vertices,
+        tangents: indices
+            .iter()
+            .map(|&index| fallback_tangent(vertices[index as usize].normal).into())
+            .collect(),
+    };
+    //only fails without triangles, every corner keeps its fallback then
+    bevy_mikktspace::generate_tangents(&mut geometry);
+    let corner_tangents = geometry.tangents;
+
+    let mut welded = HashMap::new();
+    let mut remap = Vec::new();
+    let mut tangents = Vec::new();
+    for (index, tangent) in indices.iter_mut().zip(corner_tangents) {
+        let source = *index as usize - first_vertex;
+        let key = (source, tangent.map(f32::to_bits));
+        let welded_index = *welded.entry(key).or_insert_with(|| {
+            remap.push(source);
+            tangents.push(tangent);
+            remap.len() - 1
+        });
+        *index = (first_vertex + welded_index) as u32;
+    }
+    remap_vertices(vertices, first_vertex, &remap);
+    for (vertex, tangent) in vertices[first_vertex..].iter_mut().zip(tangents) {
+        vertex.tangent = tangent.into();
+    }
+    remap
+}
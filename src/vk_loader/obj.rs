@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use cfg_if::cfg_if;
+use crate::vk_engine::VulkanEngine;
+use crate::vk_error::{EngineError, EngineResult};
+use crate::vk_loader::{compute_normals, compute_tangents, hand_over_to_engine, mesh_bounds, init_material_allocator, write_materials, GLTFMaterial, GeoSurface, MaterialTexture, MeshAsset};
+use crate::vk_materials::MaterialConstants;
+use crate::vk_types::bounds::Bounds;
+use crate::vk_types::gpu_mesh_buffers::upload_mesh;
+use crate::vk_types::material::MaterialPass;
+use crate::vk_types::vertex::Vertex;
+
+//loads a whole OBJ file as one mesh with a surface per object/group and material. The MTL
+//materials are turned into metallic-roughness ones and live as long as the engine
+pub fn load_obj_meshes<P: AsRef<Path> + ?Sized>(engine: &mut VulkanEngine,
+                       file_path: &P) -> EngineResult<Rc<RefCell<MeshAsset>>> {
+    let path = file_path.as_ref();
+    log::info!("Loading OBJ: {}", path.display());
+    //triangulated, with a single index for positions, normals and UVs like the GPU wants
+    let (models, obj_materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|source| EngineError::Obj {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    //a missing or broken MTL file only costs the materials
+    let obj_materials = obj_materials.unwrap_or_else(|e| {
+        log::warn!("Failed to load the materials of {}: {}", path.display(), e);
+        Vec::new()
+    });
+    let base = path.parent().unwrap_or_else(|| Path::new("./"));
+    let materials = load_obj_materials(engine, base, &obj_materials)?;
+
+    let mut surfaces = Vec::<GeoSurface>::new();
+    let mut indices = Vec::<u32>::new();
+    let mut vertices = Vec::<Vertex>::new();
+    for model in models.iter() {
+        let mesh = &model.mesh;
+        if mesh.indices.is_empty() {
+            continue;
+        }
+        let initial_vertex = vertices.len();
+        let start_index = indices.len();
+        surfaces.push(GeoSurface {
+            start_index: start_index as u32,
+            count: mesh.indices.len() as u32,
+            material: mesh.material_id.and_then(|index| materials.get(index).cloned()),
+            ..Default::default()
+        });
+        //load indexes
+        indices.extend(mesh.indices.iter().map(|index| index + initial_vertex as u32));
+
+        //load vertex positions
+        for position in mesh.positions.chunks_exact(3) {
+            vertices.push(Vertex {
+                position: (position[0], position[1], position[2]).into(),
+                //white so the material color factors come through untouched
+                color: (1f32, 1f32, 1f32, 1f32).into(),
+                ..Default::default()
+            })
+        }
+        //load vertex normals
+        for (index, normal) in mesh.normals.chunks_exact(3).enumerate() {
+            vertices[initial_vertex + index].normal = (normal[0], normal[1], normal[2]).into();
+        }
+        //load UVs, OBJ puts v = 0 at the bottom of the image
+        for (index, uv) in mesh.texcoords.chunks_exact(2).enumerate() {
+            vertices[initial_vertex + index].uv_x = uv[0];
+            vertices[initial_vertex + index].uv_y = 1f32 - uv[1];
+        }
+        //load vertex colors
+        for (index, color) in mesh.vertex_color.chunks_exact(3).enumerate() {
+            vertices[initial_vertex + index].color = (color[0], color[1], color[2], 1f32).into();
+        }
+        //OBJ has no rule for missing normals, smooth ones suit the usual exports best
+        if mesh.normals.is_empty() {
+            compute_normals(&indices[start_index..], &mut vertices);
+        }
+        compute_tangents(&mut indices[start_index..], &mut vertices, initial_vertex);
+    }
+    for surface in surfaces.iter_mut() {
+        let surface_indices = &indices[surface.start_index as usize..(surface.start_index + surface.count) as usize];
+        surface.bounds = Bounds::from_indexed(surface_indices, &vertices);
+    }
+    if surfaces.is_empty() {
+        return Err(EngineError::InvalidObj {
+            path: path.to_path_buf(),
+            reason: String::from("the file has no faces"),
+        });
+    }
+    //painting vertex normals
+    cfg_if!{
+        if #[cfg(feature="vertex_normals")] {
+            for vertex in vertices.iter_mut() {
+                vertex.color = vertex.normal.clone().extend(1f32);
+            }
+        }
+    }
+    let mesh_buffers = upload_mesh(&engine.device, &mut engine.allocator, &indices, &vertices, engine.immediate_command_buffer, engine.immediate_fence, engine.graphics_queue)?;
+    let name = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    };
+    Ok(Rc::new(RefCell::new(MeshAsset {
+        name,
+        bounds: mesh_bounds(&surfaces),
+        surfaces,
+        mesh_buffers,
+        morph_weights: Vec::new(),
+    })))
+}
+
+//MTL describes Phong materials, they are mapped onto metallic-roughness the same way for every file
+fn load_obj_materials(engine: &mut VulkanEngine,
+                      base: &Path,
+                      obj_materials: &[tobj::Material]) -> EngineResult<Vec<Rc<GLTFMaterial>>> {
+    let mut constants = Vec::<MaterialConstants>::new();
+    let mut passes = Vec::<(MaterialPass, MaterialTexture, MaterialTexture)>::new();
+    for material in obj_materials {
+        let diffuse = material.diffuse.unwrap_or([1f32; 3]);
+        let alpha = material.dissolve.unwrap_or(1f32);
+        constants.push(MaterialConstants {
+            color_factors: (diffuse[0], diffuse[1], diffuse[2], alpha).into(),
+            metal_rough_factors: (0f32, shininess_to_roughness(material.shininess), 0f32, 0f32).into(),
+            ..Default::default()
+        });
+        let pass_type = if alpha < 1f32 {
+            MaterialPass::Transparent
+        } else {
+            MaterialPass::MainColor
+        };
+        let color_image = match &material.diffuse_texture {
+            Some(texture) => match engine.load_image_file(base.join(texture)) {
+                Ok(image) => image.image_view,
+                Err(e) => {
+                    log::warn!("{}, using the error texture instead", e);
+                    engine.error_checkerboard_image.image_view
+                }
+            },
+            None => engine.white_image.image_view,
+        };
+        let sampler = engine.default_sampler_linear;
+        passes.push((pass_type, (color_image, sampler), (engine.white_image.image_view, sampler)));
+    }
+    let mut descriptor_allocator = init_material_allocator(&engine.device, constants.len())?;
+    let (materials, material_data_buffer) = write_materials(engine, &constants, &passes, &mut descriptor_allocator)?;
+    hand_over_to_engine(engine, descriptor_allocator, material_data_buffer);
+    Ok(materials)
+}
+
+//the usual Blinn-Phong exponent to GGX roughness approximation
+fn shininess_to_roughness(shininess: Option<f32>) -> f32 {
+    match shininess {
+        Some(shininess) => (2f32 / (shininess.max(0f32) + 2f32)).sqrt(),
+        None => 1f32,
+    }
+}
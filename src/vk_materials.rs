@@ -0,0 +1,177 @@
+use crate::vk_descriptors::{DescriptorAllocatorGrowable, DescriptorSetLayoutBuilder, DescriptorWriter};
+use crate::vk_error::EngineResult;
+use crate::vk_pipelines;
+use crate::vk_pipelines::PipelineBuilder;
+use crate::vk_types::gpu_draw_push_constants::GPUDrawPushConstants;
+use crate::vk_types::material::{MaterialInstance, MaterialPass, MaterialPipeline};
+use ash::{vk, Device};
+use std::ffi::CString;
+use std::mem::size_of;
+use std::slice;
+
+//uniform buffer contents of one material, padded to 256 bytes so materials can share a buffer
+//without breaking minUniformBufferOffsetAlignment
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MaterialConstants {
+    pub color_factors: cgmath::Vector4<f32>,
+    //x metallic, y roughness, z alpha cutoff (0 disables it)
+    pub metal_rough_factors: cgmath::Vector4<f32>,
+    pub extra: [cgmath::Vector4<f32>; 14],
+}
+
+impl Default for MaterialConstants {
+    fn default() -> Self {
+        MaterialConstants {
+            color_factors: (1f32, 1f32, 1f32, 1f32).into(),
+            metal_rough_factors: (1f32, 0.5f32, 0f32, 0f32).into(),
+            extra: [(0f32, 0f32, 0f32, 0f32).into(); 14],
+        }
+    }
+}
+
+//everything bound to a material's descriptor set
+pub struct MaterialResources {
+    pub color_image: vk::ImageView,
+    pub color_sampler: vk::Sampler,
+    pub metal_rough_image: vk::ImageView,
+    pub metal_rough_sampler: vk::Sampler,
+    pub data_buffer: vk::Buffer,
+    pub data_buffer_offset: u32,
+}
+
+//PBR metallic-roughness material as described by glTF. Owns one pipeline per pass, every
+//instance only gets its own descriptor set
+pub struct GLTFMetallicRoughness {
+    pub opaque_pipeline: MaterialPipeline,
+    pub transparent_pipeline: MaterialPipeline,
+    pub material_layout: vk::DescriptorSetLayout,
+    pub writer: DescriptorWriter,
+}
+
+impl GLTFMetallicRoughness {
+    pub fn build_pipelines(
+        device: &Device,
+        gpu_scene_data_layout: vk::DescriptorSetLayout,
+        draw_image_format: &vk::Format,
+        depth_image_format: vk::Format,
+    ) -> EngineResult<Self> {
+        let mesh_frag_shader = vk_pipelines::load_shader_module("shaders/mesh_frag.spv", device)?;
+        let mesh_vertex_shader = vk_pipelines::load_shader_module("shaders/mesh_vert.spv", device)?;
+
+        let matrix_range = vk::PushConstantRange::builder()
+            .offset(0)
+            .size(size_of::<GPUDrawPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::VERTEX)
+            .build();
+
+        let mut layout_builder = DescriptorSetLayoutBuilder::default();
+        layout_builder.add_binding(0, vk::DescriptorType::UNIFORM_BUFFER);
+        layout_builder.add_binding(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        layout_builder.add_binding(2, vk::DescriptorType::COMBINED_IMAGE_SAMPLER);
+        let material_layout = layout_builder.build(
+            device,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+        )?;
+
+        //set 0 is the scene data, set 1 the material
+        let layouts = [gpu_scene_data_layout, material_layout];
+        let mesh_layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&layouts)
+            .push_constant_ranges(slice::from_ref(&matrix_range))
+            .build();
+        let new_layout = unsafe { device.create_pipeline_layout(&mesh_layout_info, None)? };
+
+        let mut pipeline_builder = PipelineBuilder::default();
+        let shader_entry_name = CString::new("main").unwrap();
+        pipeline_builder.set_shaders(
+            mesh_vertex_shader,
+            mesh_frag_shader,
+            &shader_entry_name,
+            &shader_entry_name,
+        );
+        pipeline_builder.set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+        pipeline_builder.set_polygon_mode(vk::PolygonMode::FILL);
+        pipeline_builder.set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE);
+        pipeline_builder.set_multisampling_none();
+        pipeline_builder.disable_blending();
+        pipeline_builder.enable_depth_test(true, vk::CompareOp::GREATER_OR_EQUAL);
+
+        //render format
+        pipeline_builder.set_color_attachment_format(draw_image_format);
+        pipeline_builder.set_depth_format(depth_image_format);
+
+        //use the layout we created
+        pipeline_builder.pipeline_layout = new_layout;
+
+        //finally build the pipeline
+        let opaque_pipeline = pipeline_builder.build_pipeline(device)?;
+
+        //create the transparent variant, it blends and does not write depth
+        pipeline_builder.enable_blending_alphablend();
+        pipeline_builder.enable_depth_test(false, vk::CompareOp::GREATER_OR_EQUAL);
+        let transparent_pipeline = pipeline_builder.build_pipeline(device)?;
+
+        unsafe {
+            device.destroy_shader_module(mesh_frag_shader, None);
+            device.destroy_shader_module(mesh_vertex_shader, None);
+        }
+
+        Ok(GLTFMetallicRoughness {
+            opaque_pipeline: MaterialPipeline {
+                pipeline: opaque_pipeline,
+                layout: new_layout,
+            },
+            transparent_pipeline: MaterialPipeline {
+                pipeline: transparent_pipeline,
+                layout: new_layout,
+            },
+            material_layout,
+            writer: DescriptorWriter::default(),
+        })
+    }
+
+    pub fn write_material(
+        &mut self,
+        device: &Device,
+        pass: MaterialPass,
+        resources: &MaterialResources,
+        descriptor_allocator: &mut DescriptorAllocatorGrowable,
+    ) -> EngineResult<MaterialInstance> {
+        let pipeline = match pass {
+            MaterialPass::Transparent => self.transparent_pipeline,
+            MaterialPass::MainColor => self.opaque_pipeline,
+        };
+        let material_set = descriptor_allocator.allocate(device, self.material_layout)?;
+
+        self.writer.clear();
+        self.writer.write_buffer(
+            0,
+            resources.data_buffer,
+            size_of::<MaterialConstants>() as vk::DeviceSize,
+            resources.data_buffer_offset as vk::DeviceSize,
+            vk::DescriptorType::UNIFORM_BUFFER,
+        );
+        self.writer.write_image(
+            1,
+            resources.color_image,
+            resources.color_sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        self.writer.write_image(
+            2,
+            resources.metal_rough_image,
+            resources.metal_rough_sampler,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+        );
+        self.writer.update_set(device, material_set);
+
+        Ok(MaterialInstance {
+            pipeline,
+            material_set,
+            pass_type: pass,
+        })
+    }
+}
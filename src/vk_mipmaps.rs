@@ -0,0 +1,308 @@
+use crate::vk_descriptors::{DescriptorAllocator, DescriptorSetLayoutBuilder, DescriptorWriter, PoolSizeRatio};
+use crate::vk_error::EngineResult;
+use crate::vk_images::ImageState;
+use crate::vk_types::AllocatedImage;
+use crate::{vk_images, vk_init, vk_pipelines};
+use ash::vk::PipelineCache;
+use ash::{vk, Device, Instance};
+use std::ffi::CString;
+use std::slice;
+
+//must match local_size_x and local_size_y in mipmap.comp
+const MIPMAP_GROUP_SIZE: u32 = 8;
+
+//number of levels in a full mip chain, down to 1x1
+pub fn mip_levels(extent: vk::Extent3D) -> u32 {
+    extent.width.max(extent.height).max(1).ilog2() + 1
+}
+
+fn mip_extent(extent: vk::Extent3D, level: u32) -> vk::Extent2D {
+    vk::Extent2D {
+        width: (extent.width >> level).max(1),
+        height: (extent.height >> level).max(1),
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MipmapMethod {
+    //vkCmdBlitImage2 from each level into the next
+    Blit,
+    //mipmap.comp averages 2x2 texels of each level into the next, for formats that cannot be
+    //blitted with linear filtering
+    Compute,
+}
+
+impl MipmapMethod {
+    //what the image has to be created with on top of its own usage
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        match self {
+            MipmapMethod::Blit => vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            MipmapMethod::Compute => vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE,
+        }
+    }
+}
+
+//per-level views and descriptor sets of one compute generated mip chain. They have to live until
+//the commands recorded with them are done, destroy them after that
+pub struct ComputeMipChain {
+    views: Vec<vk::ImageView>,
+    descriptor_allocator: DescriptorAllocator,
+    //set i reads level i and writes level i + 1
+    sets: Vec<vk::DescriptorSet>,
+}
+
+impl ComputeMipChain {
+    pub fn destroy(mut self, device: &Device) {
+        self.descriptor_allocator.destroy_pool(device);
+        for view in self.views {
+            unsafe { device.destroy_image_view(view, None) };
+        }
+    }
+}
+
+//fills the mip chain of 2D color images from their first level
+pub struct MipmapGenerator {
+    instance: Instance,
+    physical_device: vk::PhysicalDevice,
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+    pub descriptor_layout: vk::DescriptorSetLayout,
+}
+
+impl MipmapGenerator {
+    pub fn build(instance: &Instance, physical_device: vk::PhysicalDevice, device: &Device) -> EngineResult<Self> {
+        let mut builder = DescriptorSetLayoutBuilder::default();
+        builder.add_binding(0, vk::DescriptorType::SAMPLED_IMAGE);
+        builder.add_binding(1, vk::DescriptorType::STORAGE_IMAGE);
+        let descriptor_layout = builder.build(device, vk::ShaderStageFlags::COMPUTE)?;
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder().set_layouts(slice::from_ref(&descriptor_layout));
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+
+        let mipmap_shader = vk_pipelines::load_shader_module("./shaders/mipmap_comp.spv", device)?;
+        let shader_entry = CString::new("main").unwrap();
+        let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(mipmap_shader)
+            .name(&shader_entry)
+            .build();
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+            .layout(layout)
+            .stage(stage_info)
+            .build();
+        let pipeline = unsafe {
+            device
+                .create_compute_pipelines(
+                    PipelineCache::null(),
+                    slice::from_ref(&pipeline_create_info),
+                    None,
+                )
+                .map_err(|(_, result)| result)?[0]
+        };
+        unsafe { device.destroy_shader_module(mipmap_shader, None) };
+
+        Ok(MipmapGenerator {
+            instance: instance.clone(),
+            physical_device,
+            pipeline,
+            layout,
+            descriptor_layout,
+        })
+    }
+
+    //None when the format can neither be blitted with linear filtering nor written from a compute
+    //shader, images in that format can only have one level
+    pub fn method(&self, format: vk::Format) -> Option<MipmapMethod> {
+        let features = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+                .optimal_tiling_features
+        };
+        let blit = vk::FormatFeatureFlags::BLIT_SRC
+            | vk::FormatFeatureFlags::BLIT_DST
+            | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+        let compute = vk::FormatFeatureFlags::SAMPLED_IMAGE | vk::FormatFeatureFlags::STORAGE_IMAGE;
+        if features.contains(blit) {
+            Some(MipmapMethod::Blit)
+        } else if features.contains(compute) {
+            Some(MipmapMethod::Compute)
+        } else {
+            None
+        }
+    }
+
+    //views and descriptor sets record_compute needs, they do not depend on the command buffer
+    pub fn prepare_compute(&self, device: &Device, image: &AllocatedImage) -> EngineResult<ComputeMipChain> {
+        let level_count = image.mip_levels.saturating_sub(1);
+        let mut descriptor_allocator = DescriptorAllocator::default();
+        let ratios = [
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                ratio: 1f32,
+            },
+            PoolSizeRatio {
+                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                ratio: 1f32,
+            },
+        ];
+        descriptor_allocator.init_pool(device, level_count.max(1), &ratios)?;
+        let mut chain = ComputeMipChain {
+            views: Vec::with_capacity(image.mip_levels as usize),
+            descriptor_allocator,
+            sets: Vec::with_capacity(level_count as usize),
+        };
+        for level in 0..image.mip_levels {
+            let mut view_info = vk_init::image_view_create_info(
+                image.image_format,
+                image.image,
+                vk::ImageAspectFlags::COLOR,
+                vk::ImageViewType::TYPE_2D,
+                1,
+                1,
+            );
+            view_info.subresource_range.base_mip_level = level;
+            match unsafe { device.create_image_view(&view_info, None) } {
+                Ok(view) => chain.views.push(view),
+                Err(e) => {
+                    chain.destroy(device);
+                    return Err(e.into());
+                }
+            }
+        }
+        for level in 0..level_count as usize {
+            let set = match chain.descriptor_allocator.allocate(device, self.descriptor_layout) {
+                Ok(set) => set,
+                Err(e) => {
+                    chain.destroy(device);
+                    return Err(e);
+                }
+            };
+            let mut writer = DescriptorWriter::default();
+            writer.write_image(
+                0,
+                chain.views[level],
+                vk::Sampler::null(),
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::DescriptorType::SAMPLED_IMAGE,
+            );
+            writer.write_image(
+                1,
+                chain.views[level + 1],
+                vk::Sampler::null(),
+                vk::ImageLayout::GENERAL,
+                vk::DescriptorType::STORAGE_IMAGE,
+            );
+            writer.update_set(device, set);
+            chain.sets.push(set);
+        }
+        Ok(chain)
+    }
+
+    //the first level must be in TRANSFER_DST_OPTIMAL layout, the rest UNDEFINED or TRANSFER_DST_OPTIMAL.
+    //Every level ends up in SHADER_READ_ONLY_OPTIMAL layout
+    pub fn record_blit(&self, device: &Device, cmd: vk::CommandBuffer, image: &AllocatedImage) {
+        for level in 1..image.mip_levels {
+            vk_images::transition_image_levels(
+                device,
+                cmd,
+                image.image,
+                level - 1,
+                1,
+                ImageState::TRANSFER_DST,
+                ImageState::TRANSFER_SRC,
+            );
+            vk_images::transition_image_levels(
+                device,
+                cmd,
+                image.image,
+                level,
+                1,
+                ImageState::UNDEFINED,
+                ImageState::TRANSFER_DST,
+            );
+            vk_images::blit_image_level(
+                device,
+                cmd,
+                image.image,
+                level - 1,
+                image.image,
+                level,
+                mip_extent(image.image_extent, level - 1),
+                mip_extent(image.image_extent, level),
+            );
+        }
+        let last_level = image.mip_levels - 1;
+        if last_level > 0 {
+            vk_images::transition_image_levels(
+                device,
+                cmd,
+                image.image,
+                0,
+                last_level,
+                ImageState::TRANSFER_SRC,
+                ImageState::SHADER_READ,
+            );
+        }
+        vk_images::transition_image_levels(
+            device,
+            cmd,
+            image.image,
+            last_level,
+            1,
+            ImageState::TRANSFER_DST,
+            ImageState::SHADER_READ,
+        );
+    }
+
+    //same layouts as record_blit
+    pub fn record_compute(&self, device: &Device, cmd: vk::CommandBuffer, image: &AllocatedImage, chain: &ComputeMipChain) {
+        vk_images::transition_image_levels(
+            device,
+            cmd,
+            image.image,
+            0,
+            1,
+            ImageState::TRANSFER_DST,
+            ImageState::SHADER_READ,
+        );
+        unsafe { device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline) };
+        for level in 1..image.mip_levels {
+            vk_images::transition_image_levels(
+                device,
+                cmd,
+                image.image,
+                level,
+                1,
+                ImageState::UNDEFINED,
+                ImageState::COMPUTE_WRITE,
+            );
+            let extent = mip_extent(image.image_extent, level);
+            unsafe {
+                device.cmd_bind_descriptor_sets(
+                    cmd,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.layout,
+                    0,
+                    slice::from_ref(&chain.sets[level as usize - 1]),
+                    &[],
+                );
+                device.cmd_dispatch(
+                    cmd,
+                    extent.width.div_ceil(MIPMAP_GROUP_SIZE),
+                    extent.height.div_ceil(MIPMAP_GROUP_SIZE),
+                    1,
+                );
+            }
+            //also makes the level visible to the next dispatch
+            vk_images::transition_image_levels(
+                device,
+                cmd,
+                image.image,
+                level,
+                1,
+                ImageState::COMPUTE_WRITE,
+                ImageState::SHADER_READ,
+            );
+        }
+    }
+}
@@ -1,22 +1,30 @@
+use crate::vk_error::{EngineError, EngineResult};
 use ash::{vk, Device};
 use core::slice;
 use std::ffi::CStr;
 use std::path::Path;
 use std::ptr::null;
 
-pub fn load_shader_module(file_path: impl AsRef<Path>, device: &Device) -> vk::ShaderModule {
-    let mut file = std::fs::File::open(file_path).unwrap();
-
-    let byte_code_aligned = ash::util::read_spv(&mut file).unwrap();
+pub fn load_shader_module(
+    file_path: impl AsRef<Path>,
+    device: &Device,
+) -> EngineResult<vk::ShaderModule> {
+    let file_path = file_path.as_ref();
+    let mut file = std::fs::File::open(file_path).map_err(|source| EngineError::ShaderFile {
+        path: file_path.to_path_buf(),
+        source,
+    })?;
+
+    let byte_code_aligned =
+        ash::util::read_spv(&mut file).map_err(|source| EngineError::InvalidSpirv {
+            path: file_path.to_path_buf(),
+            source,
+        })?;
     let shader_create_info = vk::ShaderModuleCreateInfo::builder()
         .code(&byte_code_aligned)
         .build();
 
-    unsafe {
-        device
-            .create_shader_module(&shader_create_info, None)
-            .unwrap()
-    }
+    Ok(unsafe { device.create_shader_module(&shader_create_info, None)? })
 }
 
 #[derive(Default)]
@@ -33,7 +41,7 @@ pub struct PipelineBuilder {
 }
 
 impl PipelineBuilder {
-    pub fn build_pipeline(mut self, device: &Device) -> vk::Pipeline {
+    pub fn build_pipeline(&mut self, device: &Device) -> EngineResult<vk::Pipeline> {
         let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
             .viewport_count(1)
             .scissor_count(1)
@@ -67,15 +75,15 @@ impl PipelineBuilder {
 
         let pipeline_info = pipeline_info_builder.dynamic_state(&dynamic_info).build();
 
-        unsafe {
+        Ok(unsafe {
             device
                 .create_graphics_pipelines(
                     vk::PipelineCache::null(),
                     slice::from_ref(&pipeline_info),
                     None,
                 )
-                .unwrap()[0]
-        }
+                .map_err(|(_, result)| result)?[0]
+        })
     }
 
     pub fn set_shaders(
@@ -182,11 +190,12 @@ impl PipelineBuilder {
         self.color_blend_attachment.alpha_blend_op = vk::BlendOp::ADD;
     }
 
+    //standard over blending, what glTF BLEND materials expect
     pub fn enable_blending_alphablend(&mut self){
         self.color_blend_attachment.color_write_mask = vk::ColorComponentFlags::RGBA;
         self.color_blend_attachment.blend_enable = vk::TRUE;
-        self.color_blend_attachment.src_color_blend_factor = vk::BlendFactor::ONE_MINUS_DST_ALPHA;
-        self.color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::DST_ALPHA;
+        self.color_blend_attachment.src_color_blend_factor = vk::BlendFactor::SRC_ALPHA;
+        self.color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::ONE_MINUS_SRC_ALPHA;
         self.color_blend_attachment.color_blend_op = vk::BlendOp::ADD;
         self.color_blend_attachment.src_alpha_blend_factor = vk::BlendFactor::ONE;
         self.color_blend_attachment.dst_alpha_blend_factor = vk::BlendFactor::ZERO;
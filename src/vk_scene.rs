@@ -0,0 +1,197 @@
+use crate::vk_loader::{GLTFMaterial, MeshAsset};
+use crate::vk_types::bounds::{Bounds, Frustum};
+use crate::vk_types::material::MaterialPass;
+use ash::vk;
+use cgmath::SquareMatrix;
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+//one indexed draw, everything needed to record it without looking at the scene again
+pub struct RenderObject {
+    pub index_count: u32,
+    pub first_index: u32,
+    pub index_buffer: vk::Buffer,
+    //drawn with the engine's default material when None
+    pub material: Option<Rc<GLTFMaterial>>,
+    pub transform: cgmath::Matrix4<f32>,
+    pub vertex_buffer_address: vk::DeviceAddress,
+    pub vertex_count: u32,
+    pub morph_targets_address: vk::DeviceAddress,
+    //empty when nothing is blended in the vertex shader
+    pub morph_weights: Vec<f32>,
+    //bounds of the surface before transform, None when its vertices are deformed on the GPU and
+    //can end up anywhere
+    pub bounds: Option<Bounds>,
+}
+
+impl RenderObject {
+    pub fn is_visible(&self, frustum: &Frustum) -> bool {
+        match &self.bounds {
+            Some(bounds) => frustum.intersects(&bounds.transform(&self.transform)),
+            None => true,
+        }
+    }
+}
+
+//render list filled by Renderable::draw, split by pass so transparent objects go last
+#[derive(Default)]
+pub struct DrawContext {
+    pub opaque_surfaces: Vec<RenderObject>,
+    pub transparent_surfaces: Vec<RenderObject>,
+}
+
+impl DrawContext {
+    pub fn clear(&mut self) {
+        self.opaque_surfaces.clear();
+        self.transparent_surfaces.clear();
+    }
+
+    //drops every object outside the frustum, returns how many were dropped
+    pub fn cull(&mut self, frustum: &Frustum) -> usize {
+        let count = self.opaque_surfaces.len() + self.transparent_surfaces.len();
+        self.opaque_surfaces.retain(|render_object| render_object.is_visible(frustum));
+        self.transparent_surfaces.retain(|render_object| render_object.is_visible(frustum));
+        count - self.opaque_surfaces.len() - self.transparent_surfaces.len()
+    }
+
+    //orders the transparent objects back to front so each blends over the ones behind it
+    pub fn sort_transparent(&mut self, view: &cgmath::Matrix4<f32>) {
+        let view_depth = |render_object: &RenderObject| {
+            let center = render_object.bounds.map_or(cgmath::Vector3::new(0f32, 0f32, 0f32), |bounds| bounds.origin);
+            //the camera looks down -z, the most negative z is the farthest away
+            (view * render_object.transform * center.extend(1f32)).z
+        };
+        self.transparent_surfaces
+            .sort_by(|a, b| view_depth(a).total_cmp(&view_depth(b)));
+    }
+
+    //adds one render object per surface of the mesh, blended with its default morph weights
+    pub fn add_mesh(&mut self, mesh: &MeshAsset, transform: &cgmath::Matrix4<f32>) {
+        self.add_mesh_instance(
+            mesh,
+            transform,
+            mesh.mesh_buffers.vertex_buffer_address,
+            &mesh.morph_weights,
+        );
+    }
+
+    //same as add_mesh, but the vertices can be read from another buffer with the same layout,
+    //like the output of the skinning pass, and the morph weights are the instance's own
+    pub fn add_mesh_instance(
+        &mut self,
+        mesh: &MeshAsset,
+        transform: &cgmath::Matrix4<f32>,
+        vertex_buffer_address: vk::DeviceAddress,
+        morph_weights: &[f32],
+    ) {
+        //weights past the targets of the mesh have nothing to blend
+        let morph_target_count = morph_weights
+            .len()
+            .min(mesh.mesh_buffers.morph_target_count as usize);
+        //skinned and morphed vertices leave the bounds computed at load time
+        let deformed = vertex_buffer_address != mesh.mesh_buffers.vertex_buffer_address || morph_target_count > 0;
+        for surface in mesh.surfaces.iter() {
+            let render_object = RenderObject {
+                index_count: surface.count,
+                first_index: surface.start_index,
+                index_buffer: mesh.mesh_buffers.index_buffer.buffer,
+                material: surface.material.clone(),
+                transform: *transform,
+                vertex_buffer_address,
+                vertex_count: mesh.mesh_buffers.vertex_count,
+                morph_targets_address: mesh.mesh_buffers.morph_targets_address,
+                morph_weights: morph_weights[..morph_target_count].to_vec(),
+                bounds: (!deformed).then_some(surface.bounds),
+            };
+            let pass_type = surface
+                .material
+                .as_ref()
+                .map_or(MaterialPass::MainColor, |material| material.data.pass_type);
+            match pass_type {
+                MaterialPass::Transparent => self.transparent_surfaces.push(render_object),
+                MaterialPass::MainColor => self.opaque_surfaces.push(render_object),
+            }
+        }
+    }
+}
+
+//anything that can add itself to a render list
+pub trait Renderable {
+    fn draw(&self, top_matrix: &cgmath::Matrix4<f32>, ctx: &mut DrawContext);
+}
+
+//scene graph node. Nodes with a mesh draw it, every node draws its children
+pub struct Node {
+    pub name: String,
+    //parent pointer must be a weak reference to avoid circular dependencies
+    pub parent: Weak<RefCell<Node>>,
+    pub children: Vec<Rc<RefCell<Node>>>,
+    pub local_transform: cgmath::Matrix4<f32>,
+    pub world_transform: cgmath::Matrix4<f32>,
+    //meshes can be shared by several nodes
+    pub mesh: Option<Rc<RefCell<MeshAsset>>>,
+    //set on skinned nodes every frame, the mesh is drawn from these deformed vertices instead
+    pub skinned_vertices: Option<vk::DeviceAddress>,
+    //weights of the morph targets of the mesh, empty for nodes without one
+    pub morph_weights: Vec<f32>,
+}
+
+impl Node {
+    pub fn new(name: impl Into<String>, local_transform: cgmath::Matrix4<f32>) -> Self {
+        Node {
+            name: name.into(),
+            parent: Weak::new(),
+            children: Vec::new(),
+            local_transform,
+            world_transform: local_transform,
+            mesh: None,
+            skinned_vertices: None,
+            morph_weights: Vec::new(),
+        }
+    }
+
+    //recomputes the world transform of this node and every node below it
+    pub fn refresh_transform(&mut self, parent_matrix: &cgmath::Matrix4<f32>) {
+        self.world_transform = parent_matrix * self.local_transform;
+        for child in self.children.iter() {
+            child.borrow_mut().refresh_transform(&self.world_transform);
+        }
+    }
+
+    //links child below parent, the child's world transform is updated to match
+    pub fn add_child(parent: &Rc<RefCell<Node>>, child: Rc<RefCell<Node>>) {
+        child.borrow_mut().parent = Rc::downgrade(parent);
+        child
+            .borrow_mut()
+            .refresh_transform(&parent.borrow().world_transform);
+        parent.borrow_mut().children.push(child);
+    }
+}
+
+impl Default for Node {
+    fn default() -> Self {
+        Node::new(String::new(), cgmath::Matrix4::identity())
+    }
+}
+
+impl Renderable for Node {
+    fn draw(&self, top_matrix: &cgmath::Matrix4<f32>, ctx: &mut DrawContext) {
+        if let Some(mesh) = &self.mesh {
+            let node_matrix = top_matrix * self.world_transform;
+            let mesh = mesh.borrow();
+            match self.skinned_vertices {
+                //the skinning pass already blended the morph targets in
+                Some(vertex_buffer_address) => ctx.add_mesh_instance(&mesh, &node_matrix, vertex_buffer_address, &[]),
+                None => ctx.add_mesh_instance(
+                    &mesh,
+                    &node_matrix,
+                    mesh.mesh_buffers.vertex_buffer_address,
+                    &self.morph_weights,
+                ),
+            }
+        }
+        for child in self.children.iter() {
+            child.borrow().draw(top_matrix, ctx);
+        }
+    }
+}
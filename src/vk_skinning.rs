@@ -0,0 +1,158 @@
+use crate::vk_error::EngineResult;
+use crate::vk_pipelines;
+use crate::vk_types::buffers::{buffer_device_address, create_buffer, destroy_buffer, AllocatedBuffer};
+use crate::vk_types::vertex::Vertex;
+use ash::vk::PipelineCache;
+use ash::{vk, Device};
+use std::ffi::CString;
+use std::mem::size_of;
+use std::slice;
+
+//joint influences of one vertex, kept out of Vertex so meshes without a skin do not pay for them
+#[repr(C)]
+#[derive(Copy, Clone, Default)]
+pub struct SkinVertex {
+    pub joints: [u32; 4],
+    //vertices whose weights are all zero are left where they are
+    pub weights: [f32; 4],
+}
+
+//push constants of skinning.comp, every buffer is reached through its device address
+#[repr(C)]
+pub struct SkinningPushConstants {
+    pub source_vertices: vk::DeviceAddress,
+    pub skin_vertices: vk::DeviceAddress,
+    pub joint_matrices: vk::DeviceAddress,
+    pub output_vertices: vk::DeviceAddress,
+    //morph targets are blended in before skinning, like glTF asks for
+    pub morph_targets: vk::DeviceAddress,
+    pub morph_weights: vk::DeviceAddress,
+    pub vertex_count: u32,
+    pub morph_target_count: u32,
+}
+
+//must match local_size_x in skinning.comp
+const SKINNING_GROUP_SIZE: u32 = 64;
+
+//compute pre-pass that deforms skinned meshes into plain vertex buffers, so the mesh
+//pipelines draw them like any other mesh
+pub struct SkinningPipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+impl SkinningPipeline {
+    pub fn build(device: &Device) -> EngineResult<Self> {
+        let push_constant_range = vk::PushConstantRange::builder()
+            .offset(0)
+            .size(size_of::<SkinningPushConstants>() as u32)
+            .stage_flags(vk::ShaderStageFlags::COMPUTE);
+
+        let layout_info = vk::PipelineLayoutCreateInfo::builder()
+            .push_constant_ranges(slice::from_ref(&push_constant_range));
+        let layout = unsafe { device.create_pipeline_layout(&layout_info, None)? };
+
+        let skinning_shader = vk_pipelines::load_shader_module("./shaders/skinning_comp.spv", device)?;
+        let shader_entry = CString::new("main").unwrap();
+        let stage_info = vk::PipelineShaderStageCreateInfo::builder()
+            .stage(vk::ShaderStageFlags::COMPUTE)
+            .module(skinning_shader)
+            .name(&shader_entry)
+            .build();
+        let pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+            .layout(layout)
+            .stage(stage_info)
+            .build();
+        let pipeline = unsafe {
+            device
+                .create_compute_pipelines(
+                    PipelineCache::null(),
+                    slice::from_ref(&pipeline_create_info),
+                    None,
+                )
+                .map_err(|(_, result)| result)?[0]
+        };
+        unsafe { device.destroy_shader_module(skinning_shader, None) };
+
+        Ok(SkinningPipeline { pipeline, layout })
+    }
+
+    pub fn dispatch(&self, device: &Device, cmd: vk::CommandBuffer, push_constants: &SkinningPushConstants) {
+        let push_bytes = unsafe {
+            slice::from_raw_parts(
+                push_constants as *const SkinningPushConstants as *const u8,
+                size_of::<SkinningPushConstants>(),
+            )
+        };
+        unsafe {
+            device.cmd_bind_pipeline(cmd, vk::PipelineBindPoint::COMPUTE, self.pipeline);
+            device.cmd_push_constants(cmd, self.layout, vk::ShaderStageFlags::COMPUTE, 0, push_bytes);
+            device.cmd_dispatch(cmd, push_constants.vertex_count.div_ceil(SKINNING_GROUP_SIZE), 1, 1);
+        }
+    }
+}
+
+//makes the vertices written by the skinning dispatches visible to the vertex shaders
+pub fn skinning_barrier(device: &Device, cmd: vk::CommandBuffer) {
+    let memory_barrier = vk::MemoryBarrier2::builder()
+        .src_stage_mask(vk::PipelineStageFlags2::COMPUTE_SHADER)
+        .src_access_mask(vk::AccessFlags2::SHADER_STORAGE_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::VERTEX_SHADER)
+        .dst_access_mask(vk::AccessFlags2::SHADER_STORAGE_READ)
+        .build();
+    let dependency_info = vk::DependencyInfo::builder()
+        .memory_barriers(slice::from_ref(&memory_barrier))
+        .build();
+    unsafe { device.cmd_pipeline_barrier2(cmd, &dependency_info) };
+}
+
+//one skinned node. Every frame in flight deforms into its own buffer so a dispatch never writes
+//vertices a previous frame is still drawing
+pub struct SkinnedMesh {
+    //indices into the nodes, meshes and skins of the file
+    pub node: usize,
+    pub mesh: usize,
+    pub skin: usize,
+    pub vertex_count: u32,
+    output_buffers: Vec<(AllocatedBuffer, vk::DeviceAddress)>,
+}
+
+impl SkinnedMesh {
+    pub fn new(
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+        (node, mesh, skin): (usize, usize, usize),
+        vertex_count: u32,
+        frames_in_flight: usize,
+    ) -> EngineResult<Self> {
+        let mut output_buffers = Vec::with_capacity(frames_in_flight);
+        for _ in 0..frames_in_flight {
+            let output_buffer = create_buffer(
+                device,
+                allocator,
+                (vertex_count as usize * size_of::<Vertex>()) as vk::DeviceSize,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                gpu_allocator::MemoryLocation::GpuOnly,
+            )?;
+            let output_address = buffer_device_address(device, &output_buffer);
+            output_buffers.push((output_buffer, output_address));
+        }
+        Ok(SkinnedMesh {
+            node,
+            mesh,
+            skin,
+            vertex_count,
+            output_buffers,
+        })
+    }
+
+    pub fn output_address(&self, frame_index: usize) -> vk::DeviceAddress {
+        self.output_buffers[frame_index].1
+    }
+
+    pub fn dealloc(&mut self, device: &Device, allocator: &mut gpu_allocator::vulkan::Allocator) {
+        for (mut output_buffer, _) in self.output_buffers.drain(..) {
+            destroy_buffer(device, allocator, &mut output_buffer);
+        }
+    }
+}
@@ -1,20 +1,142 @@
+pub mod bounds;
 pub mod buffers;
 pub mod gpu_draw_push_constants;
 pub mod gpu_mesh_buffers;
+pub mod gpu_scene_data;
+pub mod material;
+pub mod sampler_cache;
+pub mod texture_cache;
 pub mod vertex;
 
+use crate::vk_error::EngineResult;
+use crate::{vk_images, vk_init};
 use ash::{vk, Device};
+use gpu_allocator::vulkan::{AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
 use std::cell::OnceCell;
 
+//everything AllocatedImage::new needs to know about an image. new gives a single sampled 2D
+//image, set the other fields on top of it for mips, layers, MSAA or cube views
+#[derive(Copy, Clone, Debug)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub usage: vk::ImageUsageFlags,
+    pub extent: vk::Extent3D,
+    pub mip_levels: u32,
+    //six per cube for cube views
+    pub array_layers: u32,
+    pub samples: vk::SampleCountFlags,
+    pub view_type: vk::ImageViewType,
+}
+
+impl ImageDesc {
+    pub fn new(format: vk::Format, usage: vk::ImageUsageFlags, extent: vk::Extent3D) -> Self {
+        ImageDesc {
+            format,
+            usage,
+            extent,
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            view_type: vk::ImageViewType::TYPE_2D,
+        }
+    }
+
+    //depth formats get a depth view, the stencil of depth-stencil formats is left out so the view
+    //can be sampled
+    pub fn aspect_flags(&self) -> vk::ImageAspectFlags {
+        let aspect_flags = vk_images::format_aspect_flags(self.format);
+        if aspect_flags.contains(vk::ImageAspectFlags::DEPTH) {
+            vk::ImageAspectFlags::DEPTH
+        } else {
+            aspect_flags
+        }
+    }
+}
+
 pub struct AllocatedImage {
     pub image: vk::Image,
+    //covers every mip level and layer
     pub image_view: vk::ImageView,
     pub allocation: OnceCell<gpu_allocator::vulkan::Allocation>,
     pub image_extent: vk::Extent3D,
     pub image_format: vk::Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
 }
 
 impl AllocatedImage {
+    //creates a GPU-only image with its own dedicated allocation and a view over it. Free it with
+    //dealloc
+    pub fn new(
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+        name: &str,
+        desc: &ImageDesc,
+    ) -> EngineResult<Self> {
+        let image_create_info = vk_init::image_create_info(
+            desc.format,
+            desc.usage,
+            desc.extent,
+            desc.mip_levels,
+            desc.array_layers,
+            desc.samples,
+            desc.view_type,
+        );
+        let image = unsafe { device.create_image(&image_create_info, None)? };
+        let image_requirements = unsafe { device.get_image_memory_requirements(image) };
+        let allocation = match allocator.allocate(&AllocationCreateDesc {
+            name,
+            requirements: image_requirements,
+            location: MemoryLocation::GpuOnly,
+            linear: false,
+            allocation_scheme: AllocationScheme::DedicatedImage(image),
+        }) {
+            Ok(allocation) => allocation,
+            Err(e) => {
+                unsafe { device.destroy_image(image, None) };
+                return Err(e.into());
+            }
+        };
+        //nothing else owns the image yet, so every failure below has to free it
+        let free_image = |allocator: &mut gpu_allocator::vulkan::Allocator, allocation| {
+            allocator.free(allocation).unwrap();
+            unsafe { device.destroy_image(image, None) };
+        };
+        if let Err(e) = unsafe { device.bind_image_memory(image, allocation.memory(), allocation.offset()) } {
+            free_image(allocator, allocation);
+            return Err(e.into());
+        }
+
+        let image_view_create_info = vk_init::image_view_create_info(
+            desc.format,
+            image,
+            desc.aspect_flags(),
+            desc.view_type,
+            desc.mip_levels,
+            desc.array_layers,
+        );
+        let image_view = match unsafe { device.create_image_view(&image_view_create_info, None) } {
+            Ok(image_view) => image_view,
+            Err(e) => {
+                free_image(allocator, allocation);
+                return Err(e.into());
+            }
+        };
+
+        Ok(AllocatedImage {
+            image,
+            image_view,
+            allocation: allocation.into(),
+            image_extent: desc.extent,
+            image_format: desc.format,
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+        })
+    }
+
+    /// # Safety
+    /// The GPU must be done with the image
     pub unsafe fn dealloc(
         &mut self,
         device: &Device,
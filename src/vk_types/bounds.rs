@@ -0,0 +1,137 @@
+use crate::vk_types::vertex::Vertex;
+use cgmath::{InnerSpace, Matrix, Transform};
+
+//axis-aligned box and bounding sphere sharing one center, in the space of the mesh unless
+//transformed
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub origin: cgmath::Vector3<f32>,
+    //half the size of the box along each axis
+    pub extents: cgmath::Vector3<f32>,
+    pub sphere_radius: f32,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Bounds {
+            origin: (0f32, 0f32, 0f32).into(),
+            extents: (0f32, 0f32, 0f32).into(),
+            sphere_radius: 0f32,
+        }
+    }
+}
+
+impl Bounds {
+    //empty bounds at the origin when there are no points
+    pub fn from_points(points: impl Iterator<Item = cgmath::Vector3<f32>> + Clone) -> Self {
+        let mut min = cgmath::Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = cgmath::Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for point in points.clone() {
+            min = min.zip(point, f32::min);
+            max = max.zip(point, f32::max);
+        }
+        if min.x > max.x {
+            return Bounds::default();
+        }
+        let origin = (min + max) / 2f32;
+        //tighter than the sphere around the box
+        let sphere_radius = points.map(|point| (point - origin).magnitude2()).fold(0f32, f32::max).sqrt();
+        Bounds {
+            origin,
+            extents: (max - min) / 2f32,
+            sphere_radius,
+        }
+    }
+
+    //bounds of the vertices the indices use
+    pub fn from_indexed(indices: &[u32], vertices: &[Vertex]) -> Self {
+        Self::from_points(indices.iter().map(|&index| vertices[index as usize].position))
+    }
+
+    pub fn min(&self) -> cgmath::Vector3<f32> {
+        self.origin - self.extents
+    }
+
+    pub fn max(&self) -> cgmath::Vector3<f32> {
+        self.origin + self.extents
+    }
+
+    //smallest bounds holding both boxes and both spheres
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        let min = self.min().zip(other.min(), f32::min);
+        let max = self.max().zip(other.max(), f32::max);
+        let origin = (min + max) / 2f32;
+        let extents = (max - min) / 2f32;
+        let sphere_radius = ((self.origin - origin).magnitude() + self.sphere_radius)
+            .max((other.origin - origin).magnitude() + other.sphere_radius)
+            //the sphere around the box holds everything too
+            .min(extents.magnitude());
+        Bounds {
+            origin,
+            extents,
+            sphere_radius,
+        }
+    }
+
+    //bounds around the transformed bounds, e.g. by the world matrix of the node drawing the mesh.
+    //The box grows to stay axis-aligned and the sphere scales with the largest axis scale
+    pub fn transform(&self, matrix: &cgmath::Matrix4<f32>) -> Bounds {
+        let origin = matrix.transform_point(cgmath::Point3::new(self.origin.x, self.origin.y, self.origin.z));
+        let mut extents = cgmath::Vector3::new(0f32, 0f32, 0f32);
+        let mut max_scale2 = 0f32;
+        for axis in 0..3 {
+            let column = matrix[axis].truncate();
+            max_scale2 = max_scale2.max(column.magnitude2());
+            let row = matrix.row(axis).truncate();
+            extents[axis] = row.x.abs() * self.extents.x + row.y.abs() * self.extents.y + row.z.abs() * self.extents.z;
+        }
+        Bounds {
+            origin: cgmath::Vector3::new(origin.x, origin.y, origin.z),
+            extents,
+            sphere_radius: self.sphere_radius * max_scale2.sqrt(),
+        }
+    }
+}
+
+//the six planes of a view-projection volume, normals pointing inwards
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    //xyz is the normal and w the distance, a point p is inside when dot(xyz, p) + w >= 0
+    pub planes: [cgmath::Vector4<f32>; 6],
+}
+
+impl Frustum {
+    //works for Vulkan clip space, x and y within -w..w and z within 0..w, whether or not the depth
+    //is reversed
+    pub fn from_matrix(view_projection: &cgmath::Matrix4<f32>) -> Self {
+        let row = |index: usize| view_projection.row(index);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(2),
+            row(3) - row(2),
+        ]
+        .map(|plane| {
+            let length = plane.truncate().magnitude();
+            if length > 0f32 {
+                plane / length
+            } else {
+                plane
+            }
+        });
+        Frustum { planes }
+    }
+
+    //false only when the bounds are entirely outside one of the planes, so objects near the
+    //corners of the frustum can still pass
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let distance = normal.dot(bounds.origin) + plane.w;
+            let box_radius = normal.x.abs() * bounds.extents.x + normal.y.abs() * bounds.extents.y + normal.z.abs() * bounds.extents.z;
+            distance >= -box_radius.min(bounds.sphere_radius)
+        })
+    }
+}
@@ -1,3 +1,4 @@
+use crate::vk_error::EngineResult;
 use ash::{vk, Device};
 use gpu_allocator::vulkan::{AllocationCreateDesc, AllocationScheme};
 use std::cell::OnceCell;
@@ -12,35 +13,39 @@ pub fn create_buffer(
     allocation_size: vk::DeviceSize,
     usage: vk::BufferUsageFlags,
     memory_location: gpu_allocator::MemoryLocation,
-) -> AllocatedBuffer {
+) -> EngineResult<AllocatedBuffer> {
     let buffer_info = vk::BufferCreateInfo::builder()
         .size(allocation_size)
         .usage(usage)
         .build();
 
-    let buffer = unsafe { device.create_buffer(&buffer_info, None).unwrap() };
+    let buffer = unsafe { device.create_buffer(&buffer_info, None)? };
     let requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
 
-    let allocation = allocator
-        .allocate(&AllocationCreateDesc {
-            name: "buffer_allocation",
-            requirements,
-            location: memory_location,
-            linear: true,
-            allocation_scheme: AllocationScheme::DedicatedBuffer(buffer),
-        })
-        .unwrap();
-
-    unsafe {
-        device
-            .bind_buffer_memory(buffer, allocation.memory(), allocation.offset())
-            .unwrap()
+    let allocation = match allocator.allocate(&AllocationCreateDesc {
+        name: "buffer_allocation",
+        requirements,
+        location: memory_location,
+        linear: true,
+        allocation_scheme: AllocationScheme::DedicatedBuffer(buffer),
+    }) {
+        Ok(allocation) => allocation,
+        Err(e) => {
+            unsafe { device.destroy_buffer(buffer, None) };
+            return Err(e.into());
+        }
     };
 
-    AllocatedBuffer {
+    if let Err(e) = unsafe { device.bind_buffer_memory(buffer, allocation.memory(), allocation.offset()) } {
+        allocator.free(allocation).unwrap();
+        unsafe { device.destroy_buffer(buffer, None) };
+        return Err(e.into());
+    }
+
+    Ok(AllocatedBuffer {
         buffer,
         allocation: allocation.into(),
-    }
+    })
 }
 
 pub fn destroy_buffer(
@@ -51,3 +56,11 @@ pub fn destroy_buffer(
     allocator.free(buffer.allocation.take().unwrap()).unwrap();
     unsafe { device.destroy_buffer(buffer.buffer, None) };
 }
+
+//buffer must have been created with SHADER_DEVICE_ADDRESS usage
+pub fn buffer_device_address(device: &Device, buffer: &AllocatedBuffer) -> vk::DeviceAddress {
+    let device_address_info = vk::BufferDeviceAddressInfo::builder()
+        .buffer(buffer.buffer)
+        .build();
+    unsafe { device.get_buffer_device_address(&device_address_info) }
+}
@@ -1,7 +1,14 @@
 use ash::vk;
 
 // push constants for our mesh object draws
+#[repr(C)]
 pub struct GPUDrawPushConstants {
     pub world_matrix: cgmath::Matrix4<f32>,
     pub vertex_buffer: vk::DeviceAddress,
+    //0 when the mesh has no morph targets
+    pub morph_targets: vk::DeviceAddress,
+    pub morph_weights: vk::DeviceAddress,
+    //number of weights, targets past it are not blended in
+    pub morph_target_count: u32,
+    pub vertex_count: u32,
 }
@@ -1,17 +1,42 @@
+use crate::vk_error::EngineResult;
 use crate::vk_types::buffers::{destroy_buffer, AllocatedBuffer};
 use crate::{immediate_submit, vk_types};
 use ash::vk::DeviceSize;
 use ash::{vk, Device};
-use std::mem::size_of;
+use std::mem::{size_of, size_of_val};
 use std::slice;
-use vk_types::buffers::create_buffer;
+use vk_types::buffers::{buffer_device_address, create_buffer};
 use crate::vk_types::vertex::Vertex;
 
+//position, normal and tangent offsets of one vertex in one morph target, w is padding
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MorphTargetDelta {
+    pub position: cgmath::Vector4<f32>,
+    pub normal: cgmath::Vector4<f32>,
+    pub tangent: cgmath::Vector4<f32>,
+}
+
+impl Default for MorphTargetDelta {
+    fn default() -> Self {
+        MorphTargetDelta {
+            position: (0f32, 0f32, 0f32, 0f32).into(),
+            normal: (0f32, 0f32, 0f32, 0f32).into(),
+            tangent: (0f32, 0f32, 0f32, 0f32).into(),
+        }
+    }
+}
+
 // holds the resources needed for a mesh
 pub struct GPUMeshBuffers {
     pub index_buffer: AllocatedBuffer,
     pub vertex_buffer: AllocatedBuffer,
     pub vertex_buffer_address: vk::DeviceAddress,
+    pub vertex_count: u32,
+    //every target has a delta for each vertex, targets are stored one after the other
+    pub morph_targets: Option<AllocatedBuffer>,
+    pub morph_targets_address: vk::DeviceAddress,
+    pub morph_target_count: u32,
 }
 
 pub fn upload_mesh(
@@ -22,12 +47,12 @@ pub fn upload_mesh(
     immediate_command_buffer: vk::CommandBuffer,
     immediate_fence: vk::Fence,
     immediate_queue: vk::Queue,
-) -> GPUMeshBuffers {
+) -> EngineResult<GPUMeshBuffers> {
     let vertex_buffer_size: usize = vertices.len() * size_of::<Vertex>();
     let index_buffer_size: usize = indices.len() * size_of::<u32>();
 
     //create vertex buffer
-    let vertex_buffer = create_buffer(
+    let mut vertex_buffer = create_buffer(
         device,
         allocator,
         vertex_buffer_size as DeviceSize,
@@ -35,31 +60,41 @@ pub fn upload_mesh(
             | vk::BufferUsageFlags::TRANSFER_DST
             | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
         gpu_allocator::MemoryLocation::GpuOnly,
-    );
+    )?;
 
     //find the address of the vertex buffer
-    let device_address_info = vk::BufferDeviceAddressInfo::builder()
-        .buffer(vertex_buffer.buffer)
-        .build();
-    let vertex_buffer_address = unsafe { device.get_buffer_device_address(&device_address_info) };
+    let vertex_buffer_address = buffer_device_address(device, &vertex_buffer);
 
-    //create index buffer
-    let index_buffer = create_buffer(
+    //create index buffer, every failure from here on has to free the buffers created before it
+    let mut index_buffer = match create_buffer(
         device,
         allocator,
         index_buffer_size as DeviceSize,
         vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
         gpu_allocator::MemoryLocation::GpuOnly,
-    );
+    ) {
+        Ok(index_buffer) => index_buffer,
+        Err(e) => {
+            destroy_buffer(device, allocator, &mut vertex_buffer);
+            return Err(e);
+        }
+    };
 
     //upload buffer
-    let mut staging = create_buffer(
+    let mut staging = match create_buffer(
         device,
         allocator,
         (vertex_buffer_size + index_buffer_size) as DeviceSize,
         vk::BufferUsageFlags::TRANSFER_SRC,
         gpu_allocator::MemoryLocation::CpuToGpu,
-    );
+    ) {
+        Ok(staging) => staging,
+        Err(e) => {
+            destroy_buffer(device, allocator, &mut index_buffer);
+            destroy_buffer(device, allocator, &mut vertex_buffer);
+            return Err(e);
+        }
+    };
     presser::copy_from_slice_to_offset(vertices, staging.allocation.get_mut().unwrap(), 0).unwrap();
     presser::copy_from_slice_to_offset(
         indices,
@@ -106,16 +141,82 @@ pub fn upload_mesh(
         immediate_command_buffer
     );
     destroy_buffer(device, allocator, &mut staging);
-    GPUMeshBuffers {
+    Ok(GPUMeshBuffers {
         index_buffer,
         vertex_buffer,
         vertex_buffer_address,
-    }
+        vertex_count: vertices.len() as u32,
+        morph_targets: None,
+        morph_targets_address: 0,
+        morph_target_count: 0,
+    })
 }
 
 impl GPUMeshBuffers {
+    //deltas holds vertex_count deltas per target
+    #[allow(clippy::too_many_arguments)]
+    pub fn upload_morph_targets(
+        &mut self,
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+        deltas: &[MorphTargetDelta],
+        target_count: u32,
+        immediate_command_buffer: vk::CommandBuffer,
+        immediate_fence: vk::Fence,
+        immediate_queue: vk::Queue,
+    ) -> EngineResult<()> {
+        let buffer_size = size_of_val(deltas) as DeviceSize;
+        let mut morph_targets = create_buffer(
+            device,
+            allocator,
+            buffer_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_DST
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            gpu_allocator::MemoryLocation::GpuOnly,
+        )?;
+        let mut staging = match create_buffer(
+            device,
+            allocator,
+            buffer_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+        ) {
+            Ok(staging) => staging,
+            Err(e) => {
+                destroy_buffer(device, allocator, &mut morph_targets);
+                return Err(e);
+            }
+        };
+        presser::copy_from_slice_to_offset(deltas, staging.allocation.get_mut().unwrap(), 0).unwrap();
+
+        let upload_helper = |cmd: vk::CommandBuffer| {
+            let copy = vk::BufferCopy::builder().size(buffer_size).build();
+            unsafe {
+                device.cmd_copy_buffer(cmd, staging.buffer, morph_targets.buffer, slice::from_ref(&copy))
+            };
+        };
+        immediate_submit!(
+            device,
+            immediate_command_buffer,
+            immediate_fence,
+            immediate_queue,
+            upload_helper,
+            immediate_command_buffer
+        );
+        destroy_buffer(device, allocator, &mut staging);
+
+        self.morph_targets_address = buffer_device_address(device, &morph_targets);
+        self.morph_targets = Some(morph_targets);
+        self.morph_target_count = target_count;
+        Ok(())
+    }
+
     pub fn dealloc(&mut self, device: &Device, allocator: &mut gpu_allocator::vulkan::Allocator) {
         destroy_buffer(device, allocator, &mut self.vertex_buffer);
         destroy_buffer(device, allocator, &mut self.index_buffer);
+        if let Some(mut morph_targets) = self.morph_targets.take() {
+            destroy_buffer(device, allocator, &mut morph_targets);
+        }
     }
 }
\ No newline at end of file
@@ -0,0 +1,26 @@
+// scene-wide uniforms, bound as set 0 by every material pipeline
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct GPUSceneData {
+    pub view: cgmath::Matrix4<f32>,
+    pub proj: cgmath::Matrix4<f32>,
+    pub viewproj: cgmath::Matrix4<f32>,
+    pub ambient_color: cgmath::Vector4<f32>,
+    //w for sun power
+    pub sunlight_direction: cgmath::Vector4<f32>,
+    pub sunlight_color: cgmath::Vector4<f32>,
+}
+
+impl Default for GPUSceneData {
+    fn default() -> Self {
+        use cgmath::SquareMatrix;
+        GPUSceneData {
+            view: cgmath::Matrix4::identity(),
+            proj: cgmath::Matrix4::identity(),
+            viewproj: cgmath::Matrix4::identity(),
+            ambient_color: (0.1f32, 0.1f32, 0.1f32, 1f32).into(),
+            sunlight_direction: (0f32, 1f32, 0.5f32, 1f32).into(),
+            sunlight_color: (1f32, 1f32, 1f32, 1f32).into(),
+        }
+    }
+}
@@ -0,0 +1,21 @@
+use ash::vk;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialPass {
+    MainColor,
+    Transparent,
+}
+
+#[derive(Copy, Clone)]
+pub struct MaterialPipeline {
+    pub pipeline: vk::Pipeline,
+    pub layout: vk::PipelineLayout,
+}
+
+//what a surface needs to be drawn: the pipeline to bind and its material descriptor set
+#[derive(Copy, Clone)]
+pub struct MaterialInstance {
+    pub pipeline: MaterialPipeline,
+    pub material_set: vk::DescriptorSet,
+    pub pass_type: MaterialPass,
+}
@@ -0,0 +1,163 @@
+use crate::vk_error::EngineResult;
+use ash::{vk, Device};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+//everything a sampler is created from. Two equal descriptions share one sampler
+#[derive(Copy, Clone, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    //only used by the CLAMP_TO_BORDER address mode
+    pub border_color: vk::BorderColor,
+    //None turns anisotropic filtering off, it is clamped to what the device supports
+    pub max_anisotropy: Option<f32>,
+    //Some makes a comparison sampler, for shadow maps
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+}
+
+impl SamplerDesc {
+    //every mip level, the closest texel of the closest level
+    pub fn nearest() -> Self {
+        SamplerDesc {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0f32,
+            max_lod: vk::LOD_CLAMP_NONE,
+        }
+    }
+
+    //trilinear filtering over every mip level
+    pub fn linear() -> Self {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            ..Self::nearest()
+        }
+    }
+
+    //hardware depth comparison with linear PCF. Everything outside the shadow map is lit
+    pub fn shadow() -> Self {
+        SamplerDesc {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            compare_op: Some(vk::CompareOp::LESS_OR_EQUAL),
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            max_lod: 0f32,
+            ..Self::linear()
+        }
+    }
+
+    pub fn with_address_mode(self, address_mode: vk::SamplerAddressMode) -> Self {
+        SamplerDesc {
+            address_mode_u: address_mode,
+            address_mode_v: address_mode,
+            address_mode_w: address_mode,
+            ..self
+        }
+    }
+
+    //the floats are compared by their bits, which is all hashing needs
+    fn key(&self) -> impl Eq + Hash {
+        (
+            (self.mag_filter, self.min_filter, self.mipmap_mode),
+            (self.address_mode_u, self.address_mode_v, self.address_mode_w),
+            self.border_color,
+            self.max_anisotropy.map(f32::to_bits),
+            self.compare_op,
+            (self.min_lod.to_bits(), self.max_lod.to_bits()),
+        )
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+//samplers shared by everything that asks for the same description. They live as long as the
+//cache, there are only ever a handful of them
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerDesc, vk::Sampler>,
+    //0 when the device does not support anisotropic filtering
+    max_anisotropy: f32,
+}
+
+impl SamplerCache {
+    pub fn new(max_anisotropy: f32) -> Self {
+        SamplerCache {
+            samplers: HashMap::new(),
+            max_anisotropy,
+        }
+    }
+
+    pub fn get(&mut self, device: &Device, desc: &SamplerDesc) -> EngineResult<vk::Sampler> {
+        if let Some(&sampler) = self.samplers.get(desc) {
+            return Ok(sampler);
+        }
+        let anisotropy = desc
+            .max_anisotropy
+            .map(|max_anisotropy| max_anisotropy.min(self.max_anisotropy))
+            .filter(|&max_anisotropy| max_anisotropy >= 1f32);
+        let sampler_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(desc.mag_filter)
+            .min_filter(desc.min_filter)
+            .mipmap_mode(desc.mipmap_mode)
+            .address_mode_u(desc.address_mode_u)
+            .address_mode_v(desc.address_mode_v)
+            .address_mode_w(desc.address_mode_w)
+            .border_color(desc.border_color)
+            .anisotropy_enable(anisotropy.is_some())
+            .max_anisotropy(anisotropy.unwrap_or(1f32))
+            .compare_enable(desc.compare_op.is_some())
+            .compare_op(desc.compare_op.unwrap_or(vk::CompareOp::ALWAYS))
+            .min_lod(desc.min_lod)
+            .max_lod(desc.max_lod)
+            .build();
+        let sampler = unsafe { device.create_sampler(&sampler_info, None)? };
+        self.samplers.insert(*desc, sampler);
+        Ok(sampler)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    /// # Safety
+    /// The GPU must be done with every cached sampler
+    pub unsafe fn destroy(&mut self, device: &Device) {
+        for (_, sampler) in self.samplers.drain() {
+            device.destroy_sampler(sampler, None);
+        }
+    }
+}
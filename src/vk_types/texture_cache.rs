@@ -0,0 +1,60 @@
+use crate::vk_types::AllocatedImage;
+use ash::Device;
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_128;
+
+//identifies encoded image bytes. The 128-bit digest together with the length makes two different
+//images sharing a key practically impossible, and unlike DefaultHasher it is stable across builds
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureKey {
+    len: usize,
+    digest: u128,
+}
+
+//images decoded from encoded files, keyed by the encoded bytes so an image shared by several
+//materials or files is only uploaded once
+#[derive(Default)]
+pub struct TextureCache {
+    images: Vec<AllocatedImage>,
+    indices: HashMap<TextureKey, usize>,
+}
+
+impl TextureCache {
+    pub fn key(encoded: &[u8]) -> TextureKey {
+        TextureKey {
+            len: encoded.len(),
+            digest: xxh3_128(encoded),
+        }
+    }
+
+    pub fn get(&self, key: TextureKey) -> Option<&AllocatedImage> {
+        self.indices.get(&key).map(|&index| &self.images[index])
+    }
+
+    pub fn insert(&mut self, key: TextureKey, image: AllocatedImage) -> &AllocatedImage {
+        self.indices.insert(key, self.images.len());
+        self.images.push(image);
+        self.images.last().unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    /// # Safety
+    /// The GPU must be done with every cached image
+    pub unsafe fn dealloc(
+        &mut self,
+        device: &Device,
+        allocator: &mut gpu_allocator::vulkan::Allocator,
+    ) {
+        self.indices.clear();
+        for mut image in self.images.drain(..) {
+            image.dealloc(device, allocator);
+        }
+    }
+}
@@ -6,6 +6,8 @@ pub struct Vertex {
     pub normal: cgmath::Vector3<f32>,
     pub uv_y: f32,
     pub color: cgmath::Vector4<f32>,
+    //w is the handedness of the bitangent, bitangent = cross(normal, tangent.xyz) * w
+    pub tangent: cgmath::Vector4<f32>,
 }
 
 impl Default for Vertex {
@@ -15,7 +17,8 @@ impl Default for Vertex {
             uv_x: 0f32,
             normal: (0f32, 0f32, 0f32).into(),
             uv_y: 0f32,
-            color: (0f32, 0f32, 0f32, 1f32).into()
+            color: (0f32, 0f32, 0f32, 1f32).into(),
+            tangent: (1f32, 0f32, 0f32, 1f32).into()
         }
     }
 }
\ No newline at end of file
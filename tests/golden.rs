@@ -0,0 +1,169 @@
+//Renders known scenes on a headless engine and compares them against the reference images in
+//tests/golden. Needs a Vulkan device and compiled shaders, so the rendering tests only run with
+//GOLDEN_REFERENCES=1. Point the Vulkan loader at lavapipe so the renders stay comparable to the
+//references, see tests/golden/README.md.
+//A missing reference is skipped, UPDATE_GOLDEN=1 (re)writes them instead of comparing.
+use ash::vk;
+use image::RgbaImage;
+use std::path::PathBuf;
+use vk_guide_rs::vk_engine::capture::CaptureSource;
+use vk_guide_rs::vk_engine::VulkanEngine;
+
+const GOLDEN_DIR: &str = "./tests/golden";
+const DIFF_DIR: &str = "./target/golden_diffs";
+const GOLDEN_EXTENT: vk::Extent2D = vk::Extent2D {
+    width: 320,
+    height: 180,
+};
+//largest per-channel difference for two pixels to still count as equal
+const PIXEL_TOLERANCE: u8 = 2;
+//rasterization rules differ slightly between drivers along triangle edges
+const MAX_MISMATCHED_RATIO: f32 = 0.001;
+
+struct ImageComparison {
+    mismatched_pixels: usize,
+    diff: RgbaImage,
+}
+
+//mismatched pixels are red in the diff image, matching ones are a dimmed copy of the reference
+fn compare_images(expected: &RgbaImage, actual: &RgbaImage, tolerance: u8) -> ImageComparison {
+    assert_eq!(expected.dimensions(), actual.dimensions());
+    let mut mismatched_pixels = 0;
+    let mut diff = RgbaImage::new(expected.width(), expected.height());
+    for ((expected_pixel, actual_pixel), diff_pixel) in expected
+        .pixels()
+        .zip(actual.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let matches = expected_pixel
+            .0
+            .iter()
+            .zip(actual_pixel.0.iter())
+            .all(|(&e, &a)| e.abs_diff(a) <= tolerance);
+        *diff_pixel = if matches {
+            let [r, g, b, _] = expected_pixel.0;
+            image::Rgba([r / 4, g / 4, b / 4, 255])
+        } else {
+            mismatched_pixels += 1;
+            image::Rgba([255, 0, 0, 255])
+        };
+    }
+    ImageComparison {
+        mismatched_pixels,
+        diff,
+    }
+}
+
+//plain cargo test runs on machines without a Vulkan device, it leaves the rendering tests out
+fn golden_enabled() -> bool {
+    if std::env::var_os("GOLDEN_REFERENCES").is_some() || std::env::var_os("UPDATE_GOLDEN").is_some() {
+        return true;
+    }
+    eprintln!("skipping, set GOLDEN_REFERENCES=1 to compare renders against {}", GOLDEN_DIR);
+    false
+}
+
+fn render(engine: &mut VulkanEngine) -> RgbaImage {
+    engine.draw();
+    let captured = engine.capture(CaptureSource::FinalImage).unwrap();
+    RgbaImage::from_raw(captured.width, captured.height, captured.to_rgba8().unwrap()).unwrap()
+}
+
+fn check_golden(name: &str, actual: &RgbaImage) -> Result<(), String> {
+    let golden_path = PathBuf::from(GOLDEN_DIR).join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(GOLDEN_DIR).unwrap();
+        actual.save(&golden_path).unwrap();
+        return Ok(());
+    }
+    if !golden_path.exists() {
+        eprintln!(
+            "{}: no reference at {}, skipping. Generate it with UPDATE_GOLDEN=1",
+            name,
+            golden_path.display()
+        );
+        return Ok(());
+    }
+    let expected = match image::open(&golden_path) {
+        Ok(expected) => expected.to_rgba8(),
+        Err(e) => {
+            return Err(format!(
+                "{}: reference {} can not be read: {}",
+                name,
+                golden_path.display(),
+                e
+            ))
+        }
+    };
+    if expected.dimensions() != actual.dimensions() {
+        return Err(format!(
+            "{}: reference is {:?} but the render is {:?}",
+            name,
+            expected.dimensions(),
+            actual.dimensions()
+        ));
+    }
+
+    let comparison = compare_images(&expected, actual, PIXEL_TOLERANCE);
+    let allowed = (expected.len() as f32 / 4f32 * MAX_MISMATCHED_RATIO) as usize;
+    if comparison.mismatched_pixels <= allowed {
+        return Ok(());
+    }
+    std::fs::create_dir_all(DIFF_DIR).unwrap();
+    let actual_path = PathBuf::from(DIFF_DIR).join(format!("{}_actual.png", name));
+    let diff_path = PathBuf::from(DIFF_DIR).join(format!("{}_diff.png", name));
+    actual.save(&actual_path).unwrap();
+    comparison.diff.save(&diff_path).unwrap();
+    Err(format!(
+        "{}: {} pixels differ (at most {} allowed), see {} and {}",
+        name,
+        comparison.mismatched_pixels,
+        allowed,
+        actual_path.display(),
+        diff_path.display()
+    ))
+}
+
+#[test]
+fn compare_images_tolerance() {
+    let expected = RgbaImage::from_pixel(2, 1, image::Rgba([100, 100, 100, 255]));
+    let mut actual = expected.clone();
+    actual.put_pixel(0, 0, image::Rgba([102, 99, 100, 255]));
+    assert_eq!(compare_images(&expected, &actual, 2).mismatched_pixels, 0);
+    actual.put_pixel(1, 0, image::Rgba([100, 100, 110, 255]));
+    let comparison = compare_images(&expected, &actual, 2);
+    assert_eq!(comparison.mismatched_pixels, 1);
+    assert_eq!(comparison.diff.get_pixel(1, 0), &image::Rgba([255, 0, 0, 255]));
+}
+
+#[test]
+fn golden_background_effects() {
+    if !golden_enabled() {
+        return;
+    }
+    let mut engine = VulkanEngine::init_headless(GOLDEN_EXTENT).unwrap();
+    engine.draw_meshes = false;
+    let mut failures = Vec::new();
+    for effect_index in 0..engine.background_effects.len() {
+        engine.current_background_effect = effect_index;
+        let name = format!("background_{}", engine.background_effects[effect_index].name);
+        let actual = render(&mut engine);
+        if let Err(failure) = check_golden(&name, &actual) {
+            failures.push(failure);
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn golden_monkey_mesh() {
+    if !golden_enabled() {
+        return;
+    }
+    let mut engine = VulkanEngine::init_headless(GOLDEN_EXTENT).unwrap();
+    engine.current_background_effect = 0;
+    let actual = render(&mut engine);
+    if let Err(failure) = check_golden("monkey_mesh", &actual) {
+        panic!("{}", failure);
+    }
+}